@@ -0,0 +1,275 @@
+mod merkle;
+mod state;
+
+use std::{fs, path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Context};
+use borsh::BorshSerialize;
+use clap::{Parser, Subcommand};
+use serde::Deserialize;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signer},
+    system_instruction, system_program,
+    transaction::Transaction,
+};
+
+use crate::merkle::Distribution;
+use crate::state::{AirdropCampaign, CAMPAIGN_AUTHORITY_SEED, IX_CREATE_CAMPAIGN, IX_FUND_CAMPAIGN};
+
+const AIRDROP_PROGRAM_ID: &str = "Airdrop1111111111111111111111111111111111111";
+const FEE_WALLET: &str = "6zkf4DviZZkpWVEh53MrcQV6vGXGpESnNXgAvU6KpBUH";
+
+#[derive(Parser)]
+#[command(name = "airdrop-cli", about = "Build merkle airdrops and set up Solmint campaigns")]
+struct Cli {
+    #[arg(long, global = true, default_value = "https://api.devnet.solana.com")]
+    url: String,
+    #[arg(long, global = true)]
+    keypair: Option<PathBuf>,
+    #[arg(long, global = true, default_value = AIRDROP_PROGRAM_ID)]
+    program_id: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Read a `wallet,amount` CSV and write the merkle root and per-wallet proofs as JSON
+    Generate {
+        #[arg(long)]
+        csv: PathBuf,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Create a merkle campaign from a generated distribution and escrow its total
+    CreateCampaign {
+        #[arg(long)]
+        distribution: PathBuf,
+        #[arg(long)]
+        mint: String,
+        #[arg(long)]
+        owner_token_account: String,
+        #[arg(long, default_value_t = 0)]
+        start_time: i64,
+        #[arg(long, default_value_t = 0)]
+        end_time: i64,
+    },
+    /// Top up an existing campaign's vault
+    Fund {
+        #[arg(long)]
+        campaign: String,
+        #[arg(long)]
+        owner_token_account: String,
+        #[arg(long)]
+        amount: u64,
+    },
+}
+
+#[derive(Deserialize)]
+struct CsvRow {
+    wallet: String,
+    amount: u64,
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let program_id = Pubkey::from_str(&cli.program_id)?;
+
+    match &cli.command {
+        Command::Generate { csv, out } => generate(csv, out),
+        Command::CreateCampaign {
+            distribution,
+            mint,
+            owner_token_account,
+            start_time,
+            end_time,
+        } => {
+            let distribution: Distribution =
+                serde_json::from_str(&fs::read_to_string(distribution)?)?;
+            create_campaign(
+                &cli,
+                &program_id,
+                &distribution,
+                &Pubkey::from_str(mint)?,
+                &Pubkey::from_str(owner_token_account)?,
+                *start_time,
+                *end_time,
+            )
+        }
+        Command::Fund {
+            campaign,
+            owner_token_account,
+            amount,
+        } => fund(
+            &cli,
+            &program_id,
+            &Pubkey::from_str(campaign)?,
+            &Pubkey::from_str(owner_token_account)?,
+            *amount,
+        ),
+    }
+}
+
+fn generate(csv_path: &PathBuf, out: &PathBuf) -> anyhow::Result<()> {
+    let mut reader = csv::Reader::from_path(csv_path)
+        .with_context(|| format!("opening {}", csv_path.display()))?;
+    let mut recipients = Vec::new();
+    for (line, row) in reader.deserialize::<CsvRow>().enumerate() {
+        let row = row.with_context(|| format!("row {}", line + 1))?;
+        let wallet = Pubkey::from_str(row.wallet.trim())
+            .with_context(|| format!("row {}: invalid wallet {}", line + 1, row.wallet))?;
+        if row.amount == 0 {
+            bail!("row {}: amount must be non-zero", line + 1);
+        }
+        recipients.push((wallet, row.amount));
+    }
+    if recipients.is_empty() {
+        bail!("{} has no recipients", csv_path.display());
+    }
+
+    let distribution = merkle::build(&recipients);
+    fs::write(out, serde_json::to_string_pretty(&distribution)?)?;
+
+    println!("Merkle root: {}", distribution.merkle_root);
+    println!(
+        "{} recipients, {} tokens total",
+        distribution.recipients, distribution.total_amount
+    );
+    Ok(())
+}
+
+fn create_campaign(
+    cli: &Cli,
+    program_id: &Pubkey,
+    distribution: &Distribution,
+    mint: &Pubkey,
+    owner_token_account: &Pubkey,
+    start_time: i64,
+    end_time: i64,
+) -> anyhow::Result<()> {
+    let client = RpcClient::new_with_commitment(cli.url.clone(), CommitmentConfig::confirmed());
+    let owner = load_keypair(cli)?;
+    let campaign = Keypair::new();
+    let vault = Keypair::new();
+    let (campaign_authority, _) = Pubkey::find_program_address(
+        &[CAMPAIGN_AUTHORITY_SEED, campaign.pubkey().as_ref()],
+        program_id,
+    );
+
+    let campaign_data = AirdropCampaign {
+        total_amount: distribution.total_amount,
+        start_time,
+        end_time,
+        max_recipients: distribution.recipients,
+        merkle_root: distribution.root_bytes()?,
+        ..Default::default()
+    };
+    let mut data = vec![IX_CREATE_CAMPAIGN];
+    data.extend(campaign_data.try_to_vec()?);
+
+    let campaign_len = AirdropCampaign::len();
+    let vault_len = spl_token::state::Account::LEN;
+    let instructions = vec![
+        system_instruction::create_account(
+            &owner.pubkey(),
+            &campaign.pubkey(),
+            client.get_minimum_balance_for_rent_exemption(campaign_len)?,
+            campaign_len as u64,
+            program_id,
+        ),
+        system_instruction::create_account(
+            &owner.pubkey(),
+            &vault.pubkey(),
+            client.get_minimum_balance_for_rent_exemption(vault_len)?,
+            vault_len as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_account3(
+            &spl_token::id(),
+            &vault.pubkey(),
+            mint,
+            &campaign_authority,
+        )?,
+        Instruction::new_with_bytes(
+            *program_id,
+            &data,
+            vec![
+                AccountMeta::new(owner.pubkey(), true),
+                AccountMeta::new(campaign.pubkey(), false),
+                AccountMeta::new_readonly(*mint, false),
+                AccountMeta::new(Pubkey::from_str(FEE_WALLET)?, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(*owner_token_account, false),
+                AccountMeta::new(vault.pubkey(), false),
+            ],
+        ),
+    ];
+
+    let signature = send(&client, &instructions, &[&owner, &campaign, &vault])?;
+    println!("Campaign: {}", campaign.pubkey());
+    println!("Vault: {}", vault.pubkey());
+    println!("Signature: {}", signature);
+    Ok(())
+}
+
+fn fund(
+    cli: &Cli,
+    program_id: &Pubkey,
+    campaign: &Pubkey,
+    owner_token_account: &Pubkey,
+    amount: u64,
+) -> anyhow::Result<()> {
+    let client = RpcClient::new_with_commitment(cli.url.clone(), CommitmentConfig::confirmed());
+    let owner = load_keypair(cli)?;
+
+    let campaign_data: AirdropCampaign =
+        borsh::BorshDeserialize::try_from_slice(&client.get_account_data(campaign)?)?;
+    let mut data = vec![IX_FUND_CAMPAIGN];
+    data.extend(amount.try_to_vec()?);
+
+    let instruction = Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![
+            AccountMeta::new(owner.pubkey(), true),
+            AccountMeta::new(*campaign, false),
+            AccountMeta::new(*owner_token_account, false),
+            AccountMeta::new(campaign_data.vault, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(campaign_data.mint, false),
+        ],
+    );
+
+    let signature = send(&client, &[instruction], &[&owner])?;
+    println!("Funded {} with {} tokens: {}", campaign, amount, signature);
+    Ok(())
+}
+
+fn load_keypair(cli: &Cli) -> anyhow::Result<Keypair> {
+    let path = match &cli.keypair {
+        Some(path) => path.clone(),
+        None => PathBuf::from(std::env::var("HOME")?).join(".config/solana/id.json"),
+    };
+    read_keypair_file(&path).map_err(|e| anyhow::anyhow!("reading {}: {}", path.display(), e))
+}
+
+fn send(
+    client: &RpcClient,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> anyhow::Result<solana_sdk::signature::Signature> {
+    let blockhash = client.get_latest_blockhash()?;
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&signers[0].pubkey()),
+        signers,
+        blockhash,
+    );
+    Ok(client.send_and_confirm_transaction(&transaction)?)
+}
@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use solana_sdk::{keccak, pubkey::Pubkey};
+
+// Same leaf layout the program hashes in ClaimAirdrop: keccak(index LE || wallet || amount LE)
+pub fn leaf(index: u64, wallet: &Pubkey, amount: u64) -> [u8; 32] {
+    keccak::hashv(&[&index.to_le_bytes(), wallet.as_ref(), &amount.to_le_bytes()]).to_bytes()
+}
+
+// Sorted-pair keccak, matching the program's verify_merkle_proof
+fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    if a <= b {
+        keccak::hashv(&[a, b]).to_bytes()
+    } else {
+        keccak::hashv(&[b, a]).to_bytes()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Claim {
+    pub index: u64,
+    pub wallet: String,
+    pub amount: u64,
+    pub proof: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Distribution {
+    pub merkle_root: String,
+    pub total_amount: u64,
+    pub recipients: u64,
+    pub claims: Vec<Claim>,
+}
+
+impl Distribution {
+    pub fn root_bytes(&self) -> anyhow::Result<[u8; 32]> {
+        let bytes = hex::decode(&self.merkle_root)?;
+        bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("merkle root must be 32 bytes"))
+    }
+}
+
+// Odd nodes are carried up unchanged, so a proof simply skips that level
+pub fn build(recipients: &[(Pubkey, u64)]) -> Distribution {
+    let leaves: Vec<[u8; 32]> = recipients
+        .iter()
+        .enumerate()
+        .map(|(i, (wallet, amount))| leaf(i as u64, wallet, *amount))
+        .collect();
+
+    let mut levels = vec![leaves];
+    while levels.last().unwrap().len() > 1 {
+        let next = levels
+            .last()
+            .unwrap()
+            .chunks(2)
+            .map(|pair| match pair {
+                [a, b] => hash_pair(a, b),
+                [a] => *a,
+                _ => unreachable!(),
+            })
+            .collect();
+        levels.push(next);
+    }
+    let root = levels.last().and_then(|top| top.first()).copied().unwrap_or([0u8; 32]);
+
+    let claims = recipients
+        .iter()
+        .enumerate()
+        .map(|(i, (wallet, amount))| {
+            let mut proof = Vec::new();
+            let mut position = i;
+            for level in &levels[..levels.len() - 1] {
+                if let Some(sibling) = level.get(position ^ 1) {
+                    proof.push(hex::encode(sibling));
+                }
+                position /= 2;
+            }
+            Claim {
+                index: i as u64,
+                wallet: wallet.to_string(),
+                amount: *amount,
+                proof,
+            }
+        })
+        .collect();
+
+    Distribution {
+        merkle_root: hex::encode(root),
+        total_amount: recipients.iter().map(|(_, amount)| amount).sum(),
+        recipients: recipients.len() as u64,
+        claims,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Copy of the airdrop program's verify_merkle_proof
+    fn verify_merkle_proof(root: &[u8; 32], leaf: [u8; 32], proof: &[[u8; 32]]) -> bool {
+        let mut node = leaf;
+        for sibling in proof {
+            node = if node <= *sibling {
+                keccak::hashv(&[&node, sibling]).to_bytes()
+            } else {
+                keccak::hashv(&[sibling, &node]).to_bytes()
+            };
+        }
+
+        node == *root
+    }
+
+    #[test]
+    fn every_proof_verifies_against_the_root() {
+        for size in [1u64, 2, 3, 5] {
+            let recipients: Vec<(Pubkey, u64)> =
+                (0..size).map(|i| (Pubkey::new_unique(), 100 * (i + 1))).collect();
+            let distribution = build(&recipients);
+            let root = distribution.root_bytes().unwrap();
+
+            assert_eq!(distribution.recipients, size);
+            assert_eq!(distribution.total_amount, 100 * size * (size + 1) / 2);
+            for (claim, (wallet, amount)) in distribution.claims.iter().zip(&recipients) {
+                let proof: Vec<[u8; 32]> = claim
+                    .proof
+                    .iter()
+                    .map(|node| hex::decode(node).unwrap().try_into().unwrap())
+                    .collect();
+                assert!(
+                    verify_merkle_proof(&root, leaf(claim.index, wallet, *amount), &proof),
+                    "claim {} of {} does not verify",
+                    claim.index,
+                    size
+                );
+                // The same proof must not verify a different amount
+                let forged = leaf(claim.index, wallet, amount + 1);
+                assert!(!verify_merkle_proof(&root, forged, &proof));
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::pubkey::Pubkey;
+
+pub const CAMPAIGN_AUTHORITY_SEED: &[u8] = b"campaign_authority";
+
+// Discriminants of the program's AirdropInstruction
+pub const IX_CREATE_CAMPAIGN: u8 = 0;
+pub const IX_FUND_CAMPAIGN: u8 = 13;
+
+// Mirror of the program's AirdropCampaign; CreateCampaign takes it verbatim as instruction data
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default)]
+pub struct AirdropCampaign {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub total_amount: u64,
+    pub amount_per_recipient: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub is_active: bool,
+    pub claimed_count: u64,
+    pub max_recipients: u64,
+    pub whitelist_required: bool,
+    pub merkle_root: [u8; 32],
+    pub claimed_amount: u64,
+    pub holder_gate: HolderGate,
+    pub vault: Pubkey,
+    pub whitelist_count: u64,
+    pub sponsor: Pubkey,
+    pub is_registered: bool,
+    pub registry_index: u64,
+    pub epoch_duration: i64,
+    pub current_epoch: u64,
+    pub epoch_claimed_count: u64,
+    pub stake_gate: StakeGate,
+    pub is_paused: bool,
+    pub escrowed_fee: u64,
+    pub total_weight: u64,
+    pub weight_pool: u64,
+    pub is_ended: bool,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default)]
+pub enum GateMode {
+    #[default]
+    None,
+    TokenBalance,
+    Collection,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default)]
+pub struct HolderGate {
+    pub mode: GateMode,
+    pub key: Pubkey,
+    pub min_balance: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default)]
+pub struct StakeGate {
+    pub pool: Pubkey,
+    pub min_stake: u64,
+}
+
+impl AirdropCampaign {
+    // Every field is fixed-size, so any instance serializes to the account length
+    pub fn len() -> usize {
+        AirdropCampaign::default().try_to_vec().unwrap().len()
+    }
+}
@@ -4,13 +4,18 @@ use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint,
     entrypoint::ProgramResult,
+    keccak,
     msg,
     program_error::ProgramError,
     pubkey::Pubkey,
     system_instruction,
     sysvar::{rent::Rent, Sysvar},
 };
-use spl_token::instruction as token_instruction;
+use spl_token_2022::{
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+    instruction as token_instruction,
+    state::{Account as TokenAccount, Mint},
+};
 use thiserror::Error;
 
 // Program ID and Fee Wallet
@@ -21,6 +26,37 @@ pub const FEE_WALLET: &str = "6zkf4DviZZkpWVEh53MrcQV6vGXGpESnNXgAvU6KpBUH";
 pub const AIRDROP_BASE_FEE: u64 = 100_000_000;  // 0.1 SOL
 pub const PER_RECIPIENT_FEE: u64 = 1_000_000;   // 0.001 SOL
 
+// Merkle and whitelist campaigns record claims in bitmap chunks at [CLAIM_BITMAP_SEED, campaign, chunk]
+pub const CLAIM_BITMAP_SEED: &[u8] = b"claim_bitmap";
+pub const BITMAP_CHUNK_BYTES: usize = 8192;
+pub const BITMAP_CHUNK_BITS: u64 = (BITMAP_CHUNK_BYTES * 8) as u64;
+
+// Per-wallet whitelist entries at [WHITELIST_SEED, campaign, wallet] and claim status
+// records at [CLAIM_SEED, campaign, wallet]
+pub const WHITELIST_SEED: &[u8] = b"whitelist";
+pub const CLAIM_SEED: &[u8] = b"claim";
+pub const MAX_WHITELIST_BATCH: usize = 20;
+
+// Metaplex token metadata, read to verify NFT collection membership
+pub const METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+
+// Workspace staking program, read for the optional minimum-stake claim requirement
+pub const STAKING_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("StakingPoo111111111111111111111111111111111");
+pub const USER_STAKE_SEED: &[u8] = b"user_stake";
+
+// Campaign tokens sit in a vault owned by the PDA [CAMPAIGN_AUTHORITY_SEED, campaign]
+pub const CAMPAIGN_AUTHORITY_SEED: &[u8] = b"campaign_authority";
+
+// Prefix for ClaimEvent records written with sol_log_data
+pub const CLAIM_EVENT_TAG: &[u8] = b"airdrop_claim";
+
+// Recurring campaigns track each wallet's last claimed epoch at [EPOCH_CLAIM_SEED, campaign, wallet]
+pub const EPOCH_CLAIM_SEED: &[u8] = b"epoch_claim";
+
+// Registry header at [REGISTRY_SEED]; entries at [REGISTRY_SEED, index] so clients can walk 0..campaign_count
+pub const REGISTRY_SEED: &[u8] = b"campaign_registry";
+
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct AirdropCampaign {
     pub owner: Pubkey,
@@ -33,12 +69,185 @@ pub struct AirdropCampaign {
     pub claimed_count: u64,
     pub max_recipients: u64,
     pub whitelist_required: bool,
+    pub merkle_root: [u8; 32],  // Non-zero switches claims to merkle proofs
+    pub claimed_amount: u64,
+    pub holder_gate: HolderGate,
+    pub vault: Pubkey,
+    pub whitelist_count: u64,  // Next bitmap index handed to a whitelisted wallet
+    pub sponsor: Pubkey,  // Default means claimers pay their own claim-account rent
+    pub is_registered: bool,
+    pub registry_index: u64,
+    pub epoch_duration: i64,  // Non-zero makes the campaign recur; max_recipients then applies per epoch
+    pub current_epoch: u64,
+    pub epoch_claimed_count: u64,
+    pub stake_gate: StakeGate,
+    pub is_paused: bool,  // Temporary halt; unlike EndAirdrop it keeps the campaign active
+    pub escrowed_fee: u64,  // Per-recipient fees held on the campaign account until EndAirdrop
+    pub total_weight: u64,  // Non-zero makes merkle leaf amounts snapshot weights
+    pub weight_pool: u64,   // Tokens split across total_weight, fixed when weighting is set
+    pub is_ended: bool,     // Set by EndAirdrop; an ended campaign cannot be restarted
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum GateMode {
+    None,
+    TokenBalance,  // Hold at least min_balance of the gate mint
+    Collection,    // Hold an NFT from the verified collection
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct HolderGate {
+    pub mode: GateMode,
+    pub key: Pubkey,  // Gate mint, or collection mint for Collection mode
+    pub min_balance: u64,
+}
+
+// Default pool disables the requirement
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct StakeGate {
+    pub pool: Pubkey,
+    pub min_stake: u64,
+}
+
+// Leading fields of the staking program's per-user stake record
+#[derive(BorshDeserialize, Debug)]
+pub struct UserStakeInfo {
+    pub owner: Pubkey,
+    pub stake_amount: u64,
+}
+
+// Leading fields of a Metaplex metadata account, up to the collection
+#[derive(BorshDeserialize, Debug)]
+pub struct MetadataPrefix {
+    pub key: u8,
+    pub update_authority: Pubkey,
+    pub mint: Pubkey,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    pub creators: Option<Vec<MetadataCreator>>,
+    pub primary_sale_happened: bool,
+    pub is_mutable: bool,
+    pub edition_nonce: Option<u8>,
+    pub token_standard: Option<u8>,
+    pub collection: Option<MetadataCollection>,
+}
+
+#[derive(BorshDeserialize, Debug)]
+pub struct MetadataCreator {
+    pub address: Pubkey,
+    pub verified: bool,
+    pub share: u8,
+}
+
+#[derive(BorshDeserialize, Debug)]
+pub struct MetadataCollection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
+impl AirdropCampaign {
+    pub fn is_merkle(&self) -> bool {
+        self.merkle_root != [0u8; 32]
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct UpdateCampaignArgs {
+    pub amount_per_recipient: u64,
+    pub max_recipients: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub whitelist_required: bool,
 }
 
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct MerkleClaimArgs {
+    pub index: u64,
+    pub amount: u64,
+    pub proof: Vec<[u8; 32]>,
+}
+
+// Claims against an entry set bit `index` in the campaign's claim bitmap
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct WhitelistEntry {
     pub wallet: Pubkey,
-    pub has_claimed: bool,
+    pub index: u64,
+}
+
+impl WhitelistEntry {
+    pub const LEN: usize = 32 + 8;
+}
+
+// Claim status for gated campaigns without a whitelist; existing means claimed
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct ClaimRecord {
+    pub wallet: Pubkey,
+    pub claimed_at: i64,
+}
+
+impl ClaimRecord {
+    pub const LEN: usize = 32 + 8;
+}
+
+// Logged as [CLAIM_EVENT_TAG, borsh(event)] by ClaimAirdrop, the only instruction that
+// pays recipients; index is the merkle leaf or whitelist position, otherwise the
+// campaign's claim sequence number
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct ClaimEvent {
+    pub campaign: Pubkey,
+    pub wallet: Pubkey,
+    pub amount: u64,
+    pub net_amount: u64,  // Received after any Token-2022 transfer fee
+    pub index: u64,
+    pub epoch: u64,
+    pub timestamp: i64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct EpochClaim {
+    pub wallet: Pubkey,
+    pub last_epoch: u64,
+}
+
+impl EpochClaim {
+    pub const LEN: usize = 32 + 8;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct CampaignRegistry {
+    pub campaign_count: u64,
+}
+
+impl CampaignRegistry {
+    pub const LEN: usize = 8;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CampaignStatus {
+    Pending,
+    Active,
+    Ended,
+    Closed,  // The campaign account has been closed
+}
+
+// Snapshot of a campaign for explorers; refreshed by SyncRegistryEntry
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct RegistryEntry {
+    pub campaign: Pubkey,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub status: CampaignStatus,
+    pub total_amount: u64,
+    pub claimed_count: u64,
+    pub claimed_amount: u64,
+    pub max_recipients: u64,
+    pub last_synced: i64,
+}
+
+impl RegistryEntry {
+    pub const LEN: usize = 32 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8;
 }
 
 #[derive(FromPrimitive, Debug)]
@@ -50,6 +259,18 @@ pub enum AirdropInstruction {
     EndAirdrop,
     ClaimAirdrop,
     WithdrawRemainingTokens,
+    AddToWhitelistBatch,
+    UpdateCampaign,
+    SetSponsor,
+    RegisterCampaign,
+    SyncRegistryEntry,
+    SetRecurring,
+    FundCampaign,
+    PauseCampaign,
+    ResumeCampaign,
+    SetWeightedDistribution,
+    CloseCampaign,
+    CloseWhitelistEntries,
 }
 
 #[derive(Error, Debug, Copy, Clone)]
@@ -74,6 +295,30 @@ pub enum AirdropError {
     MaxRecipientsReached,
     #[error("Insufficient funds")]
     InsufficientFunds,
+    #[error("Invalid merkle proof")]
+    InvalidProof,
+    #[error("Invalid claim account")]
+    InvalidClaimAccount,
+    #[error("Holder requirement not met")]
+    GateNotMet,
+    #[error("Invalid campaign vault")]
+    InvalidVault,
+    #[error("Whitelist batch too large")]
+    BatchTooLarge,
+    #[error("Campaign already started")]
+    CampaignStarted,
+    #[error("Invalid campaign parameters")]
+    InvalidCampaignParams,
+    #[error("Invalid claim payer")]
+    InvalidPayer,
+    #[error("Campaign already registered")]
+    AlreadyRegistered,
+    #[error("Invalid registry account")]
+    InvalidRegistryAccount,
+    #[error("Campaign paused")]
+    CampaignPaused,
+    #[error("Campaign must be ended and emptied before closing")]
+    CampaignNotSettled,
 }
 
 impl From<AirdropError> for ProgramError {
@@ -109,11 +354,47 @@ pub fn process_instruction(
             process_end_airdrop(program_id, accounts)
         }
         AirdropInstruction::ClaimAirdrop => {
-            process_claim_airdrop(program_id, accounts)
+            process_claim_airdrop(program_id, accounts, &instruction_data[1..])
         }
         AirdropInstruction::WithdrawRemainingTokens => {
             process_withdraw_remaining_tokens(program_id, accounts)
         }
+        AirdropInstruction::AddToWhitelistBatch => {
+            process_add_to_whitelist_batch(program_id, accounts, &instruction_data[1..])
+        }
+        AirdropInstruction::UpdateCampaign => {
+            process_update_campaign(program_id, accounts, &instruction_data[1..])
+        }
+        AirdropInstruction::SetSponsor => {
+            process_set_sponsor(program_id, accounts, &instruction_data[1..])
+        }
+        AirdropInstruction::RegisterCampaign => {
+            process_register_campaign(program_id, accounts)
+        }
+        AirdropInstruction::SyncRegistryEntry => {
+            process_sync_registry_entry(program_id, accounts)
+        }
+        AirdropInstruction::SetRecurring => {
+            process_set_recurring(program_id, accounts, &instruction_data[1..])
+        }
+        AirdropInstruction::FundCampaign => {
+            process_fund_campaign(program_id, accounts, &instruction_data[1..])
+        }
+        AirdropInstruction::PauseCampaign => {
+            process_set_campaign_paused(program_id, accounts, true)
+        }
+        AirdropInstruction::ResumeCampaign => {
+            process_set_campaign_paused(program_id, accounts, false)
+        }
+        AirdropInstruction::SetWeightedDistribution => {
+            process_set_weighted_distribution(program_id, accounts, &instruction_data[1..])
+        }
+        AirdropInstruction::CloseCampaign => {
+            process_close_campaign(program_id, accounts)
+        }
+        AirdropInstruction::CloseWhitelistEntries => {
+            process_close_whitelist_entries(program_id, accounts)
+        }
     }
 }
 
@@ -129,21 +410,53 @@ fn process_create_campaign(
     let fee_wallet = next_account_info(account_info_iter)?;
     let system_program = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let owner_token_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
 
     // Verify fee wallet
     if fee_wallet.key.to_string() != FEE_WALLET {
         return Err(ProgramError::InvalidArgument);
     }
 
-    let campaign_data = AirdropCampaign::try_from_slice(instruction_data)?;
-    let total_fee = AIRDROP_BASE_FEE + (PER_RECIPIENT_FEE * campaign_data.max_recipients);
+    // The campaign account must be a fresh, program-owned allocation; a live campaign
+    // always has a non-zero owner, so any written byte means it is already in use
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if campaign_account.data.borrow().iter().any(|byte| *byte != 0) {
+        return Err(AirdropError::CampaignAlreadyExists.into());
+    }
+
+    let mut campaign_data = AirdropCampaign::try_from_slice(instruction_data)?;
+    if campaign_data.end_time != 0 && campaign_data.end_time <= campaign_data.start_time {
+        return Err(AirdropError::InvalidCampaignParams.into());
+    }
+    campaign_data.claimed_count = 0;
+    campaign_data.claimed_amount = 0;
+    campaign_data.whitelist_count = 0;
+    campaign_data.sponsor = Pubkey::default();
+    campaign_data.is_registered = false;
+    campaign_data.registry_index = 0;
+    campaign_data.epoch_duration = 0;
+    campaign_data.current_epoch = 0;
+    campaign_data.epoch_claimed_count = 0;
+    campaign_data.is_paused = false;
+    campaign_data.escrowed_fee = PER_RECIPIENT_FEE
+        .checked_mul(campaign_data.max_recipients)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    campaign_data.total_weight = 0;
+    campaign_data.weight_pool = 0;
+    campaign_data.is_ended = false;
+    campaign_data.owner = *owner_account.key;
+    campaign_data.mint = *mint_account.key;
+    campaign_data.vault = *vault_account.key;
 
     // Transfer airdrop fee
     solana_program::program::invoke(
         &system_instruction::transfer(
             owner_account.key,
             fee_wallet.key,
-            total_fee,
+            AIRDROP_BASE_FEE,
         ),
         &[
             owner_account.clone(),
@@ -152,6 +465,47 @@ fn process_create_campaign(
         ],
     )?;
 
+    // Per-recipient fees wait on the campaign account; unused slots are refunded at EndAirdrop
+    solana_program::program::invoke(
+        &system_instruction::transfer(
+            owner_account.key,
+            campaign_account.key,
+            campaign_data.escrowed_fee,
+        ),
+        &[
+            owner_account.clone(),
+            campaign_account.clone(),
+            system_program.clone(),
+        ],
+    )?;
+
+    // Escrow the full campaign amount so claims never need the owner's signature
+    let (campaign_authority, _) = Pubkey::find_program_address(
+        &[CAMPAIGN_AUTHORITY_SEED, campaign_account.key.as_ref()],
+        program_id,
+    );
+    let vault = unpack_token_account(vault_account)?;
+    if vault.owner != campaign_authority || vault.mint != *mint_account.key {
+        return Err(AirdropError::InvalidVault.into());
+    }
+
+    // Transfer-fee mints withhold part of the deposit; only what reaches the vault is claimable
+    let received = deposit_to_vault(
+        owner_token_account,
+        vault_account,
+        mint_account,
+        owner_account,
+        token_program,
+        campaign_data.total_amount,
+    )?;
+    if received == 0 {
+        return Err(AirdropError::InsufficientFunds.into());
+    }
+    if received < campaign_data.total_amount {
+        msg!("Transfer fee withheld {} tokens", campaign_data.total_amount - received);
+        campaign_data.total_amount = received;
+    }
+
     campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
 
     Ok(())
@@ -166,14 +520,27 @@ fn process_add_to_whitelist(
     let owner_account = next_account_info(account_info_iter)?;
     let campaign_account = next_account_info(account_info_iter)?;
     let whitelist_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
 
-    let campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
-    if campaign_data.owner != *owner_account.key {
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
         return Err(AirdropError::InvalidCampaignOwner.into());
     }
 
-    let whitelist_entry = WhitelistEntry::try_from_slice(instruction_data)?;
-    whitelist_entry.serialize(&mut *whitelist_account.data.borrow_mut())?;
+    let wallet = Pubkey::try_from_slice(instruction_data)?;
+    create_whitelist_entry(
+        program_id,
+        owner_account,
+        campaign_account,
+        &mut campaign_data,
+        whitelist_account,
+        system_program,
+        &wallet,
+    )?;
+    campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
 
     Ok(())
 }
@@ -189,9 +556,19 @@ fn process_remove_from_whitelist(
     let whitelist_account = next_account_info(account_info_iter)?;
 
     let campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
-    if campaign_data.owner != *owner_account.key {
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
         return Err(AirdropError::InvalidCampaignOwner.into());
     }
+    // A re-added wallet gets a fresh bitmap index, so entries can only go before claims start
+    if campaign_data.is_active || campaign_data.claimed_count > 0 {
+        return Err(AirdropError::CampaignStarted.into());
+    }
+
+    if whitelist_account.owner != program_id {
+        return Err(AirdropError::InvalidClaimAccount.into());
+    }
+    let wallet = WhitelistEntry::try_from_slice(&whitelist_account.data.borrow())?.wallet;
+    load_whitelist_entry(program_id, campaign_account, whitelist_account, &wallet)?;
 
     // Close whitelist account
     let dest_starting_lamports = owner_account.lamports();
@@ -199,6 +576,7 @@ fn process_remove_from_whitelist(
         .checked_add(whitelist_account.lamports())
         .unwrap();
     **whitelist_account.lamports.borrow_mut() = 0;
+    whitelist_account.data.borrow_mut().fill(0);
 
     Ok(())
 }
@@ -211,13 +589,23 @@ fn process_start_airdrop(
     let owner_account = next_account_info(account_info_iter)?;
     let campaign_account = next_account_info(account_info_iter)?;
 
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
     let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
-    if campaign_data.owner != *owner_account.key {
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
         return Err(AirdropError::InvalidCampaignOwner.into());
     }
+    // EndAirdrop already settled the fee escrow, so the campaign cannot run again
+    if campaign_data.is_ended {
+        return Err(AirdropError::CampaignEnded.into());
+    }
 
+    // A configured start time is kept; claims simply wait for it
     campaign_data.is_active = true;
-    campaign_data.start_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if campaign_data.start_time == 0 {
+        campaign_data.start_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    }
     campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
 
     Ok(())
@@ -230,14 +618,40 @@ fn process_end_airdrop(
     let account_info_iter = &mut accounts.iter();
     let owner_account = next_account_info(account_info_iter)?;
     let campaign_account = next_account_info(account_info_iter)?;
+    let fee_wallet = next_account_info(account_info_iter)?;
+
+    if fee_wallet.key.to_string() != FEE_WALLET {
+        return Err(ProgramError::InvalidArgument);
+    }
 
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
     let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
-    if campaign_data.owner != *owner_account.key {
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
         return Err(AirdropError::InvalidCampaignOwner.into());
     }
 
     campaign_data.is_active = false;
+    campaign_data.is_ended = true;
     campaign_data.end_time = solana_program::clock::Clock::get()?.unix_timestamp;
+
+    // Settle the escrowed fee: the fee wallet is paid for claims made, the owner gets the rest
+    if campaign_data.escrowed_fee > 0 {
+        let earned = PER_RECIPIENT_FEE
+            .checked_mul(campaign_data.claimed_count)
+            .ok_or(ProgramError::ArithmeticOverflow)?
+            .min(campaign_data.escrowed_fee);
+        let refund = campaign_data.escrowed_fee - earned;
+
+        **campaign_account.lamports.borrow_mut() -= campaign_data.escrowed_fee;
+        **owner_account.lamports.borrow_mut() += refund;
+        **fee_wallet.lamports.borrow_mut() += earned;
+        campaign_data.escrowed_fee = 0;
+
+        msg!("Refunded {} escrowed lamports after {} claims", refund, campaign_data.claimed_count);
+    }
+
     campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
 
     Ok(())
@@ -246,60 +660,214 @@ fn process_end_airdrop(
 fn process_claim_airdrop(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
+    instruction_data: &[u8],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let claimer_account = next_account_info(account_info_iter)?;
     let campaign_account = next_account_info(account_info_iter)?;
     let whitelist_account = next_account_info(account_info_iter)?;
-    let token_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let campaign_authority = next_account_info(account_info_iter)?;
+    let claimer_token_account = next_account_info(account_info_iter)?;
+    let payer_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+
+    if !claimer_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if campaign_account.owner != program_id || !is_token_program(token_program.key) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
     let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+
+    // Rent for claim accounts comes from the payer: the claimer, or the campaign's sponsor
+    let sponsored = campaign_data.sponsor != Pubkey::default()
+        && *payer_account.key == campaign_data.sponsor;
+    if !payer_account.is_signer || (*payer_account.key != *claimer_account.key && !sponsored) {
+        return Err(AirdropError::InvalidPayer.into());
+    }
     
     if !campaign_data.is_active {
         return Err(AirdropError::CampaignNotActive.into());
     }
+    if campaign_data.is_paused {
+        return Err(AirdropError::CampaignPaused.into());
+    }
+
+    // Claims are only accepted inside the configured window; a zero end_time leaves it open
+    let now = solana_program::clock::Clock::get()?.unix_timestamp;
+    if now < campaign_data.start_time {
+        return Err(AirdropError::CampaignNotActive.into());
+    }
+    if campaign_data.end_time != 0 && now >= campaign_data.end_time {
+        return Err(AirdropError::CampaignEnded.into());
+    }
+
+    // Each new epoch starts with a fresh set of recipient slots
+    let epoch = if campaign_data.epoch_duration > 0 {
+        let elapsed = now.saturating_sub(campaign_data.start_time);
+        let epoch = (elapsed / campaign_data.epoch_duration) as u64;
+        if epoch != campaign_data.current_epoch {
+            campaign_data.current_epoch = epoch;
+            campaign_data.epoch_claimed_count = 0;
+        }
+        Some(epoch)
+    } else {
+        None
+    };
 
-    if campaign_data.claimed_count >= campaign_data.max_recipients {
+    let recipients = match epoch {
+        Some(_) => campaign_data.epoch_claimed_count,
+        None => campaign_data.claimed_count,
+    };
+    if recipients >= campaign_data.max_recipients {
         return Err(AirdropError::MaxRecipientsReached.into());
     }
 
-    if campaign_data.whitelist_required {
-        let whitelist_entry = WhitelistEntry::try_from_slice(&whitelist_account.data.borrow())?;
-        if whitelist_entry.wallet != *claimer_account.key {
-            return Err(AirdropError::NotWhitelisted.into());
+    // Merkle campaigns: the third account is the bitmap chunk holding this leaf's claim bit
+    let (amount, index) = if campaign_data.is_merkle() {
+        let args = MerkleClaimArgs::try_from_slice(instruction_data)?;
+        if args.index >= campaign_data.max_recipients {
+            return Err(AirdropError::InvalidProof.into());
         }
-        if whitelist_entry.has_claimed {
-            return Err(AirdropError::AlreadyClaimed.into());
+        let leaf = keccak::hashv(&[
+            &args.index.to_le_bytes(),
+            claimer_account.key.as_ref(),
+            &args.amount.to_le_bytes(),
+        ])
+        .to_bytes();
+        if !verify_merkle_proof(&campaign_data.merkle_root, leaf, &args.proof) {
+            return Err(AirdropError::InvalidProof.into());
         }
-    }
 
-    // Transfer tokens
-    solana_program::program::invoke(
-        &token_instruction::transfer(
-            token_program.key,
-            token_account.key,
+        let system_program = next_account_info(account_info_iter)?;
+        set_claimed_bit(
+            program_id,
+            campaign_account,
+            whitelist_account,
+            payer_account,
+            system_program,
+            args.index,
+        )?;
+        // Weighted campaigns pay the leaf's pro-rata share of the pool
+        let amount = if campaign_data.total_weight > 0 {
+            (args.amount as u128 * campaign_data.weight_pool as u128
+                / campaign_data.total_weight as u128) as u64
+        } else {
+            args.amount
+        };
+        (amount, args.index)
+    } else if let Some(epoch) = epoch {
+        // Recurring campaigns: the third account is the whitelist entry when one is required,
+        // followed by the wallet's epoch claim record; otherwise it is the record itself
+        let record_account = if campaign_data.whitelist_required {
+            load_whitelist_entry(
+                program_id,
+                campaign_account,
+                whitelist_account,
+                claimer_account.key,
+            )?;
+            next_account_info(account_info_iter)?
+        } else {
+            whitelist_account
+        };
+
+        let system_program = next_account_info(account_info_iter)?;
+        mark_epoch_claimed(
+            program_id,
+            campaign_account,
+            record_account,
+            claimer_account,
+            payer_account,
+            system_program,
+            epoch,
+        )?;
+        (campaign_data.amount_per_recipient, campaign_data.claimed_count)
+    } else if campaign_data.whitelist_required {
+        // Whitelist entries stay read-only; the claim flips the entry's bit in the bitmap
+        let whitelist_entry = load_whitelist_entry(
+            program_id,
+            campaign_account,
+            whitelist_account,
             claimer_account.key,
-            &campaign_data.owner,
-            &[&campaign_data.owner],
-            campaign_data.amount_per_recipient,
-        )?,
-        &[
-            token_account.clone(),
-            claimer_account.clone(),
-            owner_account.clone(),
-            token_program.clone(),
-        ],
+        )?;
+
+        let bitmap_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        set_claimed_bit(
+            program_id,
+            campaign_account,
+            bitmap_account,
+            payer_account,
+            system_program,
+            whitelist_entry.index,
+        )?;
+        (campaign_data.amount_per_recipient, whitelist_entry.index)
+    } else {
+        // Campaigns without a whitelist track claims in the wallet's claim record
+        let system_program = next_account_info(account_info_iter)?;
+        mark_wallet_claimed(
+            program_id,
+            campaign_account,
+            whitelist_account,
+            claimer_account,
+            payer_account,
+            system_program,
+        )?;
+        (campaign_data.amount_per_recipient, campaign_data.claimed_count)
+    };
+
+    if campaign_data.holder_gate.mode != GateMode::None {
+        check_holder_gate(&campaign_data.holder_gate, claimer_account.key, account_info_iter)?;
+    }
+    if campaign_data.stake_gate.pool != Pubkey::default() {
+        check_stake_gate(&campaign_data.stake_gate, claimer_account.key, account_info_iter)?;
+    }
+
+    let claimed_amount = campaign_data.claimed_amount
+        .checked_add(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    if claimed_amount > campaign_data.total_amount {
+        return Err(AirdropError::InsufficientFunds.into());
+    }
+
+    let claimer_tokens = unpack_token_account(claimer_token_account)?;
+    if claimer_tokens.owner != *claimer_account.key || claimer_tokens.mint != campaign_data.mint {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Transfer tokens; with a transfer-fee mint the claimer receives the net amount
+    let net_amount = transfer_from_vault(
+        program_id,
+        campaign_account,
+        &campaign_data,
+        vault_account,
+        mint_account,
+        claimer_token_account,
+        campaign_authority,
+        token_program,
+        amount,
     )?;
 
     campaign_data.claimed_count += 1;
+    campaign_data.claimed_amount = claimed_amount;
+    if epoch.is_some() {
+        campaign_data.epoch_claimed_count += 1;
+    }
     campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
 
-    if campaign_data.whitelist_required {
-        let mut whitelist_entry = WhitelistEntry::try_from_slice(&whitelist_account.data.borrow())?;
-        whitelist_entry.has_claimed = true;
-        whitelist_entry.serialize(&mut *whitelist_account.data.borrow_mut())?;
-    }
+    let event = ClaimEvent {
+        campaign: *campaign_account.key,
+        wallet: *claimer_account.key,
+        amount,
+        net_amount,
+        index,
+        epoch: epoch.unwrap_or(0),
+        timestamp: now,
+    };
+    solana_program::log::sol_log_data(&[CLAIM_EVENT_TAG, &event.try_to_vec()?]);
 
     Ok(())
 }
@@ -314,9 +882,14 @@ fn process_withdraw_remaining_tokens(
     let token_account = next_account_info(account_info_iter)?;
     let destination_account = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let campaign_authority = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
 
-    let campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
-    if campaign_data.owner != *owner_account.key {
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
         return Err(AirdropError::InvalidCampaignOwner.into());
     }
 
@@ -326,27 +899,1030 @@ fn process_withdraw_remaining_tokens(
 
     // Transfer remaining tokens
     let remaining_amount = campaign_data.total_amount
-        .checked_sub(campaign_data.claimed_count
-            .checked_mul(campaign_data.amount_per_recipient)
-            .unwrap())
-        .unwrap();
+        .checked_sub(campaign_data.claimed_amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
 
-    solana_program::program::invoke(
-        &token_instruction::transfer(
+    transfer_from_vault(
+        program_id,
+        campaign_account,
+        &campaign_data,
+        token_account,
+        mint_account,
+        destination_account,
+        campaign_authority,
+        token_program,
+        remaining_amount,
+    )?;
+
+    // Nothing is left to claim once the remainder has been pulled
+    campaign_data.total_amount = campaign_data.claimed_amount;
+    campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Sorted-pair keccak proof, the same scheme the launchpad uses for whitelist roots
+fn verify_merkle_proof(root: &[u8; 32], leaf: [u8; 32], proof: &[[u8; 32]]) -> bool {
+    let mut node = leaf;
+    for sibling in proof {
+        node = if node <= *sibling {
+            keccak::hashv(&[&node, sibling]).to_bytes()
+        } else {
+            keccak::hashv(&[sibling, &node]).to_bytes()
+        };
+    }
+
+    node == *root
+}
+
+// Reads the canonical [WHITELIST_SEED, campaign, wallet] entry, rejecting look-alike accounts
+fn load_whitelist_entry(
+    program_id: &Pubkey,
+    campaign_account: &AccountInfo,
+    whitelist_account: &AccountInfo,
+    wallet: &Pubkey,
+) -> Result<WhitelistEntry, ProgramError> {
+    let (expected, _) = Pubkey::find_program_address(
+        &[WHITELIST_SEED, campaign_account.key.as_ref(), wallet.as_ref()],
+        program_id,
+    );
+    if *whitelist_account.key != expected
+        || whitelist_account.owner != program_id
+        || whitelist_account.data_is_empty()
+    {
+        return Err(AirdropError::NotWhitelisted.into());
+    }
+
+    let whitelist_entry = WhitelistEntry::try_from_slice(&whitelist_account.data.borrow())?;
+    if whitelist_entry.wallet != *wallet {
+        return Err(AirdropError::NotWhitelisted.into());
+    }
+
+    Ok(whitelist_entry)
+}
+
+// Marks `index` as claimed, creating its bitmap chunk on first use; fails if already set
+fn set_claimed_bit<'a>(
+    program_id: &Pubkey,
+    campaign_account: &AccountInfo<'a>,
+    bitmap_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    index: u64,
+) -> ProgramResult {
+    let chunk = ((index / BITMAP_CHUNK_BITS) as u32).to_le_bytes();
+    let (expected, bump) = Pubkey::find_program_address(
+        &[CLAIM_BITMAP_SEED, campaign_account.key.as_ref(), &chunk],
+        program_id,
+    );
+    if *bitmap_account.key != expected {
+        return Err(AirdropError::InvalidClaimAccount.into());
+    }
+
+    if bitmap_account.data_is_empty() {
+        solana_program::program::invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                bitmap_account.key,
+                Rent::get()?.minimum_balance(BITMAP_CHUNK_BYTES),
+                BITMAP_CHUNK_BYTES as u64,
+                program_id,
+            ),
+            &[payer.clone(), bitmap_account.clone(), system_program.clone()],
+            &[&[CLAIM_BITMAP_SEED, campaign_account.key.as_ref(), &chunk, &[bump]]],
+        )?;
+    } else if bitmap_account.owner != program_id {
+        return Err(AirdropError::InvalidClaimAccount.into());
+    }
+
+    let bit = index % BITMAP_CHUNK_BITS;
+    let mut data = bitmap_account.data.borrow_mut();
+    let byte = &mut data[(bit / 8) as usize];
+    let mask = 1u8 << (bit % 8);
+    if *byte & mask != 0 {
+        return Err(AirdropError::AlreadyClaimed.into());
+    }
+    *byte |= mask;
+
+    Ok(())
+}
+
+fn mark_wallet_claimed<'a>(
+    program_id: &Pubkey,
+    campaign_account: &AccountInfo<'a>,
+    claim_account: &AccountInfo<'a>,
+    claimer: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    let (expected, bump) = Pubkey::find_program_address(
+        &[CLAIM_SEED, campaign_account.key.as_ref(), claimer.key.as_ref()],
+        program_id,
+    );
+    if *claim_account.key != expected {
+        return Err(AirdropError::InvalidClaimAccount.into());
+    }
+    if !claim_account.data_is_empty() {
+        return Err(AirdropError::AlreadyClaimed.into());
+    }
+
+    let record = ClaimRecord {
+        wallet: *claimer.key,
+        claimed_at: solana_program::clock::Clock::get()?.unix_timestamp,
+    };
+    solana_program::program::invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            claim_account.key,
+            Rent::get()?.minimum_balance(ClaimRecord::LEN),
+            ClaimRecord::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), claim_account.clone(), system_program.clone()],
+        &[&[CLAIM_SEED, campaign_account.key.as_ref(), claimer.key.as_ref(), &[bump]]],
+    )?;
+    record.serialize(&mut *claim_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Creates the wallet's epoch claim record on first use; fails if it already claimed this epoch
+fn mark_epoch_claimed<'a>(
+    program_id: &Pubkey,
+    campaign_account: &AccountInfo<'a>,
+    record_account: &AccountInfo<'a>,
+    claimer: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    epoch: u64,
+) -> ProgramResult {
+    let (expected, bump) = Pubkey::find_program_address(
+        &[EPOCH_CLAIM_SEED, campaign_account.key.as_ref(), claimer.key.as_ref()],
+        program_id,
+    );
+    if *record_account.key != expected {
+        return Err(AirdropError::InvalidClaimAccount.into());
+    }
+
+    if record_account.data_is_empty() {
+        solana_program::program::invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                record_account.key,
+                Rent::get()?.minimum_balance(EpochClaim::LEN),
+                EpochClaim::LEN as u64,
+                program_id,
+            ),
+            &[payer.clone(), record_account.clone(), system_program.clone()],
+            &[&[EPOCH_CLAIM_SEED, campaign_account.key.as_ref(), claimer.key.as_ref(), &[bump]]],
+        )?;
+    } else {
+        if record_account.owner != program_id {
+            return Err(AirdropError::InvalidClaimAccount.into());
+        }
+        let record = EpochClaim::try_from_slice(&record_account.data.borrow())?;
+        if record.last_epoch == epoch {
+            return Err(AirdropError::AlreadyClaimed.into());
+        }
+    }
+
+    EpochClaim {
+        wallet: *claimer.key,
+        last_epoch: epoch,
+    }
+    .serialize(&mut *record_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Trailing accounts: the claimer's gate token account, plus its metadata for Collection mode
+fn check_holder_gate<'a, 'b: 'a>(
+    gate: &HolderGate,
+    claimer: &Pubkey,
+    account_info_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+) -> ProgramResult {
+    let gate_token_account = next_account_info(account_info_iter)?;
+    if !is_token_program(gate_token_account.owner) {
+        return Err(AirdropError::GateNotMet.into());
+    }
+    let holding = unpack_token_account(gate_token_account)?;
+    if holding.owner != *claimer {
+        return Err(AirdropError::GateNotMet.into());
+    }
+
+    match gate.mode {
+        GateMode::None => Ok(()),
+        GateMode::TokenBalance => {
+            if holding.mint != gate.key || holding.amount < gate.min_balance.max(1) {
+                return Err(AirdropError::GateNotMet.into());
+            }
+            Ok(())
+        }
+        GateMode::Collection => {
+            let metadata_account = next_account_info(account_info_iter)?;
+            if holding.amount == 0 || metadata_account.owner.to_string() != METADATA_PROGRAM_ID {
+                return Err(AirdropError::GateNotMet.into());
+            }
+            let (expected, _) = Pubkey::find_program_address(
+                &[b"metadata", metadata_account.owner.as_ref(), holding.mint.as_ref()],
+                metadata_account.owner,
+            );
+            if *metadata_account.key != expected {
+                return Err(AirdropError::GateNotMet.into());
+            }
+
+            let metadata = MetadataPrefix::deserialize(&mut &metadata_account.data.borrow()[..])?;
+            match metadata.collection {
+                Some(collection) if collection.verified && collection.key == gate.key => Ok(()),
+                _ => Err(AirdropError::GateNotMet.into()),
+            }
+        }
+    }
+}
+
+// Trailing accounts after any holder gate: the stake pool and the claimer's UserStakeInfo PDA
+fn check_stake_gate<'a, 'b: 'a>(
+    gate: &StakeGate,
+    claimer: &Pubkey,
+    account_info_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+) -> ProgramResult {
+    let stake_pool = next_account_info(account_info_iter)?;
+    let user_stake_account = next_account_info(account_info_iter)?;
+
+    if *stake_pool.key != gate.pool
+        || *stake_pool.owner != STAKING_PROGRAM_ID
+        || user_stake_account.owner != stake_pool.owner
+    {
+        return Err(AirdropError::GateNotMet.into());
+    }
+    let (expected, _) = Pubkey::find_program_address(
+        &[USER_STAKE_SEED, stake_pool.key.as_ref(), claimer.as_ref()],
+        user_stake_account.owner,
+    );
+    if *user_stake_account.key != expected {
+        return Err(AirdropError::GateNotMet.into());
+    }
+
+    let stake_info = UserStakeInfo::deserialize(&mut &user_stake_account.data.borrow()[..])?;
+    if stake_info.owner != *claimer || stake_info.stake_amount < gate.min_stake.max(1) {
+        return Err(AirdropError::GateNotMet.into());
+    }
+
+    Ok(())
+}
+
+// Returns the amount the destination actually receives after any transfer fee
+#[allow(clippy::too_many_arguments)]
+fn transfer_from_vault<'a>(
+    program_id: &Pubkey,
+    campaign_account: &AccountInfo<'a>,
+    campaign_data: &AirdropCampaign,
+    vault_account: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    campaign_authority: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> Result<u64, ProgramError> {
+    if *vault_account.key != campaign_data.vault {
+        return Err(AirdropError::InvalidVault.into());
+    }
+    if *mint_account.key != campaign_data.mint {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let (expected, bump) = Pubkey::find_program_address(
+        &[CAMPAIGN_AUTHORITY_SEED, campaign_account.key.as_ref()],
+        program_id,
+    );
+    if *campaign_authority.key != expected {
+        return Err(AirdropError::InvalidVault.into());
+    }
+    if amount == 0 {
+        return Ok(0);
+    }
+
+    let (decimals, fee) = mint_transfer_terms(mint_account, amount)?;
+    solana_program::program::invoke_signed(
+        &token_instruction::transfer_checked(
             token_program.key,
-            token_account.key,
-            destination_account.key,
-            &campaign_data.owner,
-            &[&campaign_data.owner],
-            remaining_amount,
+            vault_account.key,
+            mint_account.key,
+            destination.key,
+            campaign_authority.key,
+            &[],
+            amount,
+            decimals,
         )?,
         &[
-            token_account.clone(),
-            destination_account.clone(),
-            owner_account.clone(),
+            vault_account.clone(),
+            mint_account.clone(),
+            destination.clone(),
+            campaign_authority.clone(),
             token_program.clone(),
         ],
+        &[&[CAMPAIGN_AUTHORITY_SEED, campaign_account.key.as_ref(), &[bump]]],
     )?;
 
-    Ok(())
+    if fee > 0 {
+        msg!("Transfer fee withheld {} tokens", fee);
+    }
+    Ok(amount - fee)
+}
+
+// Returns how much the vault balance grew, less than `amount` under a transfer fee
+fn deposit_to_vault<'a>(
+    source: &AccountInfo<'a>,
+    vault_account: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    owner: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> Result<u64, ProgramError> {
+    let before = unpack_token_account(vault_account)?.amount;
+    let (decimals, _) = mint_transfer_terms(mint_account, amount)?;
+    solana_program::program::invoke(
+        &token_instruction::transfer_checked(
+            token_program.key,
+            source.key,
+            mint_account.key,
+            vault_account.key,
+            owner.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[
+            source.clone(),
+            mint_account.clone(),
+            vault_account.clone(),
+            owner.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    Ok(unpack_token_account(vault_account)?.amount.saturating_sub(before))
+}
+
+// Decimals for transfer_checked, plus the fee a Token-2022 transfer-fee mint withholds this epoch
+fn mint_transfer_terms(mint_account: &AccountInfo, amount: u64) -> Result<(u8, u64), ProgramError> {
+    if !is_token_program(mint_account.owner) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let data = mint_account.data.borrow();
+    let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+    let fee = match mint.get_extension::<TransferFeeConfig>() {
+        Ok(config) => config
+            .calculate_epoch_fee(solana_program::clock::Clock::get()?.epoch, amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?,
+        Err(_) => 0,
+    };
+
+    Ok((mint.base.decimals, fee))
+}
+
+fn is_token_program(key: &Pubkey) -> bool {
+    *key == spl_token::id() || *key == spl_token_2022::id()
+}
+
+// Reads SPL Token and Token-2022 accounts alike; the latter may carry extensions
+fn unpack_token_account(account: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    if !is_token_program(account.owner) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(StateWithExtensions::<TokenAccount>::unpack(&account.data.borrow())?.base)
+}
+
+fn process_add_to_whitelist_batch(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let campaign_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
+        return Err(AirdropError::InvalidCampaignOwner.into());
+    }
+
+    let wallets = Vec::<Pubkey>::try_from_slice(instruction_data)?;
+    if wallets.len() > MAX_WHITELIST_BATCH {
+        return Err(AirdropError::BatchTooLarge.into());
+    }
+
+    for wallet in &wallets {
+        let whitelist_account = next_account_info(account_info_iter)?;
+        create_whitelist_entry(
+            program_id,
+            owner_account,
+            campaign_account,
+            &mut campaign_data,
+            whitelist_account,
+            system_program,
+            wallet,
+        )?;
+    }
+
+    campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
+
+    msg!("Whitelisted {} wallets", wallets.len());
+    Ok(())
+}
+
+fn create_whitelist_entry<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    campaign_account: &AccountInfo<'a>,
+    campaign_data: &mut AirdropCampaign,
+    whitelist_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    wallet: &Pubkey,
+) -> ProgramResult {
+    let (expected, bump) = Pubkey::find_program_address(
+        &[WHITELIST_SEED, campaign_account.key.as_ref(), wallet.as_ref()],
+        program_id,
+    );
+    if *whitelist_account.key != expected {
+        return Err(AirdropError::InvalidClaimAccount.into());
+    }
+    // Re-adding an existing wallet is a no-op so batches can be retried safely
+    if !whitelist_account.data_is_empty() {
+        return Ok(());
+    }
+
+    solana_program::program::invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            whitelist_account.key,
+            Rent::get()?.minimum_balance(WhitelistEntry::LEN),
+            WhitelistEntry::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), whitelist_account.clone(), system_program.clone()],
+        &[&[WHITELIST_SEED, campaign_account.key.as_ref(), wallet.as_ref(), &[bump]]],
+    )?;
+
+    WhitelistEntry {
+        wallet: *wallet,
+        index: campaign_data.whitelist_count,
+    }
+    .serialize(&mut *whitelist_account.data.borrow_mut())?;
+    campaign_data.whitelist_count += 1;
+
+    Ok(())
+}
+
+fn process_update_campaign(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let campaign_account = next_account_info(account_info_iter)?;
+    let fee_wallet = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if fee_wallet.key.to_string() != FEE_WALLET {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
+        return Err(AirdropError::InvalidCampaignOwner.into());
+    }
+    if campaign_data.is_active || campaign_data.claimed_count > 0 {
+        return Err(AirdropError::CampaignStarted.into());
+    }
+    if campaign_data.is_ended {
+        return Err(AirdropError::CampaignEnded.into());
+    }
+
+    let args = UpdateCampaignArgs::try_from_slice(instruction_data)?;
+    if args.end_time != 0 && args.end_time <= args.start_time {
+        return Err(AirdropError::InvalidCampaignParams.into());
+    }
+    // Fixed-amount campaigns must still be covered by the escrowed total
+    if !campaign_data.is_merkle()
+        && args.amount_per_recipient
+            .checked_mul(args.max_recipients)
+            .map_or(true, |needed| needed > campaign_data.total_amount)
+    {
+        return Err(AirdropError::InsufficientFunds.into());
+    }
+
+    // Extra recipient slots escrow the per-recipient fee like they would at creation
+    if args.max_recipients > campaign_data.max_recipients {
+        let extra_fee = args
+            .max_recipients
+            .checked_sub(campaign_data.max_recipients)
+            .and_then(|extra| extra.checked_mul(PER_RECIPIENT_FEE))
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        solana_program::program::invoke(
+            &system_instruction::transfer(owner_account.key, campaign_account.key, extra_fee),
+            &[
+                owner_account.clone(),
+                campaign_account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+        campaign_data.escrowed_fee = campaign_data
+            .escrowed_fee
+            .checked_add(extra_fee)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+    } else {
+        // Dropped slots hand their escrowed fee back to the owner
+        let released_fee = campaign_data
+            .max_recipients
+            .checked_sub(args.max_recipients)
+            .and_then(|dropped| dropped.checked_mul(PER_RECIPIENT_FEE))
+            .ok_or(ProgramError::ArithmeticOverflow)?
+            .min(campaign_data.escrowed_fee);
+        **campaign_account.lamports.borrow_mut() -= released_fee;
+        **owner_account.lamports.borrow_mut() += released_fee;
+        campaign_data.escrowed_fee -= released_fee;
+    }
+
+    campaign_data.amount_per_recipient = args.amount_per_recipient;
+    campaign_data.max_recipients = args.max_recipients;
+    campaign_data.start_time = args.start_time;
+    campaign_data.end_time = args.end_time;
+    campaign_data.whitelist_required = args.whitelist_required;
+    campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// A zero sponsor returns the campaign to claimer-paid claims
+fn process_set_sponsor(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let campaign_account = next_account_info(account_info_iter)?;
+
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
+        return Err(AirdropError::InvalidCampaignOwner.into());
+    }
+
+    campaign_data.sponsor = Pubkey::try_from_slice(instruction_data)?;
+    campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
+
+    msg!("Campaign sponsor set to {}", campaign_data.sponsor);
+    Ok(())
+}
+
+fn process_register_campaign(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let campaign_account = next_account_info(account_info_iter)?;
+    let registry_account = next_account_info(account_info_iter)?;
+    let entry_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
+        return Err(AirdropError::InvalidCampaignOwner.into());
+    }
+    if campaign_data.is_registered {
+        return Err(AirdropError::AlreadyRegistered.into());
+    }
+
+    let (registry_key, registry_bump) = Pubkey::find_program_address(&[REGISTRY_SEED], program_id);
+    if *registry_account.key != registry_key {
+        return Err(AirdropError::InvalidRegistryAccount.into());
+    }
+    // The first registration creates the shared header
+    if registry_account.data_is_empty() {
+        solana_program::program::invoke_signed(
+            &system_instruction::create_account(
+                owner_account.key,
+                registry_account.key,
+                Rent::get()?.minimum_balance(CampaignRegistry::LEN),
+                CampaignRegistry::LEN as u64,
+                program_id,
+            ),
+            &[owner_account.clone(), registry_account.clone(), system_program.clone()],
+            &[&[REGISTRY_SEED, &[registry_bump]]],
+        )?;
+        CampaignRegistry { campaign_count: 0 }
+            .serialize(&mut *registry_account.data.borrow_mut())?;
+    }
+
+    let mut registry = CampaignRegistry::try_from_slice(&registry_account.data.borrow())?;
+    let index = registry.campaign_count.to_le_bytes();
+    let (entry_key, entry_bump) = Pubkey::find_program_address(&[REGISTRY_SEED, &index], program_id);
+    if *entry_account.key != entry_key {
+        return Err(AirdropError::InvalidRegistryAccount.into());
+    }
+
+    solana_program::program::invoke_signed(
+        &system_instruction::create_account(
+            owner_account.key,
+            entry_account.key,
+            Rent::get()?.minimum_balance(RegistryEntry::LEN),
+            RegistryEntry::LEN as u64,
+            program_id,
+        ),
+        &[owner_account.clone(), entry_account.clone(), system_program.clone()],
+        &[&[REGISTRY_SEED, &index, &[entry_bump]]],
+    )?;
+    registry_snapshot(campaign_account.key, &campaign_data)?
+        .serialize(&mut *entry_account.data.borrow_mut())?;
+
+    campaign_data.is_registered = true;
+    campaign_data.registry_index = registry.campaign_count;
+    campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
+
+    registry.campaign_count += 1;
+    registry.serialize(&mut *registry_account.data.borrow_mut())?;
+
+    msg!(
+        "Registered campaign {} at index {}",
+        campaign_account.key,
+        campaign_data.registry_index
+    );
+    Ok(())
+}
+
+// Permissionless: anyone can refresh a registered campaign's status and claim stats
+fn process_sync_registry_entry(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let campaign_account = next_account_info(account_info_iter)?;
+    let entry_account = next_account_info(account_info_iter)?;
+
+    if campaign_account.owner != program_id || entry_account.owner != program_id {
+        return Err(AirdropError::InvalidRegistryAccount.into());
+    }
+    let campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !campaign_data.is_registered {
+        return Err(AirdropError::InvalidRegistryAccount.into());
+    }
+    let (entry_key, _) = Pubkey::find_program_address(
+        &[REGISTRY_SEED, &campaign_data.registry_index.to_le_bytes()],
+        program_id,
+    );
+    if *entry_account.key != entry_key {
+        return Err(AirdropError::InvalidRegistryAccount.into());
+    }
+
+    registry_snapshot(campaign_account.key, &campaign_data)?
+        .serialize(&mut *entry_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn registry_snapshot(
+    campaign: &Pubkey,
+    campaign_data: &AirdropCampaign,
+) -> Result<RegistryEntry, ProgramError> {
+    let now = solana_program::clock::Clock::get()?.unix_timestamp;
+    let status = if campaign_data.is_active {
+        CampaignStatus::Active
+    } else if campaign_data.is_ended
+        || campaign_data.claimed_count > 0
+        || (campaign_data.end_time != 0 && campaign_data.end_time <= now)
+    {
+        CampaignStatus::Ended
+    } else {
+        CampaignStatus::Pending
+    };
+
+    Ok(RegistryEntry {
+        campaign: *campaign,
+        mint: campaign_data.mint,
+        owner: campaign_data.owner,
+        status,
+        total_amount: campaign_data.total_amount,
+        claimed_count: campaign_data.claimed_count,
+        claimed_amount: campaign_data.claimed_amount,
+        max_recipients: campaign_data.max_recipients,
+        last_synced: now,
+    })
+}
+
+// Zero epoch_duration turns recurrence off; merkle campaigns carry a fixed allocation and can't recur
+fn process_set_recurring(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let campaign_account = next_account_info(account_info_iter)?;
+
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
+        return Err(AirdropError::InvalidCampaignOwner.into());
+    }
+    if campaign_data.is_active || campaign_data.claimed_count > 0 {
+        return Err(AirdropError::CampaignStarted.into());
+    }
+
+    let epoch_duration = i64::try_from_slice(instruction_data)?;
+    if epoch_duration < 0 || (epoch_duration > 0 && campaign_data.is_merkle()) {
+        return Err(AirdropError::InvalidCampaignParams.into());
+    }
+
+    campaign_data.epoch_duration = epoch_duration;
+    campaign_data.current_epoch = 0;
+    campaign_data.epoch_claimed_count = 0;
+    campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Tops up the vault, e.g. with the next epochs' allocation for a recurring campaign
+fn process_fund_campaign(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let campaign_account = next_account_info(account_info_iter)?;
+    let owner_token_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
+        return Err(AirdropError::InvalidCampaignOwner.into());
+    }
+    if *vault_account.key != campaign_data.vault {
+        return Err(AirdropError::InvalidVault.into());
+    }
+
+    if *mint_account.key != campaign_data.mint {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let amount = u64::try_from_slice(instruction_data)?;
+    let received = deposit_to_vault(
+        owner_token_account,
+        vault_account,
+        mint_account,
+        owner_account,
+        token_program,
+        amount,
+    )?;
+
+    campaign_data.total_amount = campaign_data.total_amount
+        .checked_add(received)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
+
+    msg!("Funded campaign with {} tokens", received);
+    Ok(())
+}
+
+fn process_set_campaign_paused(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    paused: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let campaign_account = next_account_info(account_info_iter)?;
+
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
+        return Err(AirdropError::InvalidCampaignOwner.into());
+    }
+
+    campaign_data.is_paused = paused;
+    campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
+
+    msg!("Campaign {}", if paused { "paused" } else { "resumed" });
+    Ok(())
+}
+
+// Leaves then carry holder weights; the pool is the escrowed total at the time of the call
+fn process_set_weighted_distribution(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let campaign_account = next_account_info(account_info_iter)?;
+
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
+        return Err(AirdropError::InvalidCampaignOwner.into());
+    }
+    if campaign_data.is_active || campaign_data.claimed_count > 0 {
+        return Err(AirdropError::CampaignStarted.into());
+    }
+
+    let total_weight = u64::try_from_slice(instruction_data)?;
+    if total_weight > 0 && !campaign_data.is_merkle() {
+        return Err(AirdropError::InvalidCampaignParams.into());
+    }
+
+    campaign_data.total_weight = total_weight;
+    campaign_data.weight_pool = if total_weight > 0 { campaign_data.total_amount } else { 0 };
+    campaign_data.serialize(&mut *campaign_account.data.borrow_mut())?;
+
+    msg!(
+        "Weighted distribution: {} tokens over {} weight",
+        campaign_data.weight_pool,
+        total_weight
+    );
+    Ok(())
+}
+
+// Trailing accounts: the registry entry if the campaign is registered, then any
+// leftover whitelist entries to close alongside (see CloseWhitelistEntries)
+fn process_close_campaign(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let campaign_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let campaign_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
+        return Err(AirdropError::InvalidCampaignOwner.into());
+    }
+    if *vault_account.key != campaign_data.vault {
+        return Err(AirdropError::InvalidVault.into());
+    }
+    // EndAirdrop settles the fee escrow, WithdrawRemainingTokens empties the vault
+    if !campaign_data.is_ended
+        || campaign_data.is_active
+        || campaign_data.escrowed_fee > 0
+        || unpack_token_account(vault_account)?.amount > 0
+    {
+        return Err(AirdropError::CampaignNotSettled.into());
+    }
+
+    // Registered campaigns also pass their registry entry, which is kept but marked closed
+    if campaign_data.is_registered {
+        let entry_account = next_account_info(account_info_iter)?;
+        let (entry_key, _) = Pubkey::find_program_address(
+            &[REGISTRY_SEED, &campaign_data.registry_index.to_le_bytes()],
+            program_id,
+        );
+        if *entry_account.key != entry_key || entry_account.owner != program_id {
+            return Err(AirdropError::InvalidRegistryAccount.into());
+        }
+        let mut entry = registry_snapshot(campaign_account.key, &campaign_data)?;
+        entry.status = CampaignStatus::Closed;
+        entry.serialize(&mut *entry_account.data.borrow_mut())?;
+    }
+
+    let (expected, bump) = Pubkey::find_program_address(
+        &[CAMPAIGN_AUTHORITY_SEED, campaign_account.key.as_ref()],
+        program_id,
+    );
+    if *campaign_authority.key != expected {
+        return Err(AirdropError::InvalidVault.into());
+    }
+    solana_program::program::invoke_signed(
+        &token_instruction::close_account(
+            token_program.key,
+            vault_account.key,
+            owner_account.key,
+            campaign_authority.key,
+            &[],
+        )?,
+        &[
+            vault_account.clone(),
+            owner_account.clone(),
+            campaign_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&[CAMPAIGN_AUTHORITY_SEED, campaign_account.key.as_ref(), &[bump]]],
+    )?;
+
+    let closed =
+        close_whitelist_entries(program_id, campaign_account, owner_account, account_info_iter)?;
+
+    close_program_account(campaign_account, owner_account);
+
+    msg!("Closed campaign and {} whitelist entries", closed);
+    Ok(())
+}
+
+// Sweeps an ended campaign's whitelist entries ahead of CloseCampaign, over as many
+// transactions as needed. Claim records, epoch records and bitmap chunks are left
+// open: their rent came from claimers or the sponsor, not the owner
+fn process_close_whitelist_entries(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let campaign_account = next_account_info(account_info_iter)?;
+
+    if campaign_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let campaign_data = AirdropCampaign::try_from_slice(&campaign_account.data.borrow())?;
+    if !owner_account.is_signer || campaign_data.owner != *owner_account.key {
+        return Err(AirdropError::InvalidCampaignOwner.into());
+    }
+    // Entries are what admit claimers, so only a campaign that can never restart may drop them
+    if !campaign_data.is_ended {
+        return Err(AirdropError::CampaignNotSettled.into());
+    }
+
+    let closed =
+        close_whitelist_entries(program_id, campaign_account, owner_account, account_info_iter)?;
+
+    msg!("Closed {} whitelist entries", closed);
+    Ok(())
+}
+
+// Closes the remaining accounts as the campaign's whitelist entries, whose rent the
+// owner paid when adding them
+fn close_whitelist_entries<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    campaign_account: &AccountInfo,
+    owner_account: &AccountInfo,
+    entries: I,
+) -> Result<u64, ProgramError> {
+    let mut closed = 0u64;
+    for entry_account in entries {
+        if entry_account.owner != program_id {
+            return Err(AirdropError::InvalidClaimAccount.into());
+        }
+        let wallet = WhitelistEntry::try_from_slice(&entry_account.data.borrow())?.wallet;
+        load_whitelist_entry(program_id, campaign_account, entry_account, &wallet)?;
+        close_program_account(entry_account, owner_account);
+        closed += 1;
+    }
+
+    Ok(closed)
+}
+
+fn close_program_account(account: &AccountInfo, destination: &AccountInfo) {
+    let dest_starting_lamports = destination.lamports();
+    **destination.lamports.borrow_mut() = dest_starting_lamports
+        .checked_add(account.lamports())
+        .unwrap();
+    **account.lamports.borrow_mut() = 0;
+    account.data.borrow_mut().fill(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Program id passed to declare_id! in a workspace program's source
+    fn declared_id(source: &str) -> Pubkey {
+        let start = source.find("declare_id!(\"").unwrap() + "declare_id!(\"".len();
+        let end = start + source[start..].find('"').unwrap();
+        source[start..end].parse().unwrap()
+    }
+
+    #[test]
+    fn staking_program_id_matches_the_staking_program() {
+        let staking_source = include_str!("../../staking/src/lib.rs");
+        assert_eq!(STAKING_PROGRAM_ID, declared_id(staking_source));
+    }
 }
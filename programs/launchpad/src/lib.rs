@@ -4,8 +4,12 @@ use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint,
     entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    keccak,
     msg,
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
+    program_pack::Pack,
     pubkey::Pubkey,
     system_instruction,
     sysvar::{rent::Rent, Sysvar},
@@ -14,12 +18,42 @@ use spl_token::instruction as token_instruction;
 use thiserror::Error;
 
 // Program ID and Fee Wallet
-solana_program::declare_id!("Launchpad11111111111111111111111111111111111");
+solana_program::declare_id!("Launchpad1111111111111111111111111111111111");
 pub const FEE_WALLET: &str = "6zkf4DviZZkpWVEh53MrcQV6vGXGpESnNXgAvU6KpBUH";
 
 // Launchpad fees in lamports
 pub const LAUNCH_BASE_FEE: u64 = 1_000_000_000;  // 1 SOL
 pub const TIER_FEE: u64 = 500_000_000;          // 0.5 SOL per tier
+pub const RAISE_FEE_BPS: u16 = 200;             // 2% of the filled raise
+
+// Workspace liquidity-pool program that receives launch liquidity
+pub const LIQUIDITY_POOL_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("LiquidityPoo1111111111111111111111111111111");
+pub const LAUNCHPAD_AUTHORITY_SEED: &[u8] = b"launchpad_authority";
+pub const LP_LOCK_SEED: &[u8] = b"lp_lock";
+pub const REFERRAL_SEED: &[u8] = b"referral";
+pub const PARTICIPANT_SEED: &[u8] = b"participant";
+pub const WHITELIST_SEED: &[u8] = b"whitelist";
+pub const LOTTERY_TICKET_SEED: &[u8] = b"lottery_ticket";
+pub const MAX_WHITELIST_BATCH: usize = 20;
+
+// Time participants have to reclaim contributions after a cancellation
+pub const CANCEL_REFUND_WINDOW: i64 = 30 * 24 * 60 * 60;
+
+// Time the owner has to reveal the lottery secret before anyone may draw without it
+pub const LOTTERY_REVEAL_WINDOW: i64 = 24 * 60 * 60;
+
+// Slots between locking the lottery and the slot whose hash seeds it
+pub const LOTTERY_DRAW_DELAY_SLOTS: u64 = 32;
+
+// Workspace staking program whose stake positions qualify participants for tiers
+pub const STAKING_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("StakingPoo111111111111111111111111111111111");
+pub const USER_STAKE_SEED: &[u8] = b"user_stake";
+
+// Workspace token-verification program consulted when a launch requires it
+pub const TOKEN_VERIFICATION_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("TokenVerify11111111111111111111111111111111");
 
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct LaunchpadConfig {
@@ -40,12 +74,294 @@ pub struct LaunchpadConfig {
     pub total_sold: u64,
     pub total_raised: u64,
     pub tier_system: TierSystem,
+    pub vesting: VestingSchedule,
+    pub liquidity_lock_duration: i64, // Seconds LP tokens stay locked after finalize
+    pub is_finalized: bool,
+    pub whitelist_root: [u8; 32], // Merkle root of whitelisted wallets; all zeroes disables the whitelist
+    pub payment_mint: Pubkey,     // Contribution mint; the default pubkey means native SOL
+    pub payment_vault: Pubkey,    // Launchpad-authority token account holding SPL contributions
+    pub sale_mode: SaleMode,
+    pub excess_refunded: u64,     // Fair launch: unfilled contributions already returned
+    pub auction: DutchAuction,
+    pub token_decimals: u8,       // Read from the mint at creation
+    pub token_vault: Pubkey,      // Launchpad-authority token account escrowing the presale tokens
+    pub anti_bot: AntiBot,
+    pub emergency_withdraw_penalty_bps: u16, // Kept in the raise when a participant exits early
+    pub referral_bps: u16,           // Share of the filled raise paid out to referrers
+    pub total_referred_volume: u64,
+    pub referral_paid: u64,
+    pub rounds: Vec<SaleRound>,      // Optional seed / private / public rounds, in order
+    pub tranches: Vec<ReleaseTranche>, // When set, replaces the vesting schedule
+    pub pending_owner: Pubkey,       // Proposed new owner; default pubkey when none
+    pub raise_vesting_duration: i64, // Seconds after end_time over which the owner's funds unlock; zero releases at once
+    pub owner_withdrawn: u64,
+    pub curve: BondingCurve,
+    pub whitelist_entry_count: u64, // Wallets whitelisted through entry accounts
+    pub is_cancelled: bool,
+    pub refund_deadline: i64,        // After a cancellation, when the owner may sweep what is left
+    pub total_refunded: u64,
+    pub penalty_collected: u64,      // Emergency-withdraw penalties, never refundable
+    pub participant_count: u64,
+    pub raise_fee_bps: u16,          // Platform share of the raise, fixed at creation
+    pub raise_fee_paid: bool,
+    pub require_verified_token: bool, // Reject creation unless the mint is verified
+    pub lottery: Lottery,
+}
+
+impl LaunchpadConfig {
+    pub fn is_spl_raise(&self) -> bool {
+        self.payment_mint != Pubkey::default()
+    }
+
+    pub fn is_fair_launch(&self) -> bool {
+        self.sale_mode == SaleMode::FairLaunch
+    }
+
+    // Portion of the raise kept by the project. Fair-launch and overflow sales
+    // accept any amount and keep up to hard_cap (zero keeps everything); the
+    // rest is refunded.
+    pub fn filled_raise(&self) -> u64 {
+        if matches!(self.sale_mode, SaleMode::FairLaunch | SaleMode::Overflow) && self.hard_cap > 0 {
+            self.total_raised.min(self.hard_cap)
+        } else {
+            self.total_raised
+        }
+    }
+
+    // The schedule decides when a sale is over; closing early (sell-out,
+    // curve completion, cancellation) moves end_time forward
+    pub fn has_ended(&self, time: i64) -> bool {
+        time >= self.end_time
+    }
+
+    // Auction price per whole token at `time`, decaying linearly over the sale window
+    pub fn auction_price(&self, time: i64) -> u64 {
+        let duration = self.end_time.saturating_sub(self.start_time);
+        let elapsed = time.clamp(self.start_time, self.end_time) - self.start_time;
+        if duration <= 0 {
+            return self.auction.floor_price;
+        }
+        let decay = (self.auction.start_price - self.auction.floor_price) as u128 * elapsed as u128
+            / duration as u128;
+        self.auction.start_price - decay as u64
+    }
+
+    // Uniform price paid by every auction participant. Without a recorded
+    // sell-out, the price crossed the raise per token between bids, or never did.
+    pub fn clearing_price(&self) -> Result<u64, ProgramError> {
+        if self.auction.clearing_price > 0 {
+            return Ok(self.auction.clearing_price);
+        }
+        let implied = (self.total_raised as u128)
+            .checked_mul(10u128.pow(self.token_decimals as u32))
+            .and_then(|scaled| scaled.checked_div(self.tokens_for_presale as u128))
+            .ok_or(LaunchpadError::InvalidAmount)? as u64;
+        Ok(implied.max(self.auction.floor_price))
+    }
+
+    // Index of the round whose schedule covers `time`
+    pub fn active_round(&self, time: i64) -> Option<usize> {
+        self.rounds
+            .iter()
+            .position(|round| time >= round.start_time && time < round.end_time)
+    }
+
+    // Live (quote, token) reserves of the bonding curve
+    pub fn curve_reserves(&self) -> (u128, u128) {
+        (
+            self.curve.virtual_quote_reserve as u128 + self.total_raised as u128,
+            self.curve.virtual_token_reserve as u128 - self.total_sold as u128,
+        )
+    }
+
+    // Referral rewards owed across all referrers once the sale succeeds
+    pub fn referral_pool(&self) -> u64 {
+        if self.total_referred_volume == 0 {
+            return 0;
+        }
+        (self.filled_raise() as u128)
+            .checked_mul(self.referral_bps as u128)
+            .unwrap()
+            .checked_div(10_000)
+            .unwrap() as u64
+    }
+
+    // Platform fee on the filled raise
+    pub fn raise_fee(&self) -> u64 {
+        (self.filled_raise() as u128)
+            .checked_mul(self.raise_fee_bps as u128)
+            .unwrap()
+            .checked_div(10_000)
+            .unwrap() as u64
+    }
+
+    // Funds held back from the owner: unfilled contributions still waiting to
+    // be returned, referral rewards not yet claimed and the unpaid platform fee
+    pub fn reserved_funds(&self) -> u64 {
+        self.total_raised
+            .checked_sub(self.filled_raise())
+            .unwrap()
+            .saturating_sub(self.excess_refunded)
+            .checked_add(self.referral_pool().saturating_sub(self.referral_paid))
+            .unwrap()
+            .checked_add(if self.raise_fee_paid { 0 } else { self.raise_fee() })
+            .unwrap()
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq)]
+pub enum SalePhase {
+    Upcoming,
+    Active,
+    Ended,     // Closed and successful, awaiting finalize or claims
+    Failed,    // Closed below the soft cap; refunds open
+    Finalized,
+    Cancelled,
+}
+
+// Returned by GetSaleStats through return data
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct SaleStats {
+    pub total_raised: u64,
+    pub total_sold: u64,
+    pub participant_count: u64,
+    pub remaining_allocation: u64,
+    pub phase: SalePhase,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq)]
+pub enum SaleMode {
+    FixedPrice, // Tokens priced at hard_cap / tokens_for_presale, capped at hard_cap
+    FairLaunch, // No contribution cap; tokens split pro-rata to the total raised
+    DutchAuction, // Price decays over the window; everyone pays the clearing price
+    Overflow,   // Fixed price, oversubscription allowed; fills are pro-rata up to hard_cap
+    BondingCurve, // Continuous constant-product sale, migrated to the AMM at a target market cap
+}
+
+// Constant-product curve over virtual reserves. The live reserves are the
+// virtual ones plus the raise on the quote side and minus tokens sold.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct BondingCurve {
+    pub virtual_quote_reserve: u64,
+    pub virtual_token_reserve: u64,
+    pub target_market_cap: u64, // Quote units for the whole total_supply at the curve price
+}
+
+// One stage of a multi-round sale; prices are quote units per whole token
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct SaleRound {
+    pub name: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub price_per_token: u64,
+    pub min_buy: u64,
+    pub max_buy: u64,
+    pub hard_cap: u64,
+    pub whitelist_required: bool,
+    pub raised: u64,
+}
+
+// Allocation lottery for lowest-tier wallets. The owner commits to a secret
+// at creation and reveals it after registration, which locks the draw to a
+// slot LOTTERY_DRAW_DELAY_SLOTS ahead; that slot's hash, unknown when the lock
+// is taken, seeds a contiguous run of winning tickets. If the owner has not
+// revealed within LOTTERY_REVEAL_WINDOW, anyone can lock the draw without a
+// secret.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct Lottery {
+    pub enabled: bool,
+    pub registration_end: i64,
+    pub winner_count: u64,
+    pub commitment: [u8; 32], // keccak of the owner's secret
+    pub registrant_count: u64,
+    pub seed: [u8; 32],
+    pub drawn: bool,
+    pub target_slot: u64, // slot whose hash seeds the draw, 0 until locked
+    pub secret: [u8; 32], // revealed secret, zero when locked without one
+}
+
+impl Lottery {
+    // Tickets in [offset, offset + winner_count) modulo the registrant count win
+    pub fn is_winner(&self, ticket_index: u64) -> bool {
+        if self.winner_count >= self.registrant_count {
+            return true;
+        }
+        let offset = u64::from_le_bytes(self.seed[..8].try_into().unwrap()) % self.registrant_count;
+        let position = (ticket_index + self.registrant_count - offset) % self.registrant_count;
+        position < self.winner_count
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct LotteryTicket {
+    pub launchpad: Pubkey,
+    pub wallet: Pubkey,
+    pub index: u64,
+}
+
+impl LotteryTicket {
+    pub const LEN: usize = 32 + 32 + 8;
+}
+
+// Participation limits aimed at sniping bots; zero disables a limit
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct AntiBot {
+    pub public_delay: i64,         // Seconds after start_time reserved for whitelisted wallets
+    pub max_per_transaction: u64,
+    pub max_per_slot: u64,         // Total contributions accepted within one slot
+    pub last_slot: u64,
+    pub last_slot_contributed: u64,
+}
+
+// Prices are quote units per whole token, like listing_price
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct DutchAuction {
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub clearing_price: u64, // Set when a bid completes the supply; zero until then
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct ParticipateArgs {
+    pub amount: u64,
+    pub proof: Vec<[u8; 32]>, // Merkle proof of the participant's wallet when a whitelist is set
+    pub referrer: Option<Pubkey>, // Only recorded on the wallet's first contribution
+}
+
+// Sale parameters the owner may still correct before anyone has contributed
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct UpdateLaunchpadArgs {
+    pub price_per_token: u64,
+    pub min_buy: u64,
+    pub max_buy: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub soft_cap: u64,
+    pub hard_cap: u64,
+    pub liquidity_percentage: u8,
+    pub listing_price: u64,
+}
+
+// Release schedule for purchased tokens, measured from the end of the sale
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct VestingSchedule {
+    pub tge_percent: u8,      // Share unlocked immediately at the end of the sale
+    pub cliff_duration: i64,  // Seconds after the sale before linear release begins
+    pub vesting_duration: i64, // Seconds over which the remainder unlocks linearly
+}
+
+// Share of the purchased tokens that unlocks at a fixed timestamp
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct ReleaseTranche {
+    pub unlock_time: i64,
+    pub bps: u16,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct TierSystem {
     pub enabled: bool,
     pub tiers: Vec<Tier>,
+    pub stake_pool: Pubkey,  // Staking pool whose positions qualify for stake-based tiers
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
@@ -56,6 +372,53 @@ pub struct Tier {
     pub vesting_period: i64,
 }
 
+// Leading fields of the staking program's per-user stake record
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct UserStakeInfo {
+    pub owner: Pubkey,
+    pub stake_amount: u64,
+    pub rewards_earned: u64,
+    pub reward_per_token_paid: u128,
+    pub start_time: i64,
+    pub lock_period: i64,
+}
+
+// Leading fields of the token-verification program's TokenVerificationInfo;
+// the social links and metrics that follow are not needed here
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct TokenVerificationStatus {
+    pub mint_address: Pubkey,
+    pub owner: Pubkey,
+    pub is_verified: bool,
+}
+
+// Leading fields of the liquidity-pool program's PoolState, up to the reserves
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct AmmPoolReserves {
+    pub is_initialized: bool,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub token_a_account: Pubkey,
+    pub token_b_account: Pubkey,
+    pub pool_mint: Pubkey,
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+}
+
+// Time-locked escrow for the LP tokens minted at finalize
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct LiquidityLock {
+    pub launchpad: Pubkey,
+    pub beneficiary: Pubkey,
+    pub lp_mint: Pubkey,
+    pub escrow: Pubkey,
+    pub unlock_time: i64,
+}
+
+impl LiquidityLock {
+    pub const LEN: usize = 32 + 32 + 32 + 32 + 8;
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct Participant {
     pub wallet: Pubkey,
@@ -64,6 +427,44 @@ pub struct Participant {
     pub tokens_claimed: u64,
     pub tier: u8,
     pub last_claim_time: i64,
+    pub allocation_settled: bool, // Fair launch: pro-rata allocation and refund applied
+    pub referrer: Pubkey,         // Default pubkey when the wallet was not referred
+}
+
+// Whitelisted wallet at [WHITELIST_SEED, launchpad, wallet]; the tier is
+// assigned by the owner and used instead of a staking lookup
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct WhitelistEntry {
+    pub launchpad: Pubkey,
+    pub wallet: Pubkey,
+    pub tier: u8,
+}
+
+impl WhitelistEntry {
+    pub const LEN: usize = 32 + 32 + 1;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct WhitelistBatchEntry {
+    pub wallet: Pubkey,
+    pub tier: u8,
+}
+
+impl Participant {
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 1 + 8 + 1 + 32;
+}
+
+// Volume a referrer brought into one launchpad, at [REFERRAL_SEED, launchpad, referrer]
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct ReferralRecord {
+    pub launchpad: Pubkey,
+    pub referrer: Pubkey,
+    pub referred_volume: u64,
+    pub claimed: bool,
+}
+
+impl ReferralRecord {
+    pub const LEN: usize = 32 + 32 + 8 + 1;
 }
 
 #[derive(FromPrimitive, Debug)]
@@ -78,6 +479,20 @@ pub enum LaunchpadInstruction {
     CancelLaunch,
     AddToWhitelist,
     RemoveFromWhitelist,
+    Refund,
+    Finalize,
+    WithdrawLockedLiquidity,
+    UpdateLaunchpadConfig,
+    EmergencyWithdraw,
+    ClaimReferralReward,
+    ClaimRefundExcess,
+    ProposeOwner,
+    AcceptOwner,
+    AddToWhitelistBatch,
+    SweepCancelledLaunch,
+    GetSaleStats,
+    RegisterForLottery,
+    DrawLottery,
 }
 
 #[derive(Error, Debug, Copy, Clone)]
@@ -104,6 +519,54 @@ pub enum LaunchpadError {
     InvalidTier,
     #[error("Vesting period not ended")]
     VestingPeriodNotEnded,
+    #[error("Invalid vesting schedule")]
+    InvalidVestingSchedule,
+    #[error("Nothing to claim")]
+    NothingToClaim,
+    #[error("Presale still running")]
+    PresaleStillActive,
+    #[error("Soft cap reached")]
+    SoftCapReached,
+    #[error("Launch already finalized")]
+    AlreadyFinalized,
+    #[error("Liquidity has not been added")]
+    LiquidityNotAdded,
+    #[error("Liquidity is still locked")]
+    LiquidityLocked,
+    #[error("Invalid payment account")]
+    InvalidPaymentAccount,
+    #[error("Invalid auction prices")]
+    InvalidAuctionPrice,
+    #[error("Contribution limit for this slot reached")]
+    SlotCapReached,
+    #[error("Invalid referrer")]
+    InvalidReferrer,
+    #[error("Invalid sale round")]
+    InvalidRound,
+    #[error("Invalid bonding curve")]
+    InvalidCurve,
+    #[error("Launch cancelled")]
+    LaunchCancelled,
+    #[error("Refunds still pending")]
+    RefundsPending,
+    #[error("Token is not verified")]
+    TokenNotVerified,
+    #[error("Lottery registration closed")]
+    RegistrationClosed,
+    #[error("Lottery not drawn")]
+    LotteryNotDrawn,
+    #[error("Not a lottery winner")]
+    NotLotteryWinner,
+    #[error("Secret does not match the lottery commitment")]
+    InvalidReveal,
+    #[error("Raise funds already withdrawn")]
+    FundsWithdrawn,
+    #[error("Lottery draw slot not reached")]
+    LotteryNotReady,
+    #[error("Launchpad already exists")]
+    LaunchpadAlreadyExists,
+    #[error("Liquidity pool must be empty before migration")]
+    PoolNotEmpty,
 }
 
 impl From<LaunchpadError> for ProgramError {
@@ -153,6 +616,48 @@ pub fn process_instruction(
         LaunchpadInstruction::RemoveFromWhitelist => {
             process_remove_from_whitelist(program_id, accounts)
         }
+        LaunchpadInstruction::Refund => {
+            process_refund(program_id, accounts)
+        }
+        LaunchpadInstruction::Finalize => {
+            process_finalize(program_id, accounts)
+        }
+        LaunchpadInstruction::WithdrawLockedLiquidity => {
+            process_withdraw_locked_liquidity(program_id, accounts)
+        }
+        LaunchpadInstruction::UpdateLaunchpadConfig => {
+            process_update_launchpad_config(program_id, accounts, &instruction_data[1..])
+        }
+        LaunchpadInstruction::EmergencyWithdraw => {
+            process_emergency_withdraw(program_id, accounts)
+        }
+        LaunchpadInstruction::ClaimReferralReward => {
+            process_claim_referral_reward(program_id, accounts)
+        }
+        LaunchpadInstruction::ClaimRefundExcess => {
+            process_claim_refund_excess(program_id, accounts)
+        }
+        LaunchpadInstruction::ProposeOwner => {
+            process_propose_owner(program_id, accounts, &instruction_data[1..])
+        }
+        LaunchpadInstruction::AcceptOwner => {
+            process_accept_owner(program_id, accounts)
+        }
+        LaunchpadInstruction::AddToWhitelistBatch => {
+            process_add_to_whitelist_batch(program_id, accounts, &instruction_data[1..])
+        }
+        LaunchpadInstruction::SweepCancelledLaunch => {
+            process_sweep_cancelled_launch(program_id, accounts)
+        }
+        LaunchpadInstruction::GetSaleStats => {
+            process_get_sale_stats(program_id, accounts)
+        }
+        LaunchpadInstruction::RegisterForLottery => {
+            process_register_for_lottery(program_id, accounts)
+        }
+        LaunchpadInstruction::DrawLottery => {
+            process_draw_lottery(program_id, accounts, &instruction_data[1..])
+        }
     }
 }
 
@@ -167,13 +672,108 @@ fn process_create_launchpad(
     let mint_account = next_account_info(account_info_iter)?;
     let fee_wallet = next_account_info(account_info_iter)?;
     let system_program = next_account_info(account_info_iter)?;
+    let owner_token_account = next_account_info(account_info_iter)?;
+    let token_vault = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
 
     // Verify fee wallet
     if fee_wallet.key.to_string() != FEE_WALLET {
         return Err(ProgramError::InvalidArgument);
     }
 
-    let config = LaunchpadConfig::try_from_slice(instruction_data)?;
+    // The launchpad account must be a fresh, program-owned allocation; a live launchpad
+    // always has a non-zero owner, so any written byte means it is already in use
+    if launchpad_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if launchpad_account.data.borrow().iter().any(|byte| *byte != 0) {
+        return Err(LaunchpadError::LaunchpadAlreadyExists.into());
+    }
+
+    let mut config = LaunchpadConfig::try_from_slice(instruction_data)?;
+    validate_config(&config)?;
+    for round in config.rounds.iter_mut() {
+        round.raised = 0;
+    }
+    config.is_active = false;
+    config.total_sold = 0;
+    config.total_raised = 0;
+    config.is_finalized = false;
+    config.excess_refunded = 0;
+    config.owner_withdrawn = 0;
+    config.pending_owner = Pubkey::default();
+    config.whitelist_entry_count = 0;
+    config.is_cancelled = false;
+    config.refund_deadline = 0;
+    config.total_refunded = 0;
+    config.penalty_collected = 0;
+    config.participant_count = 0;
+    config.raise_fee_bps = RAISE_FEE_BPS;
+    config.raise_fee_paid = false;
+    config.lottery.registrant_count = 0;
+    config.lottery.seed = [0u8; 32];
+    config.lottery.drawn = false;
+    config.lottery.target_slot = 0;
+    config.lottery.secret = [0u8; 32];
+    config.total_referred_volume = 0;
+    config.referral_paid = 0;
+    config.anti_bot.last_slot = 0;
+    config.anti_bot.last_slot_contributed = 0;
+    config.token_decimals = spl_token::state::Mint::unpack(&mint_account.data.borrow())?.decimals;
+
+    let (launchpad_authority, _) = Pubkey::find_program_address(
+        &[LAUNCHPAD_AUTHORITY_SEED, launchpad_account.key.as_ref()],
+        program_id,
+    );
+
+    // Presale tokens are escrowed up front so claims never need the owner
+    let vault = spl_token::state::Account::unpack(&token_vault.data.borrow())?;
+    if vault.owner != launchpad_authority || vault.mint != *mint_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    config.mint = *mint_account.key;
+    config.token_vault = *token_vault.key;
+    invoke(
+        &token_instruction::transfer(
+            token_program.key,
+            owner_token_account.key,
+            token_vault.key,
+            owner_account.key,
+            &[],
+            config.tokens_for_presale,
+        )?,
+        &[
+            owner_token_account.clone(),
+            token_vault.clone(),
+            owner_account.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    // SPL raises collect into a vault controlled by the launchpad authority PDA
+    if config.is_spl_raise() {
+        let payment_vault = next_account_info(account_info_iter)?;
+        let vault = spl_token::state::Account::unpack(&payment_vault.data.borrow())?;
+        if *payment_vault.key != config.payment_vault
+            || vault.owner != launchpad_authority
+            || vault.mint != config.payment_mint
+        {
+            return Err(LaunchpadError::InvalidPaymentAccount.into());
+        }
+    }
+
+    if config.require_verified_token {
+        let verification_account = next_account_info(account_info_iter)?;
+        if *verification_account.owner != TOKEN_VERIFICATION_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let status =
+            TokenVerificationStatus::deserialize(&mut &verification_account.data.borrow()[..])?;
+        if status.mint_address != *mint_account.key || !status.is_verified {
+            return Err(LaunchpadError::TokenNotVerified.into());
+        }
+    }
+
     let tier_count = if config.tier_system.enabled {
         config.tier_system.tiers.len() as u64
     } else {
@@ -201,6 +801,84 @@ fn process_create_launchpad(
     Ok(())
 }
 
+// Sale parameters shared by CreateLaunchpad and UpdateLaunchpadConfig
+fn validate_config(config: &LaunchpadConfig) -> ProgramResult {
+    if config.vesting.tge_percent > 100
+        || config.vesting.cliff_duration < 0
+        || config.vesting.vesting_duration < 0
+    {
+        return Err(LaunchpadError::InvalidVestingSchedule.into());
+    }
+    if !config.tranches.is_empty() {
+        let ordered = config
+            .tranches
+            .windows(2)
+            .all(|pair| pair[0].unlock_time < pair[1].unlock_time);
+        let total_bps: u64 = config.tranches.iter().map(|tranche| tranche.bps as u64).sum();
+        if !ordered || total_bps != 10_000 {
+            return Err(LaunchpadError::InvalidVestingSchedule.into());
+        }
+    }
+    if config.tokens_for_presale == 0
+        || config.liquidity_percentage > 100
+        || config.liquidity_lock_duration < 0
+        || config.liquidity_percentage as u64 * 100
+            + config.referral_bps as u64
+            + RAISE_FEE_BPS as u64
+            > 10_000
+    {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+    if config.anti_bot.public_delay < 0 || config.emergency_withdraw_penalty_bps > 10_000 {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+    let mut previous_end = i64::MIN;
+    for round in config.rounds.iter() {
+        if round.start_time < previous_end
+            || round.start_time >= round.end_time
+            || round.price_per_token == 0
+            || round.min_buy > round.max_buy
+        {
+            return Err(LaunchpadError::InvalidRound.into());
+        }
+        previous_end = round.end_time;
+    }
+    if !config.rounds.is_empty() && config.sale_mode != SaleMode::FixedPrice {
+        return Err(LaunchpadError::InvalidRound.into());
+    }
+    if config.sale_mode == SaleMode::Overflow && config.hard_cap == 0 {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+    // Curve sales pay out tokens on every buy and cannot fail, so there is no
+    // soft cap; the virtual token reserve must outlast the presale allocation
+    if config.sale_mode == SaleMode::BondingCurve
+        && (config.is_spl_raise()
+            || config.soft_cap != 0
+            || config.curve.virtual_quote_reserve == 0
+            || config.curve.virtual_token_reserve <= config.tokens_for_presale
+            || config.curve.target_market_cap == 0)
+    {
+        return Err(LaunchpadError::InvalidCurve.into());
+    }
+    if config.raise_vesting_duration < 0 {
+        return Err(LaunchpadError::InvalidVestingSchedule.into());
+    }
+    if config.lottery.enabled
+        && (config.lottery.winner_count == 0 || config.lottery.registration_end > config.start_time)
+    {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+    if config.sale_mode == SaleMode::DutchAuction
+        && (config.auction.floor_price == 0
+            || config.auction.start_price < config.auction.floor_price
+            || config.auction.clearing_price != 0)
+    {
+        return Err(LaunchpadError::InvalidAuctionPrice.into());
+    }
+
+    Ok(())
+}
+
 fn process_configure_tiers(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -210,11 +888,24 @@ fn process_configure_tiers(
     let owner_account = next_account_info(account_info_iter)?;
     let launchpad_account = next_account_info(account_info_iter)?;
 
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if launchpad_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
     let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
     if config.owner != *owner_account.key {
         return Err(LaunchpadError::InvalidOwner.into());
     }
 
+    // Participants hold indexes into the tier list and the stake pool decides
+    // their tier, so both are frozen once the sale has opened
+    if config.is_active || config.total_raised > 0 || config.is_finalized || config.is_cancelled {
+        return Err(LaunchpadError::PresaleStillActive.into());
+    }
+
     let tier_system = TierSystem::try_from_slice(instruction_data)?;
     config.tier_system = tier_system;
     config.serialize(&mut *launchpad_account.data.borrow_mut())?;
@@ -234,9 +925,15 @@ fn process_start_presale(
     if config.owner != *owner_account.key {
         return Err(LaunchpadError::InvalidOwner.into());
     }
+    if config.is_cancelled {
+        return Err(LaunchpadError::LaunchCancelled.into());
+    }
 
+    // Opens the sale; contributions are still bounded by start_time and end_time
+    if config.has_ended(solana_program::clock::Clock::get()?.unix_timestamp) {
+        return Err(LaunchpadError::PresaleEnded.into());
+    }
     config.is_active = true;
-    config.start_time = solana_program::clock::Clock::get()?.unix_timestamp;
     config.serialize(&mut *launchpad_account.data.borrow_mut())?;
 
     Ok(())
@@ -253,65 +950,572 @@ fn process_participate(
     let participant_info_account = next_account_info(account_info_iter)?;
     let system_program = next_account_info(account_info_iter)?;
 
+    if !participant_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let participant_bump = check_participant_account(
+        program_id,
+        launchpad_account.key,
+        participant_account.key,
+        participant_info_account,
+    )?;
+
     let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
-    if !config.is_active {
+    let clock = solana_program::clock::Clock::get()?;
+    if !config.is_active || clock.unix_timestamp < config.start_time {
         return Err(LaunchpadError::PresaleNotActive.into());
     }
-
-    let amount = u64::try_from_slice(instruction_data)?;
-    if amount < config.min_buy || amount > config.max_buy {
-        return Err(LaunchpadError::InvalidAmount.into());
+    if config.has_ended(clock.unix_timestamp) {
+        return Err(LaunchpadError::PresaleEnded.into());
     }
 
-    if config.total_raised.checked_add(amount).unwrap() > config.hard_cap {
-        return Err(LaunchpadError::HardCapReached.into());
+    let args = ParticipateArgs::try_from_slice(instruction_data)?;
+
+    // Multi-round sales take contributions only inside a round's schedule
+    let round_index = if config.rounds.is_empty() {
+        None
+    } else {
+        Some(config.active_round(clock.unix_timestamp).ok_or(LaunchpadError::PresaleNotActive)?)
+    };
+
+    // With a public delay the whitelist only grants early access; without one
+    // it gates the whole sale. Rounds decide for themselves.
+    let public_open = if let Some(index) = round_index {
+        !config.rounds[index].whitelist_required
+    } else if config.anti_bot.public_delay > 0 {
+        clock.unix_timestamp >= config.start_time.saturating_add(config.anti_bot.public_delay)
+    } else {
+        config.whitelist_root == [0u8; 32] && config.whitelist_entry_count == 0
+    };
+
+    // Gated wallets prove membership with a merkle proof, or without one pass
+    // their whitelist entry as the next account
+    let mut entry_tier = None;
+    if !public_open {
+        let whitelisted = if args.proof.is_empty() {
+            let whitelist_account = next_account_info(account_info_iter)?;
+            let entry = load_whitelist_entry(
+                program_id,
+                launchpad_account.key,
+                participant_account.key,
+                whitelist_account,
+            )?;
+            entry_tier = Some(entry.tier);
+            true
+        } else {
+            config.whitelist_root != [0u8; 32]
+                && verify_whitelist_proof(&config.whitelist_root, participant_account.key, &args.proof)
+        };
+        if !whitelisted {
+            return Err(LaunchpadError::NotWhitelisted.into());
+        }
     }
 
-    // Transfer SOL to launchpad account
-    solana_program::program::invoke(
-        &system_instruction::transfer(
-            participant_account.key,
-            launchpad_account.key,
-            amount,
-        ),
-        &[
-            participant_account.clone(),
-            launchpad_account.clone(),
-            system_program.clone(),
-        ],
-    )?;
+    // With tiers enabled, the whitelist entry or the participant's stake decides
+    // the tier and how far the allocation multiplier raises max_buy
+    let (min_buy, mut max_buy) = match round_index {
+        Some(index) => (config.rounds[index].min_buy, config.rounds[index].max_buy),
+        None => (config.min_buy, config.max_buy),
+    };
+    let mut tier = 0;
+    if config.tier_system.enabled {
+        tier = match entry_tier {
+            Some(entry_tier) if (entry_tier as usize) < config.tier_system.tiers.len() => entry_tier,
+            Some(_) => return Err(LaunchpadError::InvalidTier.into()),
+            None => {
+                let stake_pool = next_account_info(account_info_iter)?;
+                let user_stake_account = next_account_info(account_info_iter)?;
+                staked_tier(&config, participant_account.key, stake_pool, user_stake_account)?
+            }
+        };
+        max_buy = max_buy
+            .checked_mul(config.tier_system.tiers[tier as usize].allocation_multiplier as u64)
+            .unwrap();
+    }
 
-    let tokens_amount = amount
-        .checked_mul(config.tokens_for_presale)
-        .unwrap()
-        .checked_div(config.hard_cap)
-        .unwrap();
+    // Lowest-tier wallets may only buy with a winning lottery ticket
+    if config.lottery.enabled && tier == 0 {
+        let ticket_account = next_account_info(account_info_iter)?;
+        if !config.lottery.drawn {
+            return Err(LaunchpadError::LotteryNotDrawn.into());
+        }
+        let (expected_ticket, _) = Pubkey::find_program_address(
+            &[LOTTERY_TICKET_SEED, launchpad_account.key.as_ref(), participant_account.key.as_ref()],
+            program_id,
+        );
+        if expected_ticket != *ticket_account.key || ticket_account.owner != program_id {
+            return Err(LaunchpadError::NotLotteryWinner.into());
+        }
+        let ticket = LotteryTicket::try_from_slice(&ticket_account.data.borrow())?;
+        if !config.lottery.is_winner(ticket.index) {
+            return Err(LaunchpadError::NotLotteryWinner.into());
+        }
+    }
 
-    let mut participant_info = if participant_info_account.data_is_empty() {
-        Participant {
-            wallet: *participant_account.key,
-            amount_contributed: amount,
-            tokens_owed: tokens_amount,
-            tokens_claimed: 0,
-            tier: 0,
-            last_claim_time: 0,
+    // A wallet keeps the referrer it joined with; its record is the next account
+    let existing_referrer = if participant_info_account.data_is_empty() {
+        None
+    } else {
+        Some(Participant::try_from_slice(&participant_info_account.data.borrow())?.referrer)
+    };
+    let referrer = match (existing_referrer, args.referrer) {
+        (Some(referrer), _) => referrer,
+        (None, Some(referrer)) if referrer == *participant_account.key => {
+            return Err(LaunchpadError::InvalidReferrer.into());
         }
+        (None, Some(referrer)) => referrer,
+        (None, None) => Pubkey::default(),
+    };
+    let referral_account = if referrer != Pubkey::default() && config.referral_bps > 0 {
+        Some(next_account_info(account_info_iter)?)
     } else {
-        let mut info = Participant::try_from_slice(&participant_info_account.data.borrow())?;
-        info.amount_contributed = info.amount_contributed.checked_add(amount).unwrap();
-        info.tokens_owed = info.tokens_owed.checked_add(tokens_amount).unwrap();
-        info
+        None
     };
 
-    participant_info.serialize(&mut *participant_info_account.data.borrow_mut())?;
+    let mut amount = args.amount;
+    if amount < min_buy || amount > max_buy {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+    if config.anti_bot.max_per_transaction > 0 && amount > config.anti_bot.max_per_transaction {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
 
-    config.total_raised = config.total_raised.checked_add(amount).unwrap();
-    config.total_sold = config.total_sold.checked_add(tokens_amount).unwrap();
-    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+    // max_buy (tier-adjusted) caps the wallet's total contribution, not each purchase
+    if !participant_info_account.data_is_empty() {
+        let contributed = Participant::try_from_slice(&participant_info_account.data.borrow())?
+            .amount_contributed;
+        if contributed.checked_add(amount).ok_or(LaunchpadError::InvalidAmount)? > max_buy {
+            return Err(LaunchpadError::InvalidAmount.into());
+        }
+    }
+
+    // Auction: once the raise buys the whole supply at the current price the
+    // sale clears there, and the final bid is trimmed to what completes it
+    if config.sale_mode == SaleMode::DutchAuction {
+        let current_time = clock.unix_timestamp;
+        let price = config.auction_price(current_time);
+        let sellout = (config.tokens_for_presale as u128)
+            .checked_mul(price as u128)
+            .unwrap()
+            .checked_div(10u128.pow(config.token_decimals as u32))
+            .unwrap() as u64;
+        let remaining = sellout.saturating_sub(config.total_raised);
+        if remaining == 0 {
+            // The decaying price already met the raise; close at the implied price
+            config.is_active = false;
+            config.end_time = current_time;
+            config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+            msg!("Auction sold out before this bid; no contribution taken");
+            return Ok(());
+        }
+        if amount >= remaining {
+            amount = remaining;
+            config.auction.clearing_price = price;
+            config.is_active = false;
+            config.end_time = current_time;
+        }
+    }
+
+    // Curve: price the buy along x * y = k; the buy that reaches the target
+    // market cap or exhausts the allocation completes the sale
+    let mut curve_tokens = 0;
+    if config.sale_mode == SaleMode::BondingCurve {
+        let (quote_reserve, token_reserve) = config.curve_reserves();
+        let k = quote_reserve.checked_mul(token_reserve).unwrap();
+        let new_quote_reserve = quote_reserve.checked_add(amount as u128).unwrap();
+        let new_token_reserve = k
+            .checked_add(new_quote_reserve - 1)
+            .unwrap()
+            .checked_div(new_quote_reserve)
+            .unwrap();
+        let remaining = config.tokens_for_presale.checked_sub(config.total_sold).unwrap();
+        curve_tokens = ((token_reserve - new_token_reserve) as u64).min(remaining);
+        if curve_tokens == 0 {
+            return Err(LaunchpadError::InvalidAmount.into());
+        }
+
+        let market_cap = new_quote_reserve
+            .checked_mul(config.total_supply as u128)
+            .unwrap()
+            .checked_div(token_reserve - curve_tokens as u128)
+            .unwrap();
+        if market_cap >= config.curve.target_market_cap as u128 || curve_tokens == remaining {
+            config.is_active = false;
+            config.end_time = clock.unix_timestamp;
+            msg!("Bonding curve complete; ready to migrate liquidity");
+        }
+    }
+
+    if config.anti_bot.max_per_slot > 0 {
+        if config.anti_bot.last_slot != clock.slot {
+            config.anti_bot.last_slot = clock.slot;
+            config.anti_bot.last_slot_contributed = 0;
+        }
+        config.anti_bot.last_slot_contributed = config.anti_bot.last_slot_contributed
+            .checked_add(amount)
+            .unwrap();
+        if config.anti_bot.last_slot_contributed > config.anti_bot.max_per_slot {
+            return Err(LaunchpadError::SlotCapReached.into());
+        }
+    }
+
+    if let Some(index) = round_index {
+        let round = &config.rounds[index];
+        if round.raised.checked_add(amount).unwrap() > round.hard_cap {
+            return Err(LaunchpadError::HardCapReached.into());
+        }
+    } else if config.sale_mode == SaleMode::FixedPrice
+        && config.total_raised.checked_add(amount).unwrap() > config.hard_cap
+    {
+        return Err(LaunchpadError::HardCapReached.into());
+    }
+
+    if config.is_spl_raise() {
+        // Transfer payment tokens into the contribution vault
+        let participant_payment_account = next_account_info(account_info_iter)?;
+        let payment_vault = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        if *payment_vault.key != config.payment_vault {
+            return Err(LaunchpadError::InvalidPaymentAccount.into());
+        }
+
+        invoke(
+            &token_instruction::transfer(
+                token_program.key,
+                participant_payment_account.key,
+                payment_vault.key,
+                participant_account.key,
+                &[],
+                amount,
+            )?,
+            &[
+                participant_payment_account.clone(),
+                payment_vault.clone(),
+                participant_account.clone(),
+                token_program.clone(),
+            ],
+        )?;
+    } else {
+        // Transfer SOL to launchpad account
+        solana_program::program::invoke(
+            &system_instruction::transfer(
+                participant_account.key,
+                launchpad_account.key,
+                amount,
+            ),
+            &[
+                participant_account.clone(),
+                launchpad_account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+    }
+
+    // Curve buys are delivered straight from the escrow vault
+    if config.sale_mode == SaleMode::BondingCurve {
+        let participant_token_account = next_account_info(account_info_iter)?;
+        let token_vault = next_account_info(account_info_iter)?;
+        let launchpad_authority = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        if *token_vault.key != config.token_vault {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        transfer_signed_by_authority(
+            program_id,
+            launchpad_account,
+            token_vault,
+            participant_token_account,
+            launchpad_authority,
+            token_program,
+            curve_tokens,
+        )?;
+    }
+
+    // Fair-launch and auction allocations depend on the outcome and are settled at claim
+    let tokens_amount = if config.sale_mode == SaleMode::BondingCurve {
+        curve_tokens
+    } else if config.sale_mode != SaleMode::FixedPrice {
+        0
+    } else if let Some(index) = round_index {
+        // Rounds sell at their own price out of the shared presale allocation
+        let round = &mut config.rounds[index];
+        round.raised = round.raised.checked_add(amount).unwrap();
+        let tokens = (amount as u128)
+            .checked_mul(10u128.pow(config.token_decimals as u32))
+            .unwrap()
+            .checked_div(round.price_per_token as u128)
+            .unwrap() as u64;
+        if config.total_sold.checked_add(tokens).unwrap() > config.tokens_for_presale {
+            return Err(LaunchpadError::HardCapReached.into());
+        }
+        tokens
+    } else {
+        amount
+            .checked_mul(config.tokens_for_presale)
+            .unwrap()
+            .checked_div(config.hard_cap)
+            .unwrap()
+    };
+
+    let mut participant_info = if participant_info_account.data_is_empty() {
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                participant_account.key,
+                participant_info_account.key,
+                rent.minimum_balance(Participant::LEN),
+                Participant::LEN as u64,
+                program_id,
+            ),
+            &[
+                participant_account.clone(),
+                participant_info_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                PARTICIPANT_SEED,
+                launchpad_account.key.as_ref(),
+                participant_account.key.as_ref(),
+                &[participant_bump],
+            ]],
+        )?;
+        config.participant_count = config.participant_count.checked_add(1).unwrap();
+        Participant {
+            wallet: *participant_account.key,
+            amount_contributed: amount,
+            tokens_owed: tokens_amount,
+            tokens_claimed: 0,
+            tier,
+            last_claim_time: 0,
+            allocation_settled: false,
+            referrer,
+        }
+    } else {
+        let mut info = Participant::try_from_slice(&participant_info_account.data.borrow())?;
+        info.amount_contributed = info.amount_contributed.checked_add(amount).unwrap();
+        info.tokens_owed = info.tokens_owed.checked_add(tokens_amount).unwrap();
+        info.tier = tier;
+        info
+    };
+
+    if config.sale_mode == SaleMode::BondingCurve {
+        participant_info.tokens_claimed = participant_info.tokens_claimed
+            .checked_add(curve_tokens)
+            .unwrap();
+    }
+    participant_info.serialize(&mut *participant_info_account.data.borrow_mut())?;
+
+    if let Some(referral_account) = referral_account {
+        credit_referral(
+            program_id,
+            launchpad_account.key,
+            &referrer,
+            referral_account,
+            participant_account,
+            system_program,
+            amount,
+        )?;
+        config.total_referred_volume = config.total_referred_volume.checked_add(amount).unwrap();
+    }
+
+    config.total_raised = config.total_raised.checked_add(amount).unwrap();
+    config.total_sold = config.total_sold.checked_add(tokens_amount).unwrap();
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Participant records live at [PARTICIPANT_SEED, launchpad, wallet]; once
+// created they must also be owned by this program. Returns the bump.
+fn check_participant_account(
+    program_id: &Pubkey,
+    launchpad: &Pubkey,
+    wallet: &Pubkey,
+    participant_info_account: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let (expected_participant, bump) = Pubkey::find_program_address(
+        &[PARTICIPANT_SEED, launchpad.as_ref(), wallet.as_ref()],
+        program_id,
+    );
+    if expected_participant != *participant_info_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !participant_info_account.data_is_empty() && participant_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(bump)
+}
+
+// Reads a wallet's whitelist entry after checking its derivation and owner
+fn load_whitelist_entry(
+    program_id: &Pubkey,
+    launchpad: &Pubkey,
+    wallet: &Pubkey,
+    whitelist_account: &AccountInfo,
+) -> Result<WhitelistEntry, ProgramError> {
+    let (expected_entry, _) = Pubkey::find_program_address(
+        &[WHITELIST_SEED, launchpad.as_ref(), wallet.as_ref()],
+        program_id,
+    );
+    if expected_entry != *whitelist_account.key
+        || whitelist_account.owner != program_id
+        || whitelist_account.data_is_empty()
+    {
+        return Err(LaunchpadError::NotWhitelisted.into());
+    }
+    let entry = WhitelistEntry::try_from_slice(&whitelist_account.data.borrow())?;
+    if entry.wallet != *wallet || entry.launchpad != *launchpad {
+        return Err(LaunchpadError::NotWhitelisted.into());
+    }
+    Ok(entry)
+}
+
+// Creates or updates a whitelist entry, paid for by the owner
+fn write_whitelist_entry<'a>(
+    program_id: &Pubkey,
+    launchpad: &Pubkey,
+    config: &mut LaunchpadConfig,
+    owner_account: &AccountInfo<'a>,
+    whitelist_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    batch_entry: &WhitelistBatchEntry,
+) -> ProgramResult {
+    let (expected_entry, entry_bump) = Pubkey::find_program_address(
+        &[WHITELIST_SEED, launchpad.as_ref(), batch_entry.wallet.as_ref()],
+        program_id,
+    );
+    if expected_entry != *whitelist_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    if whitelist_account.data_is_empty() {
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                owner_account.key,
+                whitelist_account.key,
+                rent.minimum_balance(WhitelistEntry::LEN),
+                WhitelistEntry::LEN as u64,
+                program_id,
+            ),
+            &[owner_account.clone(), whitelist_account.clone(), system_program.clone()],
+            &[&[WHITELIST_SEED, launchpad.as_ref(), batch_entry.wallet.as_ref(), &[entry_bump]]],
+        )?;
+        config.whitelist_entry_count = config.whitelist_entry_count.checked_add(1).unwrap();
+    } else if whitelist_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let entry = WhitelistEntry {
+        launchpad: *launchpad,
+        wallet: batch_entry.wallet,
+        tier: batch_entry.tier,
+    };
+    entry.serialize(&mut *whitelist_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Adds referred volume to the referrer's record, creating it on first use
+fn credit_referral<'a>(
+    program_id: &Pubkey,
+    launchpad: &Pubkey,
+    referrer: &Pubkey,
+    referral_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let (expected_referral, referral_bump) = Pubkey::find_program_address(
+        &[REFERRAL_SEED, launchpad.as_ref(), referrer.as_ref()],
+        program_id,
+    );
+    if expected_referral != *referral_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let mut record = if referral_account.data_is_empty() {
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                referral_account.key,
+                rent.minimum_balance(ReferralRecord::LEN),
+                ReferralRecord::LEN as u64,
+                program_id,
+            ),
+            &[payer.clone(), referral_account.clone(), system_program.clone()],
+            &[&[REFERRAL_SEED, launchpad.as_ref(), referrer.as_ref(), &[referral_bump]]],
+        )?;
+        ReferralRecord {
+            launchpad: *launchpad,
+            referrer: *referrer,
+            referred_volume: 0,
+            claimed: false,
+        }
+    } else {
+        ReferralRecord::try_from_slice(&referral_account.data.borrow())?
+    };
+
+    record.referred_volume = record.referred_volume.checked_add(amount).unwrap();
+    record.serialize(&mut *referral_account.data.borrow_mut())?;
 
     Ok(())
 }
 
+// Highest tier whose stake requirement the wallet's staking position meets
+fn staked_tier(
+    config: &LaunchpadConfig,
+    wallet: &Pubkey,
+    stake_pool: &AccountInfo,
+    user_stake_account: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    // Only the configured pool counts; anyone can open a pool for a worthless mint
+    if *stake_pool.key != config.tier_system.stake_pool
+        || *user_stake_account.owner != STAKING_PROGRAM_ID
+        || stake_pool.owner != user_stake_account.owner
+    {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let (expected_stake_account, _) = Pubkey::find_program_address(
+        &[USER_STAKE_SEED, stake_pool.key.as_ref(), wallet.as_ref()],
+        user_stake_account.owner,
+    );
+    if expected_stake_account != *user_stake_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let stake_info = UserStakeInfo::deserialize(&mut &user_stake_account.data.borrow()[..])?;
+    if stake_info.owner != *wallet {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+
+    config
+        .tier_system
+        .tiers
+        .iter()
+        .enumerate()
+        .filter(|(_, tier)| stake_info.stake_amount >= tier.required_tokens)
+        .max_by_key(|(_, tier)| tier.required_tokens)
+        .map(|(index, _)| index as u8)
+        .ok_or_else(|| LaunchpadError::InvalidTier.into())
+}
+
+// Leaves are keccak(wallet); pairs are hashed in sorted order so proofs
+// don't need to carry left/right flags
+fn verify_whitelist_proof(root: &[u8; 32], wallet: &Pubkey, proof: &[[u8; 32]]) -> bool {
+    let mut node = keccak::hashv(&[wallet.as_ref()]).to_bytes();
+    for sibling in proof {
+        node = if node <= *sibling {
+            keccak::hashv(&[&node, sibling]).to_bytes()
+        } else {
+            keccak::hashv(&[sibling, &node]).to_bytes()
+        };
+    }
+
+    node == *root
+}
+
 fn process_claim_tokens(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -320,21 +1524,36 @@ fn process_claim_tokens(
     let participant_account = next_account_info(account_info_iter)?;
     let launchpad_account = next_account_info(account_info_iter)?;
     let participant_info_account = next_account_info(account_info_iter)?;
-    let token_account = next_account_info(account_info_iter)?;
+    let participant_token_account = next_account_info(account_info_iter)?;
+    let token_vault = next_account_info(account_info_iter)?;
+    let launchpad_authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
 
-    let config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if !participant_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_participant_account(
+        program_id,
+        launchpad_account.key,
+        participant_account.key,
+        participant_info_account,
+    )?;
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
     let mut participant_info = Participant::try_from_slice(&participant_info_account.data.borrow())?;
 
-    if config.is_active {
-        return Err(LaunchpadError::PresaleNotActive.into());
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if !config.has_ended(current_time) {
+        return Err(LaunchpadError::PresaleStillActive.into());
+    }
+    if config.is_cancelled {
+        return Err(LaunchpadError::LaunchCancelled.into());
     }
 
     if config.total_raised < config.soft_cap {
         return Err(LaunchpadError::SoftCapNotReached.into());
     }
 
-    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
     if config.tier_system.enabled {
         let tier = &config.tier_system.tiers[participant_info.tier as usize];
         if current_time < participant_info.last_claim_time + tier.vesting_period {
@@ -342,26 +1561,66 @@ fn process_claim_tokens(
         }
     }
 
-    let claimable_amount = participant_info.tokens_owed
+    if participant_info.wallet != *participant_account.key {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+
+    let mut refund_amount = 0;
+    if config.sale_mode != SaleMode::FixedPrice && !participant_info.allocation_settled {
+        refund_amount = settle_allocation(&config, &mut participant_info)?;
+    }
+
+    if refund_amount > 0 {
+        if config.is_spl_raise() {
+            let participant_payment_account = next_account_info(account_info_iter)?;
+            let payment_vault = next_account_info(account_info_iter)?;
+            transfer_from_payment_vault(
+                program_id,
+                launchpad_account,
+                &config,
+                payment_vault,
+                participant_payment_account,
+                launchpad_authority,
+                token_program,
+                refund_amount,
+            )?;
+        } else {
+            **launchpad_account.lamports.borrow_mut() = launchpad_account
+                .lamports()
+                .checked_sub(refund_amount)
+                .ok_or(LaunchpadError::InvalidAmount)?;
+            **participant_account.lamports.borrow_mut() = participant_account
+                .lamports()
+                .checked_add(refund_amount)
+                .unwrap();
+        }
+        config.excess_refunded = config.excess_refunded.checked_add(refund_amount).unwrap();
+        config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+    }
+
+    let claimable_amount = vested_amount(&config, participant_info.tokens_owed, current_time)
         .checked_sub(participant_info.tokens_claimed)
         .unwrap();
+    if claimable_amount == 0 {
+        if refund_amount > 0 {
+            participant_info.serialize(&mut *participant_info_account.data.borrow_mut())?;
+            return Ok(());
+        }
+        return Err(LaunchpadError::NothingToClaim.into());
+    }
 
-    // Transfer tokens
-    solana_program::program::invoke(
-        &token_instruction::transfer(
-            token_program.key,
-            token_account.key,
-            participant_account.key,
-            &config.owner,
-            &[&config.owner],
-            claimable_amount,
-        )?,
-        &[
-            token_account.clone(),
-            participant_account.clone(),
-            owner_account.clone(),
-            token_program.clone(),
-        ],
+    // Transfer tokens out of the escrow vault
+    if *token_vault.key != config.token_vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    transfer_signed_by_authority(
+        program_id,
+        launchpad_account,
+        token_vault,
+        participant_token_account,
+        launchpad_authority,
+        token_program,
+        claimable_amount,
     )?;
 
     participant_info.tokens_claimed = participant_info.tokens_claimed
@@ -373,6 +1632,99 @@ fn process_claim_tokens(
     Ok(())
 }
 
+// Fixes a fair-launch, overflow or auction allocation once the sale is over, returning
+// the part of the contribution that was not filled
+fn settle_allocation(
+    config: &LaunchpadConfig,
+    participant_info: &mut Participant,
+) -> Result<u64, ProgramError> {
+    let contributed = participant_info.amount_contributed as u128;
+    let (tokens_owed, filled) = match config.sale_mode {
+        SaleMode::FixedPrice | SaleMode::BondingCurve => {
+            (participant_info.tokens_owed as u128, contributed)
+        }
+        SaleMode::FairLaunch => {
+            let total_raised = config.total_raised as u128;
+            (
+                contributed
+                    .checked_mul(config.tokens_for_presale as u128)
+                    .unwrap()
+                    .checked_div(total_raised)
+                    .unwrap(),
+                contributed
+                    .checked_mul(config.filled_raise() as u128)
+                    .unwrap()
+                    .checked_div(total_raised)
+                    .unwrap(),
+            )
+        }
+        SaleMode::Overflow => {
+            let filled = contributed
+                .checked_mul(config.filled_raise() as u128)
+                .unwrap()
+                .checked_div(config.total_raised as u128)
+                .unwrap();
+            (
+                filled
+                    .checked_mul(config.tokens_for_presale as u128)
+                    .unwrap()
+                    .checked_div(config.hard_cap as u128)
+                    .unwrap(),
+                filled,
+            )
+        }
+        SaleMode::DutchAuction => (
+            contributed
+                .checked_mul(10u128.pow(config.token_decimals as u32))
+                .unwrap()
+                .checked_div(config.clearing_price()? as u128)
+                .unwrap(),
+            contributed,
+        ),
+    };
+
+    let refund_amount = participant_info.amount_contributed.checked_sub(filled as u64).unwrap();
+    participant_info.tokens_owed = tokens_owed as u64;
+    participant_info.amount_contributed = filled as u64;
+    participant_info.allocation_settled = true;
+    Ok(refund_amount)
+}
+
+// Tokens unlocked so far: the TGE share at the end of the sale, then the
+// remainder linearly once the cliff has passed
+fn vested_amount(config: &LaunchpadConfig, tokens_owed: u64, current_time: i64) -> u64 {
+    let vesting = &config.vesting;
+    if current_time < config.end_time {
+        return 0;
+    }
+
+    if !config.tranches.is_empty() {
+        let unlocked_bps: u64 = config
+            .tranches
+            .iter()
+            .filter(|tranche| current_time >= tranche.unlock_time)
+            .map(|tranche| tranche.bps as u64)
+            .sum();
+        return (tokens_owed as u128 * unlocked_bps as u128 / 10_000) as u64;
+    }
+
+    let tge_amount = tokens_owed as u128 * vesting.tge_percent as u128 / 100;
+    let linear_start = config.end_time.saturating_add(vesting.cliff_duration);
+    if current_time < linear_start {
+        return tge_amount as u64;
+    }
+
+    let elapsed = current_time - linear_start;
+    if vesting.vesting_duration == 0 || elapsed >= vesting.vesting_duration {
+        return tokens_owed;
+    }
+
+    let linear_amount = (tokens_owed as u128 - tge_amount)
+        * elapsed as u128
+        / vesting.vesting_duration as u128;
+    (tge_amount + linear_amount) as u64
+}
+
 fn process_withdraw_funds(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -381,30 +1733,167 @@ fn process_withdraw_funds(
     let owner_account = next_account_info(account_info_iter)?;
     let launchpad_account = next_account_info(account_info_iter)?;
     let system_program = next_account_info(account_info_iter)?;
+    // Fee wallet, or its payment-mint token account for SPL raises
+    let fee_account = next_account_info(account_info_iter)?;
 
-    let config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
     if config.owner != *owner_account.key {
         return Err(LaunchpadError::InvalidOwner.into());
     }
 
-    if config.is_active {
-        return Err(LaunchpadError::PresaleNotActive.into());
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if !config.has_ended(current_time) {
+        return Err(LaunchpadError::PresaleStillActive.into());
+    }
+    if config.is_cancelled {
+        return Err(LaunchpadError::LaunchCancelled.into());
     }
 
     if config.total_raised < config.soft_cap {
         return Err(LaunchpadError::SoftCapNotReached.into());
     }
 
-    let lamports = launchpad_account.lamports();
-    **launchpad_account.lamports.borrow_mut() = 0;
+    // The liquidity share has to be placed before the rest can be withdrawn
+    if config.liquidity_percentage > 0 && !config.is_finalized {
+        return Err(LaunchpadError::LiquidityNotAdded.into());
+    }
+
+    if config.is_spl_raise() {
+        let owner_payment_account = next_account_info(account_info_iter)?;
+        let payment_vault = next_account_info(account_info_iter)?;
+        let launchpad_authority = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        pay_raise_fee(
+            program_id,
+            launchpad_account,
+            &mut config,
+            fee_account,
+            Some((payment_vault, launchpad_authority, token_program)),
+        )?;
+        let available = spl_token::state::Account::unpack(&payment_vault.data.borrow())?
+            .amount
+            .checked_sub(config.reserved_funds())
+            .ok_or(ProgramError::InsufficientFunds)?;
+        let amount = releasable_raise(&config, available, current_time);
+        if amount == 0 {
+            return Err(LaunchpadError::NothingToClaim.into());
+        }
+        transfer_from_payment_vault(
+            program_id,
+            launchpad_account,
+            &config,
+            payment_vault,
+            owner_payment_account,
+            launchpad_authority,
+            token_program,
+            amount,
+        )?;
+        config.owner_withdrawn = config.owner_withdrawn.checked_add(amount).unwrap();
+        config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+        return Ok(());
+    }
+
+    pay_raise_fee(program_id, launchpad_account, &mut config, fee_account, None)?;
+
+    // Unfilled contributions, unclaimed referral rewards and the account's rent
+    // stay behind so participants can still use it
+    let reserved = config
+        .reserved_funds()
+        .checked_add(Rent::get()?.minimum_balance(launchpad_account.data_len()))
+        .unwrap();
+    let available = launchpad_account.lamports().saturating_sub(reserved);
+    let lamports = releasable_raise(&config, available, current_time);
+    if lamports == 0 {
+        return Err(LaunchpadError::NothingToClaim.into());
+    }
+    **launchpad_account.lamports.borrow_mut() = launchpad_account
+        .lamports()
+        .checked_sub(lamports)
+        .unwrap();
     **owner_account.lamports.borrow_mut() = owner_account
         .lamports()
         .checked_add(lamports)
         .unwrap();
+    config.owner_withdrawn = config.owner_withdrawn.checked_add(lamports).unwrap();
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Sends the platform's share of the raise to the fee wallet, once. SPL raises
+// pay from the payment vault and pass (vault, authority, token program).
+fn pay_raise_fee<'a>(
+    program_id: &Pubkey,
+    launchpad_account: &AccountInfo<'a>,
+    config: &mut LaunchpadConfig,
+    fee_account: &AccountInfo<'a>,
+    spl_accounts: Option<(&AccountInfo<'a>, &AccountInfo<'a>, &AccountInfo<'a>)>,
+) -> ProgramResult {
+    let fee = config.raise_fee();
+    if config.raise_fee_paid || fee == 0 {
+        return Ok(());
+    }
+
+    match spl_accounts {
+        Some((payment_vault, launchpad_authority, token_program)) => {
+            let fee_token_account = spl_token::state::Account::unpack(&fee_account.data.borrow())?;
+            if fee_token_account.owner.to_string() != FEE_WALLET
+                || fee_token_account.mint != config.payment_mint
+            {
+                return Err(ProgramError::InvalidArgument);
+            }
+            transfer_from_payment_vault(
+                program_id,
+                launchpad_account,
+                config,
+                payment_vault,
+                fee_account,
+                launchpad_authority,
+                token_program,
+                fee,
+            )?;
+        }
+        None => {
+            if fee_account.key.to_string() != FEE_WALLET {
+                return Err(ProgramError::InvalidArgument);
+            }
+            **launchpad_account.lamports.borrow_mut() = launchpad_account
+                .lamports()
+                .checked_sub(fee)
+                .ok_or(LaunchpadError::InvalidAmount)?;
+            **fee_account.lamports.borrow_mut() = fee_account
+                .lamports()
+                .checked_add(fee)
+                .unwrap();
+        }
+    }
+
+    config.raise_fee_paid = true;
+    msg!("Raise fee paid: {}", fee);
 
     Ok(())
 }
 
+// Part of the owner's funds released so far. With raise vesting the owner's
+// total is what was already withdrawn plus what is still available, unlocked
+// linearly from the end of the sale.
+fn releasable_raise(config: &LaunchpadConfig, available: u64, current_time: i64) -> u64 {
+    if config.raise_vesting_duration == 0 {
+        return available;
+    }
+    let elapsed = current_time.saturating_sub(config.end_time).max(0);
+    if elapsed >= config.raise_vesting_duration {
+        return available;
+    }
+    let entitlement = config.owner_withdrawn as u128 + available as u128;
+    let released = entitlement * elapsed as u128 / config.raise_vesting_duration as u128;
+    (released as u64).saturating_sub(config.owner_withdrawn)
+}
+
 fn process_cancel_launch(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -413,15 +1902,44 @@ fn process_cancel_launch(
     let owner_account = next_account_info(account_info_iter)?;
     let launchpad_account = next_account_info(account_info_iter)?;
 
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
     let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
     if config.owner != *owner_account.key {
         return Err(LaunchpadError::InvalidOwner.into());
     }
+    if config.is_cancelled {
+        return Err(LaunchpadError::LaunchCancelled.into());
+    }
+    if config.is_finalized {
+        return Err(LaunchpadError::AlreadyFinalized.into());
+    }
+    // Curve buyers already hold their tokens, so there is nothing to refund against
+    if config.sale_mode == SaleMode::BondingCurve && config.total_raised > 0 {
+        return Err(LaunchpadError::InvalidCurve.into());
+    }
+    // Refunds are paid from the raise, so none of it may have left the launchpad
+    if config.owner_withdrawn > 0 || config.raise_fee_paid {
+        return Err(LaunchpadError::FundsWithdrawn.into());
+    }
+    // A sale that ended above its soft cap is open for claims and excess refunds, which
+    // a refund would pay out a second time
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if config.has_ended(current_time) && config.total_raised >= config.soft_cap {
+        return Err(LaunchpadError::SoftCapReached.into());
+    }
 
+    // Cancelling opens a refund window for every participant
     config.is_active = false;
-    config.end_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    config.is_cancelled = true;
+    config.end_time = current_time;
+    config.refund_deadline = current_time.checked_add(CANCEL_REFUND_WINDOW).unwrap();
     config.serialize(&mut *launchpad_account.data.borrow_mut())?;
 
+    msg!("Launch cancelled; refunds open until {}", config.refund_deadline);
+
     Ok(())
 }
 
@@ -434,14 +1952,28 @@ fn process_add_to_whitelist(
     let owner_account = next_account_info(account_info_iter)?;
     let launchpad_account = next_account_info(account_info_iter)?;
     let whitelist_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
 
-    let config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
     if config.owner != *owner_account.key {
         return Err(LaunchpadError::InvalidOwner.into());
     }
 
     let wallet = Pubkey::try_from_slice(instruction_data)?;
-    wallet.serialize(&mut *whitelist_account.data.borrow_mut())?;
+    write_whitelist_entry(
+        program_id,
+        launchpad_account.key,
+        &mut config,
+        owner_account,
+        whitelist_account,
+        system_program,
+        &WhitelistBatchEntry { wallet, tier: 0 },
+    )?;
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
 
     Ok(())
 }
@@ -455,17 +1987,1265 @@ fn process_remove_from_whitelist(
     let launchpad_account = next_account_info(account_info_iter)?;
     let whitelist_account = next_account_info(account_info_iter)?;
 
-    let config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
     if config.owner != *owner_account.key {
         return Err(LaunchpadError::InvalidOwner.into());
     }
 
+    let entry = WhitelistEntry::try_from_slice(&whitelist_account.data.borrow())?;
+    load_whitelist_entry(program_id, launchpad_account.key, &entry.wallet, whitelist_account)?;
+
     // Close whitelist account
     let dest_starting_lamports = owner_account.lamports();
     **owner_account.lamports.borrow_mut() = dest_starting_lamports
         .checked_add(whitelist_account.lamports())
         .unwrap();
     **whitelist_account.lamports.borrow_mut() = 0;
+    whitelist_account.data.borrow_mut().fill(0);
+
+    config.whitelist_entry_count = config.whitelist_entry_count.saturating_sub(1);
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_refund(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let participant_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+    let participant_info_account = next_account_info(account_info_iter)?;
+
+    if !participant_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_participant_account(
+        program_id,
+        launchpad_account.key,
+        participant_account.key,
+        participant_info_account,
+    )?;
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if !config.has_ended(current_time) {
+        return Err(LaunchpadError::PresaleStillActive.into());
+    }
+
+    // Refunds are only available for cancelled launches and presales that
+    // failed to reach the soft cap
+    if !config.is_cancelled && config.total_raised >= config.soft_cap {
+        return Err(LaunchpadError::SoftCapReached.into());
+    }
+
+    let mut participant_info = Participant::try_from_slice(&participant_info_account.data.borrow())?;
+    if participant_info.wallet != *participant_account.key {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+
+    let refund_amount = participant_info.amount_contributed;
+    if refund_amount == 0 {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+    // Tokens already received cannot be handed back, so neither can the payment
+    if participant_info.tokens_claimed > 0 {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+
+    if config.is_spl_raise() {
+        let participant_payment_account = next_account_info(account_info_iter)?;
+        let payment_vault = next_account_info(account_info_iter)?;
+        let launchpad_authority = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        transfer_from_payment_vault(
+            program_id,
+            launchpad_account,
+            &config,
+            payment_vault,
+            participant_payment_account,
+            launchpad_authority,
+            token_program,
+            refund_amount,
+        )?;
+    } else {
+        **launchpad_account.lamports.borrow_mut() = launchpad_account
+            .lamports()
+            .checked_sub(refund_amount)
+            .ok_or(LaunchpadError::InvalidAmount)?;
+        **participant_account.lamports.borrow_mut() = participant_account
+            .lamports()
+            .checked_add(refund_amount)
+            .unwrap();
+    }
+
+    config.total_refunded = config.total_refunded.checked_add(refund_amount).unwrap();
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    participant_info.amount_contributed = 0;
+    participant_info.tokens_owed = 0;
+    participant_info.serialize(&mut *participant_info_account.data.borrow_mut())?;
 
     Ok(())
 }
+
+fn process_finalize(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+    let launchpad_authority = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let owner_token_account = next_account_info(account_info_iter)?;
+    let authority_token_account = next_account_info(account_info_iter)?;
+    // Wrapped SOL account, or the payment vault for SPL raises
+    let authority_quote_account = next_account_info(account_info_iter)?;
+    let amm_program = next_account_info(account_info_iter)?;
+    let amm_pool = next_account_info(account_info_iter)?;
+    // The pool's [b"pool_authority", pool] PDA, which mints the LP tokens
+    let amm_authority = next_account_info(account_info_iter)?;
+    let amm_token_a = next_account_info(account_info_iter)?;
+    let amm_token_b = next_account_info(account_info_iter)?;
+    let lp_mint = next_account_info(account_info_iter)?;
+    let lp_lock_account = next_account_info(account_info_iter)?;
+    let lp_escrow_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    // Fee wallet, or its payment-mint token account for SPL raises
+    let fee_account = next_account_info(account_info_iter)?;
+
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *amm_program.key != LIQUIDITY_POOL_PROGRAM_ID || amm_pool.owner != amm_program.key {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // A completed curve can be migrated by anyone; the signer only pays rent
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    let is_curve = config.sale_mode == SaleMode::BondingCurve;
+    if config.owner != *owner_account.key && !is_curve {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+    if config.is_finalized {
+        return Err(LaunchpadError::AlreadyFinalized.into());
+    }
+    if config.is_cancelled {
+        return Err(LaunchpadError::LaunchCancelled.into());
+    }
+
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if !config.has_ended(current_time) {
+        return Err(LaunchpadError::PresaleStillActive.into());
+    }
+    if config.total_raised < config.soft_cap {
+        return Err(LaunchpadError::SoftCapNotReached.into());
+    }
+
+    let (expected_authority, authority_bump) = Pubkey::find_program_address(
+        &[LAUNCHPAD_AUTHORITY_SEED, launchpad_account.key.as_ref()],
+        program_id,
+    );
+    if expected_authority != *launchpad_authority.key || config.mint != *mint_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let authority_seeds: &[&[u8]] = &[
+        LAUNCHPAD_AUTHORITY_SEED,
+        launchpad_account.key.as_ref(),
+        &[authority_bump],
+    ];
+
+    // Quote side: liquidity_percentage of the raise, at listing_price quote
+    // units per whole token. A curve migrates its whole raise at the final
+    // curve price, paired with unsold tokens from the escrow vault.
+    let (sol_amount, token_amount) = if is_curve {
+        let sol_amount = config
+            .filled_raise()
+            .checked_sub(config.referral_pool())
+            .unwrap()
+            .checked_sub(config.raise_fee())
+            .unwrap();
+        let (quote_reserve, token_reserve) = config.curve_reserves();
+        let at_curve_price = (sol_amount as u128)
+            .checked_mul(token_reserve)
+            .unwrap()
+            .checked_div(quote_reserve)
+            .unwrap() as u64;
+        let unsold = config.tokens_for_presale.checked_sub(config.total_sold).unwrap();
+        (sol_amount, at_curve_price.min(unsold))
+    } else {
+        let sol_amount = config.filled_raise()
+            .checked_mul(config.liquidity_percentage as u64)
+            .unwrap()
+            .checked_div(100)
+            .unwrap();
+        let decimals = spl_token::state::Mint::unpack(&mint_account.data.borrow())?.decimals;
+        let token_amount = (sol_amount as u128)
+            .checked_mul(10u128.pow(decimals as u32))
+            .unwrap()
+            .checked_div(config.listing_price as u128)
+            .ok_or(LaunchpadError::InvalidAmount)?;
+        let token_amount = u64::try_from(token_amount).map_err(|_| LaunchpadError::InvalidAmount)?;
+        (sol_amount, token_amount)
+    };
+
+    let spl_accounts = if config.is_spl_raise() {
+        Some((authority_quote_account, launchpad_authority, token_program))
+    } else {
+        None
+    };
+    pay_raise_fee(program_id, launchpad_account, &mut config, fee_account, spl_accounts)?;
+
+    if config.is_spl_raise() {
+        // Payment tokens are already held by the launchpad authority
+        if *authority_quote_account.key != config.payment_vault {
+            return Err(LaunchpadError::InvalidPaymentAccount.into());
+        }
+    } else {
+        // Move the SOL into the authority's wrapped SOL account
+        **launchpad_account.lamports.borrow_mut() = launchpad_account
+            .lamports()
+            .checked_sub(sol_amount)
+            .ok_or(LaunchpadError::InvalidAmount)?;
+        **authority_quote_account.lamports.borrow_mut() = authority_quote_account
+            .lamports()
+            .checked_add(sol_amount)
+            .unwrap();
+        invoke(
+            &token_instruction::sync_native(token_program.key, authority_quote_account.key)?,
+            &[authority_quote_account.clone(), token_program.clone()],
+        )?;
+    }
+
+    // The project supplies the token side of the pool; a curve already holds
+    // it in the escrow vault
+    if is_curve {
+        if *authority_token_account.key != config.token_vault {
+            return Err(ProgramError::InvalidAccountData);
+        }
+    } else {
+        invoke(
+            &token_instruction::transfer(
+                token_program.key,
+                owner_token_account.key,
+                authority_token_account.key,
+                owner_account.key,
+                &[],
+                token_amount,
+            )?,
+            &[
+                owner_token_account.clone(),
+                authority_token_account.clone(),
+                owner_account.clone(),
+                token_program.clone(),
+            ],
+        )?;
+    }
+
+    // Order the deposit to match the pool's token A / token B sides
+    let amm_token_a_mint = spl_token::state::Account::unpack(&amm_token_a.data.borrow())?.mint;
+    let (user_token_a, user_token_b, amount_a, amount_b) = if amm_token_a_mint == config.mint {
+        (authority_token_account, authority_quote_account, token_amount, sol_amount)
+    } else {
+        (authority_quote_account, authority_token_account, sol_amount, token_amount)
+    };
+
+    // Escrow token account for the LP tokens, owned by the lock PDA
+    let (expected_lock, lock_bump) = Pubkey::find_program_address(
+        &[LP_LOCK_SEED, launchpad_account.key.as_ref()],
+        program_id,
+    );
+    if expected_lock != *lp_lock_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let escrow = spl_token::state::Account::unpack(&lp_escrow_account.data.borrow())?;
+    if escrow.owner != expected_lock || escrow.mint != *lp_mint.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // The raise must set the opening price: a pool someone pre-seeded at a skewed ratio
+    // would mint LP against the smaller side and keep the rest of the deposit. Curves
+    // migrate permissionlessly, so the pool must also pair exactly the sale's two mints
+    let pool = AmmPoolReserves::deserialize(&mut &amm_pool.data.borrow()[..])?;
+    let quote_mint = if config.is_spl_raise() {
+        config.payment_mint
+    } else {
+        spl_token::native_mint::id()
+    };
+    let pairs_sale_mints = (pool.token_a_mint == config.mint && pool.token_b_mint == quote_mint)
+        || (pool.token_a_mint == quote_mint && pool.token_b_mint == config.mint);
+    if !pool.is_initialized
+        || !pairs_sale_mints
+        || pool.token_a_account != *amm_token_a.key
+        || pool.token_b_account != *amm_token_b.key
+        || pool.pool_mint != *lp_mint.key
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if pool.token_a_amount != 0
+        || pool.token_b_amount != 0
+        || spl_token::state::Mint::unpack(&lp_mint.data.borrow())?.supply != 0
+    {
+        return Err(LaunchpadError::PoolNotEmpty.into());
+    }
+
+    // Seed the pool through the liquidity-pool AddLiquidity instruction
+    let mut data = vec![1u8];
+    data.extend_from_slice(&amount_a.to_le_bytes());
+    data.extend_from_slice(&amount_b.to_le_bytes());
+    invoke_signed(
+        &Instruction {
+            program_id: *amm_program.key,
+            accounts: vec![
+                AccountMeta::new(*amm_pool.key, false),
+                AccountMeta::new(*user_token_a.key, false),
+                AccountMeta::new(*user_token_b.key, false),
+                AccountMeta::new(*amm_token_a.key, false),
+                AccountMeta::new(*amm_token_b.key, false),
+                AccountMeta::new(*lp_mint.key, false),
+                AccountMeta::new(*lp_escrow_account.key, false),
+                AccountMeta::new_readonly(*token_program.key, false),
+                AccountMeta::new_readonly(*launchpad_authority.key, true),
+                AccountMeta::new_readonly(*amm_authority.key, false),
+            ],
+            data,
+        },
+        &[
+            amm_pool.clone(),
+            user_token_a.clone(),
+            user_token_b.clone(),
+            amm_token_a.clone(),
+            amm_token_b.clone(),
+            lp_mint.clone(),
+            lp_escrow_account.clone(),
+            token_program.clone(),
+            launchpad_authority.clone(),
+            amm_authority.clone(),
+            amm_program.clone(),
+        ],
+        &[authority_seeds],
+    )?;
+
+    // Record the lock
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            owner_account.key,
+            lp_lock_account.key,
+            rent.minimum_balance(LiquidityLock::LEN),
+            LiquidityLock::LEN as u64,
+            program_id,
+        ),
+        &[
+            owner_account.clone(),
+            lp_lock_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[LP_LOCK_SEED, launchpad_account.key.as_ref(), &[lock_bump]]],
+    )?;
+    let lock = LiquidityLock {
+        launchpad: *launchpad_account.key,
+        beneficiary: config.owner,
+        lp_mint: *lp_mint.key,
+        escrow: *lp_escrow_account.key,
+        unlock_time: current_time.checked_add(config.liquidity_lock_duration).unwrap(),
+    };
+    lock.serialize(&mut *lp_lock_account.data.borrow_mut())?;
+
+    config.is_finalized = true;
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    msg!("Launch finalized: {} lamports and {} tokens added to liquidity", sol_amount, token_amount);
+
+    Ok(())
+}
+
+// Pays SPL contributions out of the payment vault, signed by the launchpad authority
+#[allow(clippy::too_many_arguments)]
+fn transfer_from_payment_vault<'a>(
+    program_id: &Pubkey,
+    launchpad_account: &AccountInfo<'a>,
+    config: &LaunchpadConfig,
+    payment_vault: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    launchpad_authority: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    if *payment_vault.key != config.payment_vault {
+        return Err(LaunchpadError::InvalidPaymentAccount.into());
+    }
+
+    transfer_signed_by_authority(
+        program_id,
+        launchpad_account,
+        payment_vault,
+        destination,
+        launchpad_authority,
+        token_program,
+        amount,
+    )
+}
+
+// Moves tokens out of an account owned by the launchpad authority PDA
+fn transfer_signed_by_authority<'a>(
+    program_id: &Pubkey,
+    launchpad_account: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    launchpad_authority: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let (expected_authority, authority_bump) = Pubkey::find_program_address(
+        &[LAUNCHPAD_AUTHORITY_SEED, launchpad_account.key.as_ref()],
+        program_id,
+    );
+    if expected_authority != *launchpad_authority.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    invoke_signed(
+        &token_instruction::transfer(
+            token_program.key,
+            source.key,
+            destination.key,
+            launchpad_authority.key,
+            &[],
+            amount,
+        )?,
+        &[
+            source.clone(),
+            destination.clone(),
+            launchpad_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&[LAUNCHPAD_AUTHORITY_SEED, launchpad_account.key.as_ref(), &[authority_bump]]],
+    )
+}
+
+fn process_withdraw_locked_liquidity(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let beneficiary_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+    let lp_lock_account = next_account_info(account_info_iter)?;
+    let lp_escrow_account = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    if !beneficiary_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (expected_lock, lock_bump) = Pubkey::find_program_address(
+        &[LP_LOCK_SEED, launchpad_account.key.as_ref()],
+        program_id,
+    );
+    if expected_lock != *lp_lock_account.key || lp_lock_account.owner != program_id {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let lock = LiquidityLock::try_from_slice(&lp_lock_account.data.borrow())?;
+    if lock.beneficiary != *beneficiary_account.key {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+    if lock.escrow != *lp_escrow_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if current_time < lock.unlock_time {
+        return Err(LaunchpadError::LiquidityLocked.into());
+    }
+
+    let amount = spl_token::state::Account::unpack(&lp_escrow_account.data.borrow())?.amount;
+    invoke_signed(
+        &token_instruction::transfer(
+            token_program.key,
+            lp_escrow_account.key,
+            destination_account.key,
+            lp_lock_account.key,
+            &[],
+            amount,
+        )?,
+        &[
+            lp_escrow_account.clone(),
+            destination_account.clone(),
+            lp_lock_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[LP_LOCK_SEED, launchpad_account.key.as_ref(), &[lock_bump]]],
+    )?;
+
+    Ok(())
+}
+
+fn process_update_launchpad_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if launchpad_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if config.owner != *owner_account.key {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+
+    // Parameters are frozen once the sale has started taking contributions
+    if config.is_active || config.total_raised > 0 || config.is_finalized || config.is_cancelled {
+        return Err(LaunchpadError::PresaleStillActive.into());
+    }
+
+    let args = UpdateLaunchpadArgs::try_from_slice(instruction_data)?;
+    if args.min_buy > args.max_buy
+        || args.start_time >= args.end_time
+        || (args.hard_cap > 0 && args.soft_cap > args.hard_cap)
+    {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+    if config.sale_mode == SaleMode::FixedPrice && args.hard_cap == 0 {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+
+    config.price_per_token = args.price_per_token;
+    config.min_buy = args.min_buy;
+    config.max_buy = args.max_buy;
+    config.start_time = args.start_time;
+    config.end_time = args.end_time;
+    config.soft_cap = args.soft_cap;
+    config.hard_cap = args.hard_cap;
+    config.liquidity_percentage = args.liquidity_percentage;
+    config.listing_price = args.listing_price;
+    validate_config(&config)?;
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    msg!("Launchpad parameters updated");
+
+    Ok(())
+}
+
+fn process_emergency_withdraw(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let participant_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+    let participant_info_account = next_account_info(account_info_iter)?;
+
+    if !participant_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_participant_account(
+        program_id,
+        launchpad_account.key,
+        participant_account.key,
+        participant_info_account,
+    )?;
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if !config.is_active || current_time >= config.end_time {
+        return Err(LaunchpadError::PresaleNotActive.into());
+    }
+    // Curve buyers already hold their tokens
+    if config.sale_mode == SaleMode::BondingCurve {
+        return Err(LaunchpadError::InvalidCurve.into());
+    }
+
+    let mut participant_info = Participant::try_from_slice(&participant_info_account.data.borrow())?;
+    if participant_info.wallet != *participant_account.key {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+
+    let contributed = participant_info.amount_contributed;
+    if contributed == 0 {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+    let penalty = (contributed as u128)
+        .checked_mul(config.emergency_withdraw_penalty_bps as u128)
+        .unwrap()
+        .checked_div(10_000)
+        .unwrap() as u64;
+    let refund_amount = contributed.checked_sub(penalty).unwrap();
+
+    if config.is_spl_raise() {
+        let participant_payment_account = next_account_info(account_info_iter)?;
+        let payment_vault = next_account_info(account_info_iter)?;
+        let launchpad_authority = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        transfer_from_payment_vault(
+            program_id,
+            launchpad_account,
+            &config,
+            payment_vault,
+            participant_payment_account,
+            launchpad_authority,
+            token_program,
+            refund_amount,
+        )?;
+    } else {
+        **launchpad_account.lamports.borrow_mut() = launchpad_account
+            .lamports()
+            .checked_sub(refund_amount)
+            .ok_or(LaunchpadError::InvalidAmount)?;
+        **participant_account.lamports.borrow_mut() = participant_account
+            .lamports()
+            .checked_add(refund_amount)
+            .unwrap();
+    }
+
+    // Withdrawn contributions no longer count toward the referrer's volume
+    if participant_info.referrer != Pubkey::default() && config.referral_bps > 0 {
+        let referral_account = next_account_info(account_info_iter)?;
+        let (expected_referral, _) = Pubkey::find_program_address(
+            &[REFERRAL_SEED, launchpad_account.key.as_ref(), participant_info.referrer.as_ref()],
+            program_id,
+        );
+        if expected_referral != *referral_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let mut record = ReferralRecord::try_from_slice(&referral_account.data.borrow())?;
+        record.referred_volume = record.referred_volume.saturating_sub(contributed);
+        record.serialize(&mut *referral_account.data.borrow_mut())?;
+        config.total_referred_volume = config.total_referred_volume.saturating_sub(contributed);
+    }
+
+    // The penalty stays in the raise but no longer backs any tokens
+    config.total_raised = config.total_raised.checked_sub(refund_amount).unwrap();
+    config.penalty_collected = config.penalty_collected.checked_add(penalty).unwrap();
+    config.total_sold = config.total_sold.checked_sub(participant_info.tokens_owed).unwrap();
+    // Frees the refunded amount under the live round's cap; contributions from an earlier
+    // round can exceed what this one has raised
+    if let Some(index) = config.active_round(current_time) {
+        let round = &mut config.rounds[index];
+        round.raised = round.raised.saturating_sub(refund_amount);
+    }
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    participant_info.amount_contributed = 0;
+    participant_info.tokens_owed = 0;
+    participant_info.serialize(&mut *participant_info_account.data.borrow_mut())?;
+
+    msg!("Emergency withdraw: refunded {}, penalty {}", refund_amount, penalty);
+
+    Ok(())
+}
+
+fn process_claim_referral_reward(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let referrer_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+    let referral_account = next_account_info(account_info_iter)?;
+
+    if !referrer_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if !config.has_ended(current_time) {
+        return Err(LaunchpadError::PresaleStillActive.into());
+    }
+    if config.is_cancelled {
+        return Err(LaunchpadError::LaunchCancelled.into());
+    }
+    if config.total_raised < config.soft_cap {
+        return Err(LaunchpadError::SoftCapNotReached.into());
+    }
+    if config.liquidity_percentage > 0 && !config.is_finalized {
+        return Err(LaunchpadError::LiquidityNotAdded.into());
+    }
+
+    let (expected_referral, _) = Pubkey::find_program_address(
+        &[REFERRAL_SEED, launchpad_account.key.as_ref(), referrer_account.key.as_ref()],
+        program_id,
+    );
+    if expected_referral != *referral_account.key || referral_account.owner != program_id {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let mut record = ReferralRecord::try_from_slice(&referral_account.data.borrow())?;
+    if record.claimed {
+        return Err(LaunchpadError::NothingToClaim.into());
+    }
+
+    // Each referrer's share of the pool follows the volume they brought in
+    let reward = (config.referral_pool() as u128)
+        .checked_mul(record.referred_volume as u128)
+        .unwrap()
+        .checked_div(config.total_referred_volume as u128)
+        .unwrap_or(0) as u64;
+    if reward == 0 {
+        return Err(LaunchpadError::NothingToClaim.into());
+    }
+
+    if config.is_spl_raise() {
+        let referrer_payment_account = next_account_info(account_info_iter)?;
+        let payment_vault = next_account_info(account_info_iter)?;
+        let launchpad_authority = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        transfer_from_payment_vault(
+            program_id,
+            launchpad_account,
+            &config,
+            payment_vault,
+            referrer_payment_account,
+            launchpad_authority,
+            token_program,
+            reward,
+        )?;
+    } else {
+        **launchpad_account.lamports.borrow_mut() = launchpad_account
+            .lamports()
+            .checked_sub(reward)
+            .ok_or(LaunchpadError::InvalidAmount)?;
+        **referrer_account.lamports.borrow_mut() = referrer_account
+            .lamports()
+            .checked_add(reward)
+            .unwrap();
+    }
+
+    record.claimed = true;
+    record.serialize(&mut *referral_account.data.borrow_mut())?;
+
+    config.referral_paid = config.referral_paid.checked_add(reward).unwrap();
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_claim_refund_excess(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let participant_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+    let participant_info_account = next_account_info(account_info_iter)?;
+
+    if !participant_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_participant_account(
+        program_id,
+        launchpad_account.key,
+        participant_account.key,
+        participant_info_account,
+    )?;
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if !config.has_ended(current_time) {
+        return Err(LaunchpadError::PresaleStillActive.into());
+    }
+    if config.is_cancelled {
+        return Err(LaunchpadError::LaunchCancelled.into());
+    }
+    if config.total_raised < config.soft_cap {
+        return Err(LaunchpadError::SoftCapNotReached.into());
+    }
+
+    let mut participant_info = Participant::try_from_slice(&participant_info_account.data.borrow())?;
+    if participant_info.wallet != *participant_account.key {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+    if config.sale_mode == SaleMode::FixedPrice || participant_info.allocation_settled {
+        return Err(LaunchpadError::NothingToClaim.into());
+    }
+
+    // Fixes the filled amount and token allocation; ClaimTokens then pays tokens only
+    let refund_amount = settle_allocation(&config, &mut participant_info)?;
+    if refund_amount > 0 {
+        if config.is_spl_raise() {
+            let participant_payment_account = next_account_info(account_info_iter)?;
+            let payment_vault = next_account_info(account_info_iter)?;
+            let launchpad_authority = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            transfer_from_payment_vault(
+                program_id,
+                launchpad_account,
+                &config,
+                payment_vault,
+                participant_payment_account,
+                launchpad_authority,
+                token_program,
+                refund_amount,
+            )?;
+        } else {
+            **launchpad_account.lamports.borrow_mut() = launchpad_account
+                .lamports()
+                .checked_sub(refund_amount)
+                .ok_or(LaunchpadError::InvalidAmount)?;
+            **participant_account.lamports.borrow_mut() = participant_account
+                .lamports()
+                .checked_add(refund_amount)
+                .unwrap();
+        }
+        config.excess_refunded = config.excess_refunded.checked_add(refund_amount).unwrap();
+        config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+    }
+    participant_info.serialize(&mut *participant_info_account.data.borrow_mut())?;
+
+    msg!("Excess contribution refunded: {}", refund_amount);
+
+    Ok(())
+}
+
+fn process_propose_owner(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if launchpad_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if config.owner != *owner_account.key {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+
+    // Proposing the default pubkey cancels a pending transfer
+    config.pending_owner = Pubkey::try_from_slice(instruction_data)?;
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    msg!("Proposed launchpad owner: {}", config.pending_owner);
+
+    Ok(())
+}
+
+fn process_accept_owner(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let new_owner_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+
+    if !new_owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if launchpad_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if config.pending_owner == Pubkey::default() || config.pending_owner != *new_owner_account.key {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+
+    config.owner = config.pending_owner;
+    config.pending_owner = Pubkey::default();
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    msg!("Launchpad ownership transferred to {}", config.owner);
+
+    Ok(())
+}
+
+fn process_add_to_whitelist_batch(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if config.owner != *owner_account.key {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+
+    let batch = Vec::<WhitelistBatchEntry>::try_from_slice(instruction_data)?;
+    if batch.is_empty() || batch.len() > MAX_WHITELIST_BATCH {
+        return Err(LaunchpadError::InvalidAmount.into());
+    }
+
+    // One entry account per pair, in the same order as the instruction data
+    for batch_entry in batch.iter() {
+        if config.tier_system.enabled && batch_entry.tier as usize >= config.tier_system.tiers.len() {
+            return Err(LaunchpadError::InvalidTier.into());
+        }
+        let whitelist_account = next_account_info(account_info_iter)?;
+        write_whitelist_entry(
+            program_id,
+            launchpad_account.key,
+            &mut config,
+            owner_account,
+            whitelist_account,
+            system_program,
+            batch_entry,
+        )?;
+    }
+
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    msg!("Whitelisted {} wallets", batch.len());
+
+    Ok(())
+}
+
+fn process_sweep_cancelled_launch(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+    let token_vault = next_account_info(account_info_iter)?;
+    let owner_token_account = next_account_info(account_info_iter)?;
+    let launchpad_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if config.owner != *owner_account.key {
+        return Err(LaunchpadError::InvalidOwner.into());
+    }
+    if !config.is_cancelled {
+        return Err(LaunchpadError::PresaleNotActive.into());
+    }
+
+    // Everyone has been refunded, or the refund window has closed; penalties and
+    // excess already returned are not owed to anyone
+    let refundable = config
+        .total_raised
+        .checked_sub(config.penalty_collected)
+        .and_then(|refundable| refundable.checked_sub(config.excess_refunded))
+        .ok_or(LaunchpadError::InvalidAmount)?;
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if config.total_refunded < refundable && current_time < config.refund_deadline {
+        return Err(LaunchpadError::RefundsPending.into());
+    }
+
+    // Return the escrowed presale tokens
+    if *token_vault.key != config.token_vault {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let tokens = spl_token::state::Account::unpack(&token_vault.data.borrow())?.amount;
+    if tokens > 0 {
+        transfer_signed_by_authority(
+            program_id,
+            launchpad_account,
+            token_vault,
+            owner_token_account,
+            launchpad_authority,
+            token_program,
+            tokens,
+        )?;
+    }
+
+    if config.is_spl_raise() {
+        let payment_vault = next_account_info(account_info_iter)?;
+        let owner_payment_account = next_account_info(account_info_iter)?;
+        let amount = spl_token::state::Account::unpack(&payment_vault.data.borrow())?.amount;
+        if amount > 0 {
+            transfer_from_payment_vault(
+                program_id,
+                launchpad_account,
+                &config,
+                payment_vault,
+                owner_payment_account,
+                launchpad_authority,
+                token_program,
+                amount,
+            )?;
+        }
+    }
+
+    // Close the launchpad account
+    let dest_starting_lamports = owner_account.lamports();
+    **owner_account.lamports.borrow_mut() = dest_starting_lamports
+        .checked_add(launchpad_account.lamports())
+        .unwrap();
+    **launchpad_account.lamports.borrow_mut() = 0;
+    launchpad_account.data.borrow_mut().fill(0);
+
+    Ok(())
+}
+
+fn process_get_sale_stats(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let launchpad_account = next_account_info(account_info_iter)?;
+
+    if launchpad_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    let phase = if config.is_cancelled {
+        SalePhase::Cancelled
+    } else if config.is_finalized {
+        SalePhase::Finalized
+    } else if config.has_ended(current_time) {
+        if config.total_raised < config.soft_cap {
+            SalePhase::Failed
+        } else {
+            SalePhase::Ended
+        }
+    } else if config.is_active && current_time >= config.start_time {
+        SalePhase::Active
+    } else {
+        SalePhase::Upcoming
+    };
+
+    let stats = SaleStats {
+        total_raised: config.total_raised,
+        total_sold: config.total_sold,
+        participant_count: config.participant_count,
+        remaining_allocation: config.tokens_for_presale.saturating_sub(config.total_sold),
+        phase,
+    };
+    set_return_data(&stats.try_to_vec()?);
+
+    Ok(())
+}
+
+fn process_register_for_lottery(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let participant_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+    let ticket_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !participant_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if launchpad_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if !config.lottery.enabled || config.is_cancelled {
+        return Err(LaunchpadError::InvalidInstruction.into());
+    }
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if config.lottery.drawn || current_time >= config.lottery.registration_end {
+        return Err(LaunchpadError::RegistrationClosed.into());
+    }
+
+    // One ticket per wallet; creating the PDA fails if it already exists
+    let (expected_ticket, ticket_bump) = Pubkey::find_program_address(
+        &[LOTTERY_TICKET_SEED, launchpad_account.key.as_ref(), participant_account.key.as_ref()],
+        program_id,
+    );
+    if expected_ticket != *ticket_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            participant_account.key,
+            ticket_account.key,
+            rent.minimum_balance(LotteryTicket::LEN),
+            LotteryTicket::LEN as u64,
+            program_id,
+        ),
+        &[
+            participant_account.clone(),
+            ticket_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[
+            LOTTERY_TICKET_SEED,
+            launchpad_account.key.as_ref(),
+            participant_account.key.as_ref(),
+            &[ticket_bump],
+        ]],
+    )?;
+
+    let ticket = LotteryTicket {
+        launchpad: *launchpad_account.key,
+        wallet: *participant_account.key,
+        index: config.lottery.registrant_count,
+    };
+    ticket.serialize(&mut *ticket_account.data.borrow_mut())?;
+
+    config.lottery.registrant_count = config
+        .lottery
+        .registrant_count
+        .checked_add(1)
+        .ok_or(LaunchpadError::InvalidAmount)?;
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_draw_lottery(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let caller_account = next_account_info(account_info_iter)?;
+    let launchpad_account = next_account_info(account_info_iter)?;
+    let slot_hashes_account = next_account_info(account_info_iter)?;
+
+    if !caller_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *slot_hashes_account.key != solana_program::sysvar::slot_hashes::ID {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if launchpad_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut config = LaunchpadConfig::try_from_slice(&launchpad_account.data.borrow())?;
+    if !config.lottery.enabled || config.lottery.drawn {
+        return Err(LaunchpadError::InvalidInstruction.into());
+    }
+    let current_time = solana_program::clock::Clock::get()?.unix_timestamp;
+    if current_time < config.lottery.registration_end {
+        return Err(LaunchpadError::PresaleStillActive.into());
+    }
+
+    let current_slot = solana_program::clock::Clock::get()?.slot;
+
+    // First call locks the draw to a future slot. Until the reveal deadline only the
+    // owner can, revealing the secret committed at creation; after it anyone can, so
+    // a withheld reveal cannot block tier-0 buyers
+    if config.lottery.target_slot == 0 {
+        let reveal_deadline =
+            config.lottery.registration_end.saturating_add(LOTTERY_REVEAL_WINDOW);
+        if current_time < reveal_deadline {
+            if config.owner != *caller_account.key {
+                return Err(LaunchpadError::InvalidOwner.into());
+            }
+            let secret = <[u8; 32]>::try_from_slice(instruction_data)?;
+            if keccak::hash(&secret).to_bytes() != config.lottery.commitment {
+                return Err(LaunchpadError::InvalidReveal.into());
+            }
+            config.lottery.secret = secret;
+        }
+        config.lottery.target_slot = current_slot.saturating_add(LOTTERY_DRAW_DELAY_SLOTS);
+        config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+        msg!("Lottery locked to slot {}", config.lottery.target_slot);
+        return Ok(());
+    }
+
+    // Second call, by anyone, draws from the first recorded slot at or after the target
+    if current_slot <= config.lottery.target_slot {
+        return Err(LaunchpadError::LotteryNotReady.into());
+    }
+    let slot_hash = slot_hash_from(&slot_hashes_account.data.borrow(), config.lottery.target_slot)?;
+    let slot_hash = match slot_hash {
+        Some(slot_hash) => slot_hash,
+        None => {
+            // The target fell out of the sysvar's history; lock to a fresh slot
+            config.lottery.target_slot = current_slot.saturating_add(LOTTERY_DRAW_DELAY_SLOTS);
+            config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+            msg!("Lottery relocked to slot {}", config.lottery.target_slot);
+            return Ok(());
+        }
+    };
+
+    config.lottery.seed = keccak::hashv(&[
+        &config.lottery.secret,
+        launchpad_account.key.as_ref(),
+        &slot_hash,
+    ])
+    .to_bytes();
+    config.lottery.drawn = true;
+    config.serialize(&mut *launchpad_account.data.borrow_mut())?;
+
+    msg!(
+        "Lottery drawn: {} winners from {} registrants",
+        config.lottery.winner_count.min(config.lottery.registrant_count),
+        config.lottery.registrant_count
+    );
+
+    Ok(())
+}
+
+// Hash of the oldest slot at or after `target` in SlotHashes data
+// ([len: u64] then [slot: u64][hash: 32 bytes] entries, newest first), or None
+// once the history no longer reaches back to `target`
+fn slot_hash_from(slot_hashes: &[u8], target: u64) -> Result<Option<[u8; 32]>, ProgramError> {
+    let len = slot_hashes
+        .get(..8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ProgramError::InvalidAccountData)? as usize;
+
+    let mut found = None;
+    let mut oldest_slot = u64::MAX;
+    for entry in slot_hashes[8..].chunks_exact(40).take(len) {
+        let slot = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        oldest_slot = slot;
+        if slot < target {
+            break;
+        }
+        found = Some(<[u8; 32]>::try_from(&entry[8..40]).unwrap());
+    }
+
+    if oldest_slot > target {
+        return Ok(None);
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Program id passed to declare_id! in a workspace program's source
+    fn declared_id(source: &str) -> Pubkey {
+        let start = source.find("declare_id!(\"").unwrap() + "declare_id!(\"".len();
+        let end = start + source[start..].find('"').unwrap();
+        source[start..end].parse().unwrap()
+    }
+
+    #[test]
+    fn staking_program_id_matches_the_staking_program() {
+        let staking_source = include_str!("../../staking/src/lib.rs");
+        assert_eq!(STAKING_PROGRAM_ID, declared_id(staking_source));
+    }
+}
@@ -1,14 +1,18 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use num_derive::FromPrimitive;
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
+    account_info::{next_account_info, next_account_infos, AccountInfo},
     clock::Clock,
     entrypoint,
     entrypoint::ProgramResult,
     msg,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
     pubkey::Pubkey,
-    sysvar::Sysvar,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
 };
 use spl_token::state::Account as TokenAccount;
 use thiserror::Error;
@@ -17,30 +21,245 @@ use thiserror::Error;
 solana_program::declare_id!("LendingPool11111111111111111111111111111111");
 pub const FEE_WALLET: &str = "6zkf4DviZZkpWVEh53MrcQV6vGXGpESnNXgAvU6KpBUH";
 pub const SERVICE_FEE_BPS: u64 = 20; // 0.2% fee for lending operations
+pub const CLOSE_FACTOR_BPS: u64 = 5000; // Max 50% of debt repaid per liquidation
+pub const PRICE_SCALE: u64 = 1_000_000_000; // Fixed-point scale for collateral prices
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+pub const INDEX_SCALE: u128 = 1_000_000_000_000_000_000; // Fixed-point scale for interest indices
+pub const VAULT_AUTHORITY_SEED: &[u8] = b"vault_authority";
+pub const DELEGATION_SEED: &[u8] = b"delegation";
+pub const LENDING_POOL_SEED: &[u8] = b"lending_pool";
+pub const USER_SEED: &[u8] = b"user";
+pub const FIXED_LOAN_SEED: &[u8] = b"fixed_loan";
+pub const FIXED_RATE_PREMIUM_BPS: u64 = 200; // Fixed loans pay 2% over the variable rate
+pub const STABLE_RATE_PREMIUM_BPS: u64 = 100; // Stable borrows pay 1% over the variable rate
+pub const STABLE_REBALANCE_THRESHOLD_BPS: u64 = 500; // Market lead that allows a rebalance
+pub const EARLY_REPAYMENT_PENALTY_BPS: u64 = 100; // 1% of principal when repaid before maturity
+pub const OVERDUE_PENALTY_BPS: u64 = 500; // Overdue fixed loans pay 5% over the variable rate
+pub const MIN_FIXED_TERM: i64 = 24 * 60 * 60; // 1 day
+pub const MAX_FIXED_TERM: i64 = 365 * 24 * 60 * 60; // 1 year
+pub const COLLATERAL_ASSET_SEED: &[u8] = b"collateral_asset";
+pub const MAX_COLLATERAL_POSITIONS: usize = 4;
+pub const MAX_PRICE_OBSERVATIONS: usize = 16;
+pub const KEEPER_TIP_INTERVAL: i64 = 24 * 60 * 60; // At most one keeper tip per position per day
+
+// Workspace liquidity-pool program whose accumulators back TWAP collateral prices
+pub const LIQUIDITY_POOL_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("LiquidityPoo1111111111111111111111111111111");
 
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
 pub struct LendingPool {
     pub is_initialized: bool,
     pub token_mint: Pubkey,
     pub pool_authority: Pubkey,
+    pub guardian: Pubkey,         // Can pause deposits, withdrawals and borrows during incidents
+    pub is_paused: bool,
+    pub vault_authority_bump: u8, // Bump of the PDA that owns the pool vaults and receipt mint
     pub lending_token_account: Pubkey,
+    pub receipt_mint: Pubkey,  // Interest-bearing deposit receipt (cToken) mint
     pub total_deposits: u64,
     pub total_borrows: u64,
+    pub total_fixed_borrows: u64, // Principal outstanding on fixed-rate loans
+    pub total_stable_borrows: u64, // Stable-rate debt including accrued interest
+    pub average_stable_rate: u64,  // Debt-weighted stable rate across all users (basis points)
+    pub total_reserves: u64,   // Protocol share of borrower interest held in the vault
+    pub unpaid_interest: u64,  // Borrower interest accrued but not yet repaid
+    pub funded_interest: u64,  // Supplier share of repaid interest available to claim
     pub last_update_time: i64,
+    pub borrow_index: u128,    // Cumulative borrow interest factor (scaled by INDEX_SCALE)
+    pub supply_index: u128,    // Cumulative supplier interest factor (scaled by INDEX_SCALE)
     pub lending_rate: u64,     // Lending interest rate (basis points)
     pub borrowing_rate: u64,   // Borrowing interest rate (basis points)
     pub collateral_ratio: u64, // Required collateral ratio (percentage * 100)
+    pub reserve_factor: u64,   // Share of borrower interest kept as reserves (basis points)
+    pub liquidation_threshold_bps: u64, // Collateral ratio below which positions can be liquidated
+    pub liquidation_bonus_bps: u64,     // Maximum collateral discount reached by the liquidation auction
+    pub auction_start_bonus_bps: u64,   // Discount offered when the auction opens
+    pub auction_duration: i64,          // Seconds for the discount to ramp up to the maximum
+    pub liquidation_grace_period: i64,  // Seconds a marked position has to recover before liquidation
+    pub hard_floor_bps: u64,            // Collateral ratio below which the grace period is skipped
+    pub keeper_tip: u64,                // Paid from reserves to whoever marks a position
+    pub max_price_age: i64,             // Seconds before a collateral price is considered stale
+    pub max_confidence_bps: u64,        // Largest accepted confidence interval relative to price
+    pub max_borrow_per_user: u64,       // Ceiling on each position's total debt; u64::MAX is unlimited
+    pub rate_model: InterestRateModel,
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct InterestRateModel {
+    pub base_rate: u64,           // Borrow rate at 0% utilization (basis points)
+    pub slope1: u64,              // Rate increase up to optimal utilization (basis points)
+    pub slope2: u64,              // Rate increase above optimal utilization (basis points)
+    pub optimal_utilization: u64, // Kink point (basis points)
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct InitializeParams {
+    pub rate_model: InterestRateModel,
+    pub reserve_factor: u64,
+    pub liquidation: LiquidationParams,
+    pub guardian: Pubkey,
+    pub max_price_age: i64,
+    pub max_confidence_bps: u64,
+    pub max_borrow_per_user: Option<u64>,
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct LiquidationParams {
+    pub liquidation_threshold_bps: u64,
+    pub liquidation_bonus_bps: u64,
+    pub auction_start_bonus_bps: u64,
+    pub auction_duration: i64,
+    pub grace_period: i64,
+    pub hard_floor_bps: u64,
+    pub keeper_tip: u64,
 }
 
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
 pub struct UserLendingInfo {
+    pub wallet: Pubkey,         // Wallet the account address is derived from
+    pub bump: u8,
     pub owner: Pubkey,
-    pub deposited_amount: u64,
-    pub borrowed_amount: u64,
-    pub collateral_amount: u64,
+    pub deposited_amount: u64,  // Denominated in the pool token mint
+    pub borrowed_amount: u64,   // Denominated in the pool token mint
+    pub fixed_borrowed_amount: u64, // Principal across open fixed-rate loans
+    pub stable_borrowed_amount: u64, // Stable-rate debt including accrued interest
+    pub stable_rate: u64,            // Rate snapshotted for stable debt (basis points)
     pub last_update_time: i64,
+    pub borrow_index: u128, // Pool borrow index when borrowed_amount was last updated
+    pub supply_index: u128, // Pool supply index when deposited_amount was last updated
     pub cumulative_deposit_interest: u64,
     pub cumulative_borrow_interest: u64,
+    pub unpaid_borrow_interest: u64, // Portion of borrowed_amount that is accrued interest
+    pub marked_for_liquidation_at: i64, // 0 while the position is not flagged
+    pub last_tip_paid_at: i64,          // Kept when a mark clears so re-marking cannot farm tips
+    pub position_mint: Option<Pubkey>,  // Set once the position is represented by an NFT
+    pub collateral: Vec<CollateralPosition>,
+}
+
+impl UserLendingInfo {
+    // Space for a position holding the maximum number of collateral entries
+    pub const LEN: usize = 32 + 1 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 16 + 16 + 8 + 8 + 8 + 8 + 8 + 33
+        + 4 + MAX_COLLATERAL_POSITIONS * CollateralPosition::LEN;
+
+    // Accounts are sized for MAX_COLLATERAL_POSITIONS, so trailing bytes are expected
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Ok(Self::deserialize(&mut &data[..])?)
+    }
+
+    fn position_mut(&mut self, mint: &Pubkey) -> Option<&mut CollateralPosition> {
+        self.collateral.iter_mut().find(|position| position.mint == *mint)
+    }
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct CollateralPosition {
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub amount: u64,
+}
+
+impl CollateralPosition {
+    pub const LEN: usize = 32 + 32 + 8;
+}
+
+// Collateral mint accepted by a pool, together with its price feed
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct CollateralAsset {
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub price_authority: Pubkey, // Oracle signer allowed to publish prices
+    pub price: u64,              // Borrow-token units per collateral token (scaled by PRICE_SCALE)
+    pub confidence: u64,         // Price uncertainty in the same units as price
+    pub last_price_update: i64,
+    pub total_amount: u64,
+    pub twap_window: i64,          // Seconds averaged over; 0 values collateral at spot price
+    pub twap_pool: Pubkey,         // Liquidity pool whose price accumulator is observed
+    pub twap_program: Pubkey,      // Program that owns twap_pool
+    pub twap_collateral_is_a: bool, // Whether the collateral mint is the pool's token A
+    pub observation_index: u8,     // Slot of the most recent observation
+    pub observations: Vec<PriceObservation>,
+}
+
+impl CollateralAsset {
+    // Space for an asset with a full observation buffer
+    pub const LEN: usize = 32 + 32 + 32 + 32 + 8 + 8 + 8 + 8
+        + 8 + 32 + 32 + 1 + 1
+        + 4 + MAX_PRICE_OBSERVATIONS * PriceObservation::LEN;
+
+    // Accounts are sized for a full buffer, so trailing bytes are expected
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Ok(Self::deserialize(&mut &data[..])?)
+    }
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct PriceObservation {
+    pub timestamp: i64,
+    pub price_cumulative: u128,
+}
+
+impl PriceObservation {
+    pub const LEN: usize = 8 + 16;
+}
+
+// Leading fields of the liquidity-pool program's PoolState, read when
+// sampling its price accumulators
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct LiquidityPoolState {
+    pub is_initialized: bool,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub token_a_account: Pubkey,
+    pub token_b_account: Pubkey,
+    pub pool_mint: Pubkey,
+    pub token_a_amount: u64,
+    pub token_b_amount: u64,
+    pub fee_rate: u64,
+    pub price_a_cumulative: u128,
+    pub price_b_cumulative: u128,
+    pub last_update_time: i64,
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct PriceUpdate {
+    pub price: u64,
+    pub confidence: u64,
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct BorrowDelegation {
+    pub pool: Pubkey,
+    pub delegator: Pubkey,
+    pub delegate: Pubkey,
+    pub borrow_allowance: u64, // Remaining amount the delegate may borrow against the delegator's collateral
+}
+
+impl BorrowDelegation {
+    pub const LEN: usize = 32 + 32 + 32 + 8;
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct FixedLoan {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub loan_id: u64,
+    pub principal: u64,
+    pub fixed_rate: u64, // Annual rate locked at origination (basis points)
+    pub start_time: i64,
+    pub maturity_time: i64,
+    pub early_repayment_penalty_bps: u64,
+}
+
+impl FixedLoan {
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 8 + 8 + 8 + 8;
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct FixedBorrowArgs {
+    pub loan_id: u64,
+    pub amount: u64,
+    pub term: i64, // Loan duration in seconds
 }
 
 #[derive(FromPrimitive, Debug)]
@@ -53,6 +272,24 @@ pub enum LendingInstruction {
     AddCollateral,
     WithdrawCollateral,
     LiquidatePosition,
+    WithdrawReserves,
+    UpdateLiquidationParams,
+    DelegateBorrowPower,
+    BorrowFixed,
+    RepayFixed,
+    RolloverFixedLoan,
+    Pause,
+    Unpause,
+    ClaimInterest,
+    AddCollateralAsset,
+    UpdatePrice,
+    MarkForLiquidation,
+    CloseUserAccount,
+    TokenizePosition,
+    ConfigureTwap,
+    RecordPriceObservation,
+    SetBorrowLimit,
+    RebalanceStableRate,
 }
 
 #[derive(Error, Debug, Copy, Clone)]
@@ -71,6 +308,48 @@ pub enum LendingError {
     InsufficientLiquidity,
     #[error("Position not liquidatable")]
     PositionNotLiquidatable,
+    #[error("Invalid interest rate model")]
+    InvalidRateModel,
+    #[error("Invalid pool authority")]
+    InvalidAuthority,
+    #[error("Insufficient reserves")]
+    InsufficientReserves,
+    #[error("Invalid liquidation parameters")]
+    InvalidLiquidationParams,
+    #[error("Invalid position owner")]
+    InvalidOwner,
+    #[error("Borrow allowance exceeded")]
+    DelegationLimitExceeded,
+    #[error("Invalid loan term")]
+    InvalidLoanTerm,
+    #[error("Loan not matured")]
+    LoanNotMatured,
+    #[error("Pool is paused")]
+    PoolPaused,
+    #[error("Too many collateral positions")]
+    TooManyCollateralPositions,
+    #[error("Missing collateral price account")]
+    MissingCollateralPrice,
+    #[error("Position already marked for liquidation")]
+    AlreadyMarked,
+    #[error("Liquidation grace period has not elapsed")]
+    GracePeriodActive,
+    #[error("Account still holds balances")]
+    NonZeroBalance,
+    #[error("Position is already tokenized")]
+    PositionTokenized,
+    #[error("Oracle price is stale")]
+    StaleOracle,
+    #[error("Oracle price confidence is too wide")]
+    UntrustedPrice,
+    #[error("Not enough price observations for the TWAP window")]
+    InsufficientObservations,
+    #[error("Per-user borrow limit exceeded")]
+    BorrowLimitExceeded,
+    #[error("Pool liabilities exceed its assets")]
+    PoolInsolvent,
+    #[error("Stable rate is within the rebalance threshold")]
+    RebalanceNotAllowed,
 }
 
 impl From<LendingError> for ProgramError {
@@ -92,7 +371,7 @@ pub fn process_instruction(
     match instruction {
         LendingInstruction::Initialize => {
             msg!("Instruction: Initialize Lending Pool");
-            process_initialize(program_id, accounts)
+            process_initialize(program_id, accounts, &instruction_data[1..])
         }
         LendingInstruction::Deposit => {
             msg!("Instruction: Deposit Tokens");
@@ -122,36 +401,204 @@ pub fn process_instruction(
             msg!("Instruction: Liquidate Position");
             process_liquidate_position(program_id, accounts, &instruction_data[1..])
         }
+        LendingInstruction::WithdrawReserves => {
+            msg!("Instruction: Withdraw Reserves");
+            process_withdraw_reserves(program_id, accounts, &instruction_data[1..])
+        }
+        LendingInstruction::UpdateLiquidationParams => {
+            msg!("Instruction: Update Liquidation Params");
+            process_update_liquidation_params(program_id, accounts, &instruction_data[1..])
+        }
+        LendingInstruction::DelegateBorrowPower => {
+            msg!("Instruction: Delegate Borrow Power");
+            process_delegate_borrow_power(program_id, accounts, &instruction_data[1..])
+        }
+        LendingInstruction::BorrowFixed => {
+            msg!("Instruction: Borrow Fixed");
+            process_borrow_fixed(program_id, accounts, &instruction_data[1..])
+        }
+        LendingInstruction::RepayFixed => {
+            msg!("Instruction: Repay Fixed");
+            process_repay_fixed(program_id, accounts)
+        }
+        LendingInstruction::RolloverFixedLoan => {
+            msg!("Instruction: Rollover Fixed Loan");
+            process_rollover_fixed_loan(program_id, accounts, &instruction_data[1..])
+        }
+        LendingInstruction::Pause => {
+            msg!("Instruction: Pause Pool");
+            process_set_paused(program_id, accounts, true)
+        }
+        LendingInstruction::Unpause => {
+            msg!("Instruction: Unpause Pool");
+            process_set_paused(program_id, accounts, false)
+        }
+        LendingInstruction::ClaimInterest => {
+            msg!("Instruction: Claim Interest");
+            process_claim_interest(program_id, accounts)
+        }
+        LendingInstruction::AddCollateralAsset => {
+            msg!("Instruction: Add Collateral Asset");
+            process_add_collateral_asset(program_id, accounts, &instruction_data[1..])
+        }
+        LendingInstruction::UpdatePrice => {
+            msg!("Instruction: Update Price");
+            process_update_price(program_id, accounts, &instruction_data[1..])
+        }
+        LendingInstruction::MarkForLiquidation => {
+            msg!("Instruction: Mark For Liquidation");
+            process_mark_for_liquidation(program_id, accounts)
+        }
+        LendingInstruction::CloseUserAccount => {
+            msg!("Instruction: Close User Account");
+            process_close_user_account(program_id, accounts)
+        }
+        LendingInstruction::TokenizePosition => {
+            msg!("Instruction: Tokenize Position");
+            process_tokenize_position(program_id, accounts)
+        }
+        LendingInstruction::ConfigureTwap => {
+            msg!("Instruction: Configure TWAP");
+            process_configure_twap(program_id, accounts, &instruction_data[1..])
+        }
+        LendingInstruction::RecordPriceObservation => {
+            msg!("Instruction: Record Price Observation");
+            process_record_price_observation(program_id, accounts)
+        }
+        LendingInstruction::SetBorrowLimit => {
+            msg!("Instruction: Set Borrow Limit");
+            process_set_borrow_limit(program_id, accounts, &instruction_data[1..])
+        }
+        LendingInstruction::RebalanceStableRate => {
+            msg!("Instruction: Rebalance Stable Rate");
+            process_rebalance_stable_rate(program_id, accounts)
+        }
     }
 }
 
 fn process_initialize(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
+    instruction_data: &[u8],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let pool_account = next_account_info(account_info_iter)?;
     let token_mint = next_account_info(account_info_iter)?;
     let pool_authority = next_account_info(account_info_iter)?;
     let lending_token_account = next_account_info(account_info_iter)?;
+    let receipt_mint = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let payer_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
 
-    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
-    if pool.is_initialized {
+    if !payer_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // Each mint gets exactly one isolated market at its canonical address
+    let (expected_pool, pool_bump) = Pubkey::find_program_address(
+        &[LENDING_POOL_SEED, token_mint.key.as_ref()],
+        program_id,
+    );
+    if expected_pool != *pool_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !pool_account.data_is_empty() {
         return Err(LendingError::AlreadyInUse.into());
     }
 
-    pool.is_initialized = true;
-    pool.token_mint = *token_mint.key;
-    pool.pool_authority = *pool_authority.key;
-    pool.lending_token_account = *lending_token_account.key;
-    pool.total_deposits = 0;
-    pool.total_borrows = 0;
-    pool.last_update_time = Clock::get()?.unix_timestamp;
-    pool.lending_rate = 500;     // 5% APY
-    pool.borrowing_rate = 1000;  // 10% APR
-    pool.collateral_ratio = 15000; // 150%
+    let (expected_vault_authority, vault_authority_bump) = Pubkey::find_program_address(
+        &[VAULT_AUTHORITY_SEED, pool_account.key.as_ref()],
+        program_id,
+    );
+    if expected_vault_authority != *vault_authority.key {
+        return Err(LendingError::InvalidAuthority.into());
+    }
 
-    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    let params = InitializeParams::try_from_slice(instruction_data)?;
+    let rate_model = params.rate_model;
+    if rate_model.optimal_utilization == 0 || rate_model.optimal_utilization >= 10000 {
+        return Err(LendingError::InvalidRateModel.into());
+    }
+    if params.reserve_factor > 10000 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if params.max_price_age <= 0 || params.max_confidence_bps > 10000 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let collateral_ratio = 15000; // 150%
+    validate_liquidation_params(collateral_ratio, &params.liquidation)?;
+
+    // The vault and the receipt mint must be controlled by the vault authority PDA
+    let lending_vault = TokenAccount::unpack(&lending_token_account.data.borrow())?;
+    if lending_vault.owner != expected_vault_authority || lending_vault.mint != *token_mint.key {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+    let receipt = spl_token::state::Mint::unpack(&receipt_mint.data.borrow())?;
+    if receipt.mint_authority != COption::Some(expected_vault_authority) {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let pool = LendingPool {
+        is_initialized: true,
+        token_mint: *token_mint.key,
+        pool_authority: *pool_authority.key,
+        guardian: params.guardian,
+        is_paused: false,
+        vault_authority_bump,
+        lending_token_account: *lending_token_account.key,
+        receipt_mint: *receipt_mint.key,
+        total_deposits: 0,
+        total_borrows: 0,
+        total_fixed_borrows: 0,
+        total_stable_borrows: 0,
+        average_stable_rate: 0,
+        total_reserves: 0,
+        unpaid_interest: 0,
+        funded_interest: 0,
+        last_update_time: Clock::get()?.unix_timestamp,
+        borrow_index: INDEX_SCALE,
+        supply_index: INDEX_SCALE,
+        lending_rate: 0,
+        borrowing_rate: rate_model.base_rate,
+        collateral_ratio,
+        reserve_factor: params.reserve_factor,
+        liquidation_threshold_bps: params.liquidation.liquidation_threshold_bps,
+        liquidation_bonus_bps: params.liquidation.liquidation_bonus_bps,
+        auction_start_bonus_bps: params.liquidation.auction_start_bonus_bps,
+        auction_duration: params.liquidation.auction_duration,
+        liquidation_grace_period: params.liquidation.grace_period,
+        hard_floor_bps: params.liquidation.hard_floor_bps,
+        keeper_tip: params.liquidation.keeper_tip,
+        max_price_age: params.max_price_age,
+        max_confidence_bps: params.max_confidence_bps,
+        max_borrow_per_user: params.max_borrow_per_user.unwrap_or(u64::MAX),
+        rate_model,
+    };
+    let pool_data = pool.try_to_vec()?;
+
+    // Create the pool account at its PDA
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer_account.key,
+            pool_account.key,
+            rent.minimum_balance(pool_data.len()),
+            pool_data.len() as u64,
+            program_id,
+        ),
+        &[
+            payer_account.clone(),
+            pool_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[LENDING_POOL_SEED, token_mint.key.as_ref(), &[pool_bump]]],
+    )?;
+
+    pool_account.data.borrow_mut().copy_from_slice(&pool_data);
+
+    msg!("Created lending market {} for mint {}", pool_account.key, token_mint.key);
 
     Ok(())
 }
@@ -164,11 +611,20 @@ fn process_deposit(
     let account_info_iter = &mut accounts.iter();
     let pool_account = next_account_info(account_info_iter)?;
     let user_lending_info = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
     let user_token_account = next_account_info(account_info_iter)?;
     let pool_token_account = next_account_info(account_info_iter)?;
+    let receipt_mint = next_account_info(account_info_iter)?;
+    let user_receipt_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
     let clock = Clock::get()?;
 
+    if !user_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
     let amount = {
         let mut data = [0u8; 8];
         data.copy_from_slice(&instruction_data[..8]);
@@ -176,38 +632,64 @@ fn process_deposit(
     };
 
     let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
-    let mut user_info = if user_lending_info.data_len() > 0 {
-        UserLendingInfo::try_from_slice(&user_lending_info.data.borrow())?
+    if pool.is_paused {
+        return Err(LendingError::PoolPaused.into());
+    }
+    if *pool_token_account.key != pool.lending_token_account
+        || *receipt_mint.key != pool.receipt_mint
+    {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut user_info = if user_lending_info.data_is_empty() {
+        create_user_info(
+            program_id,
+            pool_account,
+            &pool,
+            user_lending_info,
+            user_authority,
+            system_program,
+            clock.unix_timestamp,
+        )?
     } else {
-        UserLendingInfo {
-            owner: *user_token_account.key,
-            deposited_amount: 0,
-            borrowed_amount: 0,
-            collateral_amount: 0,
-            last_update_time: clock.unix_timestamp,
-            cumulative_deposit_interest: 0,
-            cumulative_borrow_interest: 0,
-        }
+        let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+        check_position_owner(&mut user_info, user_authority, account_info_iter)?;
+        user_info
     };
 
     // Update interest before deposit
     update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
 
     // Transfer tokens to pool
-    spl_token::instruction::transfer(
-        token_program.key,
-        user_token_account.key,
-        pool_token_account.key,
-        &user_token_account.key,
-        &[],
+    transfer_to_vault(
+        user_authority,
+        user_token_account,
+        pool_token_account,
+        token_program,
         amount,
     )?;
 
-    user_info.deposited_amount = user_info.deposited_amount.checked_add(amount)
-        .ok_or(ProgramError::Overflow)?;
-    pool.total_deposits = pool.total_deposits.checked_add(amount)
-        .ok_or(ProgramError::Overflow)?;
+    // Mint receipt tokens at the current exchange rate
+    let receipt_supply = spl_token::state::Mint::unpack(&receipt_mint.data.borrow())?.supply;
+    let receipt_amount = deposit_to_receipt(&pool, amount, receipt_supply)?;
+    if receipt_amount == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    check_vault_authority(program_id, pool_account, &pool, vault_authority)?;
+    mint_receipt(
+        pool_account,
+        &pool,
+        vault_authority,
+        receipt_mint,
+        user_receipt_account,
+        token_program,
+        receipt_amount,
+    )?;
+
+    record_deposit(&mut pool, &mut user_info, amount)?;
 
+    check_solvency(&pool, pool_token_account)?;
     pool.serialize(&mut *pool_account.data.borrow_mut())?;
     user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
 
@@ -222,55 +704,134 @@ fn process_withdraw(
     let account_info_iter = &mut accounts.iter();
     let pool_account = next_account_info(account_info_iter)?;
     let user_lending_info = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
     let user_token_account = next_account_info(account_info_iter)?;
     let pool_token_account = next_account_info(account_info_iter)?;
+    let receipt_mint = next_account_info(account_info_iter)?;
+    let user_receipt_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
     let clock = Clock::get()?;
 
-    let amount = {
+    if !user_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // Amount of receipt tokens to redeem
+    let receipt_amount = {
         let mut data = [0u8; 8];
         data.copy_from_slice(&instruction_data[..8]);
         u64::from_le_bytes(data)
     };
 
     let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
-    let mut user_info = UserLendingInfo::try_from_slice(&user_lending_info.data.borrow())?;
+    if pool.is_paused {
+        return Err(LendingError::PoolPaused.into());
+    }
+    if *pool_token_account.key != pool.lending_token_account
+        || *receipt_mint.key != pool.receipt_mint
+    {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+    check_position_owner(&mut user_info, user_authority, account_info_iter)?;
 
-    // Update interest before withdrawal
+    // Update interest before withdrawal so receipts redeem at the current exchange rate
     update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
 
-    // Check if user has enough available balance
-    if amount > user_info.deposited_amount {
+    // Check if user holds enough receipt tokens
+    let user_receipts = TokenAccount::unpack(&user_receipt_account.data.borrow())?.amount;
+    if receipt_amount > user_receipts {
         return Err(LendingError::InsufficientLiquidity.into());
     }
 
-    // Check collateral ratio after withdrawal
-    let remaining_deposit = user_info.deposited_amount.checked_sub(amount)
-        .ok_or(ProgramError::Overflow)?;
-    if !check_collateral_ratio(&pool, remaining_deposit, user_info.borrowed_amount) {
-        return Err(LendingError::InsufficientCollateral.into());
+    let receipt_supply = spl_token::state::Mint::unpack(&receipt_mint.data.borrow())?.supply;
+    let amount = receipt_to_deposit(&pool, receipt_amount, receipt_supply)?;
+
+    // Deposits are not collateral, but borrowed funds cannot be withdrawn
+    if amount > available_liquidity(&pool)? {
+        return Err(LendingError::InsufficientLiquidity.into());
     }
 
+    // Burn redeemed receipt tokens
+    invoke(
+        &spl_token::instruction::burn(
+            token_program.key,
+            user_receipt_account.key,
+            receipt_mint.key,
+            user_authority.key,
+            &[],
+            receipt_amount,
+        )?,
+        &[
+            user_receipt_account.clone(),
+            receipt_mint.clone(),
+            user_authority.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
     // Transfer tokens back to user
-    spl_token::instruction::transfer(
-        token_program.key,
-        pool_token_account.key,
-        user_token_account.key,
-        &pool_account.key,
-        &[],
+    check_vault_authority(program_id, pool_account, &pool, vault_authority)?;
+    transfer_from_vault(
+        pool_account,
+        &pool,
+        vault_authority,
+        pool_token_account,
+        user_token_account,
+        token_program,
         amount,
     )?;
 
-    user_info.deposited_amount = remaining_deposit;
-    pool.total_deposits = pool.total_deposits.checked_sub(amount)
-        .ok_or(ProgramError::Overflow)?;
+    record_withdrawal(&mut pool, &mut user_info, amount)?;
 
+    check_solvency(&pool, pool_token_account)?;
     pool.serialize(&mut *pool_account.data.borrow_mut())?;
     user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
 
     Ok(())
 }
 
+fn record_deposit(pool: &mut LendingPool, user: &mut UserLendingInfo, amount: u64) -> ProgramResult {
+    user.deposited_amount = user.deposited_amount.checked_add(amount)
+        .ok_or(ProgramError::Overflow)?;
+    pool.total_deposits = pool.total_deposits.checked_add(amount)
+        .ok_or(ProgramError::Overflow)?;
+
+    Ok(())
+}
+
+// Books a withdrawal against the position and the pool. Receipts are transferable, so
+// the redeemed amount can exceed the recorded deposit; the deposit interest leaving with
+// it stops being claimable.
+fn record_withdrawal(
+    pool: &mut LendingPool,
+    user: &mut UserLendingInfo,
+    amount: u64,
+) -> ProgramResult {
+    let withdrawn = amount.min(user.deposited_amount);
+    let interest = if user.deposited_amount == 0 {
+        0
+    } else {
+        (user.cumulative_deposit_interest as u128)
+            .checked_mul(withdrawn as u128)
+            .ok_or(ProgramError::Overflow)?
+            .checked_div(user.deposited_amount as u128)
+            .ok_or(ProgramError::Overflow)? as u64
+    };
+
+    user.deposited_amount = user.deposited_amount.checked_sub(withdrawn)
+        .ok_or(ProgramError::Overflow)?;
+    user.cumulative_deposit_interest = user.cumulative_deposit_interest
+        .saturating_sub(interest);
+    pool.funded_interest = pool.funded_interest.saturating_sub(interest);
+    pool.total_deposits = pool.total_deposits.checked_sub(amount)
+        .ok_or(ProgramError::Overflow)?;
+
+    Ok(())
+}
+
 fn process_borrow(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -279,12 +840,18 @@ fn process_borrow(
     let account_info_iter = &mut accounts.iter();
     let pool_account = next_account_info(account_info_iter)?;
     let user_lending_info = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?; // Position owner or delegate
     let user_token_account = next_account_info(account_info_iter)?;
     let pool_token_account = next_account_info(account_info_iter)?;
     let fee_wallet_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
     let clock = Clock::get()?;
 
+    if !user_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
     // Verify fee wallet
     if fee_wallet_account.key.to_string() != FEE_WALLET {
         return Err(ProgramError::InvalidArgument);
@@ -295,26 +862,74 @@ fn process_borrow(
         data.copy_from_slice(&instruction_data[..8]);
         u64::from_le_bytes(data)
     };
+    // An optional trailing byte of 1 borrows at a stable rate
+    let stable = instruction_data.get(8) == Some(&1);
 
     let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
-    let mut user_info = UserLendingInfo::try_from_slice(&user_lending_info.data.borrow())?;
+    if pool.is_paused {
+        return Err(LendingError::PoolPaused.into());
+    }
+    if *pool_token_account.key != pool.lending_token_account {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+
+    let owner = position_owner(&user_info, account_info_iter)?;
+    user_info.owner = owner;
+
+    // Collateral asset accounts for each position entry, followed by the
+    // optional delegation account when borrowing against another user's collateral
+    let asset_accounts = next_account_infos(account_info_iter, user_info.collateral.len())?;
+    let delegation_account = next_account_info(account_info_iter).ok();
 
     // Update interest before borrowing
     update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
 
     // Check if pool has enough liquidity
-    if amount > pool.total_deposits.checked_sub(pool.total_borrows)
-        .ok_or(ProgramError::Overflow)? {
+    if amount > available_liquidity(&pool)? {
         return Err(LendingError::InsufficientLiquidity.into());
     }
 
     // Check if user has enough collateral
-    let new_borrow_amount = user_info.borrowed_amount.checked_add(amount)
+    let new_total_debt = total_debt(&user_info)?.checked_add(amount)
         .ok_or(ProgramError::Overflow)?;
-    if !check_collateral_ratio(&pool, user_info.deposited_amount, new_borrow_amount) {
+    check_borrow_limit(&pool, new_total_debt)?;
+    let collateral_value = position_collateral_value(program_id, pool_account, &pool, &user_info, asset_accounts, clock.unix_timestamp)?;
+    if !check_collateral_ratio(&pool, collateral_value, new_total_debt)? {
         return Err(LendingError::InsufficientCollateral.into());
     }
 
+    // Delegated borrows consume the allowance granted by the position owner
+    if let Some(delegation_account) = delegation_account {
+        if delegation_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let (expected_delegation, _) = Pubkey::find_program_address(
+            &[
+                DELEGATION_SEED,
+                pool_account.key.as_ref(),
+                owner.as_ref(),
+                user_authority.key.as_ref(),
+            ],
+            program_id,
+        );
+        if expected_delegation != *delegation_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        let mut delegation = BorrowDelegation::try_from_slice(&delegation_account.data.borrow())?;
+        if amount > delegation.borrow_allowance {
+            return Err(LendingError::DelegationLimitExceeded.into());
+        }
+        delegation.borrow_allowance = delegation.borrow_allowance.checked_sub(amount)
+            .ok_or(ProgramError::Overflow)?;
+        delegation.serialize(&mut *delegation_account.data.borrow_mut())?;
+    } else if owner != *user_authority.key {
+        return Err(LendingError::InvalidOwner.into());
+    }
+
     // Calculate service fee
     let fee_amount = amount
         .checked_mul(SERVICE_FEE_BPS)
@@ -324,30 +939,50 @@ fn process_borrow(
     let user_borrow_amount = amount.checked_sub(fee_amount)
         .ok_or(ProgramError::Overflow)?;
 
+    check_vault_authority(program_id, pool_account, &pool, vault_authority)?;
+
     // Transfer tokens to user
-    spl_token::instruction::transfer(
-        token_program.key,
-        pool_token_account.key,
-        user_token_account.key,
-        &pool_account.key,
-        &[],
+    transfer_from_vault(
+        pool_account,
+        &pool,
+        vault_authority,
+        pool_token_account,
+        user_token_account,
+        token_program,
         user_borrow_amount,
     )?;
 
     // Transfer fee to fee wallet
-    spl_token::instruction::transfer(
-        token_program.key,
-        pool_token_account.key,
-        fee_wallet_account.key,
-        &pool_account.key,
-        &[],
+    transfer_from_vault(
+        pool_account,
+        &pool,
+        vault_authority,
+        pool_token_account,
+        fee_wallet_account,
+        token_program,
         fee_amount,
     )?;
 
-    user_info.borrowed_amount = new_borrow_amount;
-    pool.total_borrows = pool.total_borrows.checked_add(amount)
-        .ok_or(ProgramError::Overflow)?;
+    if stable {
+        // Snapshot the current market rate plus premium, blended with any
+        // existing stable debt
+        let rate = pool.borrowing_rate.checked_add(STABLE_RATE_PREMIUM_BPS)
+            .ok_or(ProgramError::Overflow)?;
+        let new_stable_amount = user_info.stable_borrowed_amount.checked_add(amount)
+            .ok_or(ProgramError::Overflow)?;
+        user_info.stable_rate = ((user_info.stable_borrowed_amount as u128 * user_info.stable_rate as u128
+            + amount as u128 * rate as u128)
+            / new_stable_amount as u128) as u64;
+        user_info.stable_borrowed_amount = new_stable_amount;
+        add_stable_borrow(&mut pool, amount, rate)?;
+    } else {
+        user_info.borrowed_amount = user_info.borrowed_amount.checked_add(amount)
+            .ok_or(ProgramError::Overflow)?;
+        pool.total_borrows = pool.total_borrows.checked_add(amount)
+            .ok_or(ProgramError::Overflow)?;
+    }
 
+    check_solvency(&pool, pool_token_account)?;
     pool.serialize(&mut *pool_account.data.borrow_mut())?;
     user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
 
@@ -362,40 +997,67 @@ fn process_repay(
     let account_info_iter = &mut accounts.iter();
     let pool_account = next_account_info(account_info_iter)?;
     let user_lending_info = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
     let user_token_account = next_account_info(account_info_iter)?;
     let pool_token_account = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
     let clock = Clock::get()?;
 
+    if !user_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
     let amount = {
         let mut data = [0u8; 8];
         data.copy_from_slice(&instruction_data[..8]);
         u64::from_le_bytes(data)
     };
+    // An optional trailing byte of 1 repays stable-rate debt
+    let stable = instruction_data.get(8) == Some(&1);
 
     let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
-    let mut user_info = UserLendingInfo::try_from_slice(&user_lending_info.data.borrow())?;
+    if *pool_token_account.key != pool.lending_token_account {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+    check_position_owner(&mut user_info, user_authority, account_info_iter)?;
 
     // Update interest before repayment
     update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
 
-    let repay_amount = std::cmp::min(amount, user_info.borrowed_amount);
+    let outstanding = if stable {
+        user_info.stable_borrowed_amount
+    } else {
+        user_info.borrowed_amount
+    };
+    let repay_amount = std::cmp::min(amount, outstanding);
 
     // Transfer tokens to pool
-    spl_token::instruction::transfer(
-        token_program.key,
-        user_token_account.key,
-        pool_token_account.key,
-        &user_token_account.key,
-        &[],
+    transfer_to_vault(
+        user_authority,
+        user_token_account,
+        pool_token_account,
+        token_program,
         repay_amount,
     )?;
 
-    user_info.borrowed_amount = user_info.borrowed_amount.checked_sub(repay_amount)
-        .ok_or(ProgramError::Overflow)?;
-    pool.total_borrows = pool.total_borrows.checked_sub(repay_amount)
-        .ok_or(ProgramError::Overflow)?;
+    settle_interest(&mut pool, &mut user_info, repay_amount)?;
+    if stable {
+        remove_stable_borrow(&mut pool, repay_amount, user_info.stable_rate)?;
+        user_info.stable_borrowed_amount = user_info.stable_borrowed_amount.checked_sub(repay_amount)
+            .ok_or(ProgramError::Overflow)?;
+        if user_info.stable_borrowed_amount == 0 {
+            user_info.stable_rate = 0;
+        }
+    } else {
+        user_info.borrowed_amount = user_info.borrowed_amount.checked_sub(repay_amount)
+            .ok_or(ProgramError::Overflow)?;
+        pool.total_borrows = pool.total_borrows.checked_sub(repay_amount)
+            .ok_or(ProgramError::Overflow)?;
+    }
 
+    check_solvency(&pool, pool_token_account)?;
     pool.serialize(&mut *pool_account.data.borrow_mut())?;
     user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
 
@@ -407,86 +1069,2688 @@ fn update_interest(
     user: &mut UserLendingInfo,
     current_time: i64,
 ) -> ProgramResult {
-    let time_elapsed = (current_time - user.last_update_time) as u64;
-    if time_elapsed > 0 {
-        // Calculate deposit interest
-        if user.deposited_amount > 0 {
-            let deposit_interest = user.deposited_amount
-                .checked_mul(pool.lending_rate)
-                .ok_or(ProgramError::Overflow)?
-                .checked_mul(time_elapsed)
-                .ok_or(ProgramError::Overflow)?
-                .checked_div(365 * 24 * 60 * 60 * 10000)
-                .ok_or(ProgramError::Overflow)?;
+    // Accrue pool-level interest at the previous rates, then move rates
+    // with current pool utilization
+    accrue_pool_interest(pool, current_time)?;
+    update_rates(pool)?;
+
+    // Bring the user's balances up to the pool indices
+    if user.borrowed_amount > 0 && user.borrow_index > 0 {
+        let current_borrow = scale_by_index(user.borrowed_amount, pool.borrow_index, user.borrow_index)?;
+        let borrow_interest = current_borrow.checked_sub(user.borrowed_amount)
+            .ok_or(ProgramError::Overflow)?;
+
+        user.cumulative_borrow_interest = user.cumulative_borrow_interest
+            .checked_add(borrow_interest)
+            .ok_or(ProgramError::Overflow)?;
+        user.unpaid_borrow_interest = user.unpaid_borrow_interest
+            .checked_add(borrow_interest)
+            .ok_or(ProgramError::Overflow)?;
+        user.borrowed_amount = current_borrow;
+    }
 
-            user.cumulative_deposit_interest = user.cumulative_deposit_interest
-                .checked_add(deposit_interest)
-                .ok_or(ProgramError::Overflow)?;
-        }
+    if user.deposited_amount > 0 && user.supply_index > 0 {
+        let current_deposit = scale_by_index(user.deposited_amount, pool.supply_index, user.supply_index)?;
+        let deposit_interest = current_deposit.checked_sub(user.deposited_amount)
+            .ok_or(ProgramError::Overflow)?;
 
-        // Calculate borrow interest
-        if user.borrowed_amount > 0 {
-            let borrow_interest = user.borrowed_amount
-                .checked_mul(pool.borrowing_rate)
-                .ok_or(ProgramError::Overflow)?
-                .checked_mul(time_elapsed)
-                .ok_or(ProgramError::Overflow)?
-                .checked_div(365 * 24 * 60 * 60 * 10000)
-                .ok_or(ProgramError::Overflow)?;
+        user.cumulative_deposit_interest = user.cumulative_deposit_interest
+            .checked_add(deposit_interest)
+            .ok_or(ProgramError::Overflow)?;
+        user.deposited_amount = current_deposit;
+    }
 
-            user.cumulative_borrow_interest = user.cumulative_borrow_interest
-                .checked_add(borrow_interest)
-                .ok_or(ProgramError::Overflow)?;
-        }
+    // Stable debt compounds per update at the user's snapshotted rate
+    let time_elapsed = current_time - user.last_update_time;
+    if user.stable_borrowed_amount > 0 && time_elapsed > 0 {
+        let stable_interest = stable_interest(user.stable_borrowed_amount, user.stable_rate, time_elapsed)?;
+
+        user.cumulative_borrow_interest = user.cumulative_borrow_interest
+            .checked_add(stable_interest)
+            .ok_or(ProgramError::Overflow)?;
+        user.unpaid_borrow_interest = user.unpaid_borrow_interest
+            .checked_add(stable_interest)
+            .ok_or(ProgramError::Overflow)?;
+        user.stable_borrowed_amount = user.stable_borrowed_amount
+            .checked_add(stable_interest)
+            .ok_or(ProgramError::Overflow)?;
+    }
+
+    user.borrow_index = pool.borrow_index;
+    user.supply_index = pool.supply_index;
+    user.last_update_time = current_time;
+
+    Ok(())
+}
+
+// Grows an amount from the index it was recorded at to the current index
+fn scale_by_index(
+    amount: u64,
+    current_index: u128,
+    snapshot_index: u128,
+) -> Result<u64, ProgramError> {
+    let scaled = (amount as u128)
+        .checked_mul(current_index)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(snapshot_index)
+        .ok_or(ProgramError::Overflow)?;
+
+    u64::try_from(scaled).map_err(|_| ProgramError::Overflow)
+}
+
+// Compounds an index by numerator / denominator
+fn grow_index(
+    index: u128,
+    numerator: u128,
+    denominator: u128,
+) -> Result<u128, ProgramError> {
+    if denominator == 0 {
+        return Ok(index);
+    }
 
-        user.last_update_time = current_time;
+    index
+        .checked_mul(numerator)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(denominator)
+        .ok_or(ProgramError::Overflow)?
+        .checked_add(index)
+        .ok_or(ProgramError::Overflow)
+}
+
+fn accrue_pool_interest(
+    pool: &mut LendingPool,
+    current_time: i64,
+) -> ProgramResult {
+    let time_elapsed = current_time - pool.last_update_time;
+    if time_elapsed <= 0 {
+        return Ok(());
     }
 
+    // Borrower interest grows outstanding debt; the reserve factor share goes
+    // to the protocol and the rest to suppliers, raising the receipt exchange rate
+    let variable_interest = (pool.total_borrows as u128)
+        .checked_mul(pool.borrowing_rate as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_mul(time_elapsed as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(SECONDS_PER_YEAR as u128 * 10000)
+        .ok_or(ProgramError::Overflow)? as u64;
+    let stable_interest = stable_interest(pool.total_stable_borrows, pool.average_stable_rate, time_elapsed)?;
+    let interest = variable_interest.checked_add(stable_interest)
+        .ok_or(ProgramError::Overflow)?;
+
+    let reserve_share = interest
+        .checked_mul(pool.reserve_factor)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::Overflow)?;
+    let supplier_share = interest.checked_sub(reserve_share)
+        .ok_or(ProgramError::Overflow)?;
+
+    pool.borrow_index = grow_index(
+        pool.borrow_index,
+        (pool.borrowing_rate as u128)
+            .checked_mul(time_elapsed as u128)
+            .ok_or(ProgramError::Overflow)?,
+        SECONDS_PER_YEAR as u128 * 10000,
+    )?;
+    pool.supply_index = grow_index(
+        pool.supply_index,
+        supplier_share as u128,
+        pool.total_deposits as u128,
+    )?;
+
+    pool.total_borrows = pool.total_borrows.checked_add(variable_interest)
+        .ok_or(ProgramError::Overflow)?;
+    pool.total_stable_borrows = pool.total_stable_borrows.checked_add(stable_interest)
+        .ok_or(ProgramError::Overflow)?;
+    pool.total_deposits = pool.total_deposits.checked_add(supplier_share)
+        .ok_or(ProgramError::Overflow)?;
+    pool.total_reserves = pool.total_reserves.checked_add(reserve_share)
+        .ok_or(ProgramError::Overflow)?;
+    pool.unpaid_interest = pool.unpaid_interest.checked_add(interest)
+        .ok_or(ProgramError::Overflow)?;
+    pool.last_update_time = current_time;
+
     Ok(())
 }
 
-fn check_collateral_ratio(
+// Receipt tokens minted for a deposit of the pool token
+fn deposit_to_receipt(
     pool: &LendingPool,
-    deposit_amount: u64,
-    borrow_amount: u64,
-) -> bool {
-    if borrow_amount == 0 {
-        return true;
+    amount: u64,
+    receipt_supply: u64,
+) -> Result<u64, ProgramError> {
+    if receipt_supply == 0 || pool.total_deposits == 0 {
+        return Ok(amount);
     }
 
-    let collateral_value = (deposit_amount as u128)
-        .checked_mul(10000)
-        .unwrap_or(0);
-    let required_collateral = (borrow_amount as u128)
-        .checked_mul(pool.collateral_ratio as u128)
-        .unwrap_or(0);
+    let receipt_amount = (amount as u128)
+        .checked_mul(receipt_supply as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(pool.total_deposits as u128)
+        .ok_or(ProgramError::Overflow)?;
 
-    collateral_value >= required_collateral
+    u64::try_from(receipt_amount).map_err(|_| ProgramError::Overflow)
 }
 
-fn process_add_collateral(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> ProgramResult {
-    // Similar to deposit but updates collateral amount
-    Ok(())
+// Pool tokens redeemable for an amount of receipt tokens
+fn receipt_to_deposit(
+    pool: &LendingPool,
+    receipt_amount: u64,
+    receipt_supply: u64,
+) -> Result<u64, ProgramError> {
+    if receipt_supply == 0 {
+        return Ok(0);
+    }
+
+    let amount = (receipt_amount as u128)
+        .checked_mul(pool.total_deposits as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(receipt_supply as u128)
+        .ok_or(ProgramError::Overflow)?;
+
+    u64::try_from(amount).map_err(|_| ProgramError::Overflow)
 }
 
-fn process_withdraw_collateral(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> ProgramResult {
-    // Similar to withdraw but checks collateral ratio
-    Ok(())
+// Simple interest on stable debt over the elapsed period
+fn stable_interest(
+    amount: u64,
+    rate: u64,
+    time_elapsed: i64,
+) -> Result<u64, ProgramError> {
+    let interest = (amount as u128)
+        .checked_mul(rate as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_mul(time_elapsed as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(SECONDS_PER_YEAR as u128 * 10000)
+        .ok_or(ProgramError::Overflow)?;
+
+    u64::try_from(interest).map_err(|_| ProgramError::Overflow)
 }
 
-fn process_liquidate_position(
-    program_id: &Pubkey,
+// Adds stable debt at the given rate, keeping the pool's weighted average
+// stable rate in step
+fn add_stable_borrow(
+    pool: &mut LendingPool,
+    amount: u64,
+    rate: u64,
+) -> ProgramResult {
+    let new_total = pool.total_stable_borrows.checked_add(amount)
+        .ok_or(ProgramError::Overflow)?;
+    let weighted = (pool.total_stable_borrows as u128)
+        .checked_mul(pool.average_stable_rate as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_add((amount as u128).checked_mul(rate as u128).ok_or(ProgramError::Overflow)?)
+        .ok_or(ProgramError::Overflow)?;
+
+    pool.average_stable_rate = if new_total == 0 {
+        0
+    } else {
+        (weighted / new_total as u128) as u64
+    };
+    pool.total_stable_borrows = new_total;
+
+    Ok(())
+}
+
+fn remove_stable_borrow(
+    pool: &mut LendingPool,
+    amount: u64,
+    rate: u64,
+) -> ProgramResult {
+    // User and pool accruals round independently, so clamp rather than fail
+    let new_total = pool.total_stable_borrows.saturating_sub(amount);
+    let weighted = (pool.total_stable_borrows as u128)
+        .checked_mul(pool.average_stable_rate as u128)
+        .ok_or(ProgramError::Overflow)?
+        .saturating_sub((amount as u128).checked_mul(rate as u128).ok_or(ProgramError::Overflow)?);
+
+    pool.average_stable_rate = if new_total == 0 {
+        0
+    } else {
+        (weighted / new_total as u128) as u64
+    };
+    pool.total_stable_borrows = new_total;
+
+    Ok(())
+}
+
+fn update_rates(pool: &mut LendingPool) -> ProgramResult {
+    let utilization = calculate_utilization(
+        pool.total_deposits,
+        pool.total_borrows.checked_add(pool.total_fixed_borrows)
+            .ok_or(ProgramError::Overflow)?
+            .checked_add(pool.total_stable_borrows)
+            .ok_or(ProgramError::Overflow)?,
+    )?;
+    let model = &pool.rate_model;
+
+    let borrowing_rate = if utilization <= model.optimal_utilization {
+        model.slope1
+            .checked_mul(utilization)
+            .ok_or(ProgramError::Overflow)?
+            .checked_div(model.optimal_utilization)
+            .ok_or(ProgramError::Overflow)?
+            .checked_add(model.base_rate)
+            .ok_or(ProgramError::Overflow)?
+    } else {
+        let excess_utilization = utilization - model.optimal_utilization;
+        model.slope2
+            .checked_mul(excess_utilization)
+            .ok_or(ProgramError::Overflow)?
+            .checked_div(10000 - model.optimal_utilization)
+            .ok_or(ProgramError::Overflow)?
+            .checked_add(model.base_rate)
+            .ok_or(ProgramError::Overflow)?
+            .checked_add(model.slope1)
+            .ok_or(ProgramError::Overflow)?
+    };
+
+    // Suppliers earn the borrow rate scaled by the share of deposits lent out,
+    // less the reserve factor
+    pool.lending_rate = borrowing_rate
+        .checked_mul(utilization)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::Overflow)?
+        .checked_mul(10000 - pool.reserve_factor)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::Overflow)?;
+    pool.borrowing_rate = borrowing_rate;
+
+    Ok(())
+}
+
+// Vault liquidity belonging to suppliers that is not lent out
+fn available_liquidity(pool: &LendingPool) -> Result<u64, ProgramError> {
+    pool.total_deposits
+        .checked_sub(pool.total_borrows)
+        .ok_or(ProgramError::Overflow)?
+        .checked_sub(pool.total_fixed_borrows)
+        .ok_or(ProgramError::Overflow)?
+        .checked_sub(pool.total_stable_borrows)
+        .ok_or(ProgramError::Overflow)
+}
+
+// Variable and fixed-rate debt backed by a position's collateral
+fn total_debt(user: &UserLendingInfo) -> Result<u64, ProgramError> {
+    user.borrowed_amount
+        .checked_add(user.fixed_borrowed_amount)
+        .ok_or(ProgramError::Overflow)?
+        .checked_add(user.stable_borrowed_amount)
+        .ok_or(ProgramError::Overflow)
+}
+
+fn calculate_utilization(
+    total_deposits: u64,
+    total_borrows: u64,
+) -> Result<u64, ProgramError> {
+    if total_deposits == 0 {
+        return Ok(0);
+    }
+
+    let utilization = (total_borrows as u128)
+        .checked_mul(10000)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(total_deposits as u128)
+        .ok_or(ProgramError::Overflow)?;
+
+    Ok(std::cmp::min(utilization, 10000) as u64)
+}
+
+fn check_vault_authority(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo,
+    pool: &LendingPool,
+    vault_authority: &AccountInfo,
+) -> ProgramResult {
+    let expected_vault_authority = Pubkey::create_program_address(
+        &[VAULT_AUTHORITY_SEED, pool_account.key.as_ref(), &[pool.vault_authority_bump]],
+        program_id,
+    )?;
+    if expected_vault_authority != *vault_authority.key {
+        return Err(LendingError::InvalidAuthority.into());
+    }
+
+    Ok(())
+}
+
+// Moves tokens out of a pool vault, signing as the vault authority PDA
+fn transfer_from_vault<'a>(
+    pool_account: &AccountInfo<'a>,
+    pool: &LendingPool,
+    vault_authority: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            source.key,
+            destination.key,
+            vault_authority.key,
+            &[],
+            amount,
+        )?,
+        &[
+            source.clone(),
+            destination.clone(),
+            vault_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&[VAULT_AUTHORITY_SEED, pool_account.key.as_ref(), &[pool.vault_authority_bump]]],
+    )
+}
+
+// Mints receipt tokens, signing as the vault authority PDA
+fn mint_receipt<'a>(
+    pool_account: &AccountInfo<'a>,
+    pool: &LendingPool,
+    vault_authority: &AccountInfo<'a>,
+    receipt_mint: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    invoke_signed(
+        &spl_token::instruction::mint_to(
+            token_program.key,
+            receipt_mint.key,
+            destination.key,
+            vault_authority.key,
+            &[],
+            amount,
+        )?,
+        &[
+            receipt_mint.clone(),
+            destination.clone(),
+            vault_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&[VAULT_AUTHORITY_SEED, pool_account.key.as_ref(), &[pool.vault_authority_bump]]],
+    )
+}
+
+// Moves tokens from a user-controlled account into a pool vault
+fn transfer_to_vault<'a>(
+    authority: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    vault: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            source.key,
+            vault.key,
+            authority.key,
+            &[],
+            amount,
+        )?,
+        &[
+            source.clone(),
+            vault.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+    )
+}
+
+fn check_borrow_limit(pool: &LendingPool, total_debt: u64) -> ProgramResult {
+    if total_debt > pool.max_borrow_per_user {
+        return Err(LendingError::BorrowLimitExceeded.into());
+    }
+
+    Ok(())
+}
+
+fn check_collateral_ratio(
+    pool: &LendingPool,
+    collateral_value: u128,
+    borrow_amount: u64,
+) -> Result<bool, ProgramError> {
+    meets_ratio(collateral_value, borrow_amount, pool.collateral_ratio)
+}
+
+fn check_liquidation_threshold(
+    pool: &LendingPool,
+    collateral_value: u128,
+    borrow_amount: u64,
+) -> Result<bool, ProgramError> {
+    meets_ratio(collateral_value, borrow_amount, pool.liquidation_threshold_bps)
+}
+
+fn meets_ratio(
+    collateral_value: u128,
+    borrow_amount: u64,
+    ratio_bps: u64,
+) -> Result<bool, ProgramError> {
+    if borrow_amount == 0 {
+        return Ok(true);
+    }
+
+    let collateral_value = collateral_value
+        .checked_mul(10000)
+        .ok_or(ProgramError::Overflow)?;
+    let required_collateral = (borrow_amount as u128)
+        .checked_mul(ratio_bps as u128)
+        .ok_or(ProgramError::Overflow)?;
+
+    Ok(collateral_value >= required_collateral)
+}
+
+// Collateral discount of a liquidation auction that has been open for the
+// given time, rising linearly from the start bonus to the maximum
+fn auction_bonus(pool: &LendingPool, elapsed: i64) -> Result<u64, ProgramError> {
+    if elapsed >= pool.auction_duration {
+        return Ok(pool.liquidation_bonus_bps);
+    }
+
+    let ramp = pool.liquidation_bonus_bps
+        .checked_sub(pool.auction_start_bonus_bps)
+        .ok_or(ProgramError::Overflow)?;
+    let increase = (ramp as u128)
+        .checked_mul(elapsed.max(0) as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(pool.auction_duration as u128)
+        .ok_or(ProgramError::Overflow)? as u64;
+
+    pool.auction_start_bonus_bps.checked_add(increase)
+        .ok_or(ProgramError::Overflow)
+}
+
+fn validate_liquidation_params(
+    collateral_ratio: u64,
+    params: &LiquidationParams,
+) -> ProgramResult {
+    // Threshold must sit between full backing and the borrow requirement so
+    // fresh borrows are never immediately liquidatable
+    if params.liquidation_threshold_bps < 10000 || params.liquidation_threshold_bps > collateral_ratio {
+        return Err(LendingError::InvalidLiquidationParams.into());
+    }
+    if params.liquidation_bonus_bps >= 10000 {
+        return Err(LendingError::InvalidLiquidationParams.into());
+    }
+    if params.auction_start_bonus_bps > params.liquidation_bonus_bps || params.auction_duration < 0 {
+        return Err(LendingError::InvalidLiquidationParams.into());
+    }
+    // The hard floor only makes sense below the threshold
+    if params.grace_period < 0 || params.hard_floor_bps > params.liquidation_threshold_bps {
+        return Err(LendingError::InvalidLiquidationParams.into());
+    }
+
+    Ok(())
+}
+
+// Loads a user account, checking it is the program-owned PDA for its wallet
+// in this pool
+fn load_user_info(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo,
+    user_lending_info: &AccountInfo,
+) -> Result<UserLendingInfo, ProgramError> {
+    if user_lending_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let user_info = UserLendingInfo::unpack(&user_lending_info.data.borrow())?;
+    let expected_address = Pubkey::create_program_address(
+        &[
+            USER_SEED,
+            pool_account.key.as_ref(),
+            user_info.wallet.as_ref(),
+            &[user_info.bump],
+        ],
+        program_id,
+    )?;
+    if expected_address != *user_lending_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(user_info)
+}
+
+// Creates the user account at its PDA, paid for by the wallet it belongs to
+fn create_user_info<'a>(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo<'a>,
+    pool: &LendingPool,
+    user_lending_info: &AccountInfo<'a>,
+    wallet: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    current_time: i64,
+) -> Result<UserLendingInfo, ProgramError> {
+    if !wallet.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (expected_address, bump) = Pubkey::find_program_address(
+        &[USER_SEED, pool_account.key.as_ref(), wallet.key.as_ref()],
+        program_id,
+    );
+    if expected_address != *user_lending_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            wallet.key,
+            user_lending_info.key,
+            rent.minimum_balance(UserLendingInfo::LEN),
+            UserLendingInfo::LEN as u64,
+            program_id,
+        ),
+        &[
+            wallet.clone(),
+            user_lending_info.clone(),
+            system_program.clone(),
+        ],
+        &[&[USER_SEED, pool_account.key.as_ref(), wallet.key.as_ref(), &[bump]]],
+    )?;
+
+    Ok(UserLendingInfo {
+        wallet: *wallet.key,
+        bump,
+        owner: *wallet.key,
+        deposited_amount: 0,
+        borrowed_amount: 0,
+        fixed_borrowed_amount: 0,
+        stable_borrowed_amount: 0,
+        stable_rate: 0,
+        last_update_time: current_time,
+        borrow_index: pool.borrow_index,
+        supply_index: pool.supply_index,
+        cumulative_deposit_interest: 0,
+        cumulative_borrow_interest: 0,
+        unpaid_borrow_interest: 0,
+        marked_for_liquidation_at: 0,
+        last_tip_paid_at: 0,
+        position_mint: None,
+        collateral: Vec::new(),
+    })
+}
+
+// Current owner of a position: the recorded owner, or the holder of the
+// position NFT once tokenized. Tokenized positions pass the holder's
+// position token account as the next account.
+fn position_owner<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    user_info: &UserLendingInfo,
+    account_info_iter: &mut I,
+) -> Result<Pubkey, ProgramError> {
+    let position_mint = match user_info.position_mint {
+        Some(position_mint) => position_mint,
+        None => return Ok(user_info.owner),
+    };
+
+    let holder_account = next_account_info(account_info_iter)?;
+    if *holder_account.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let holder = TokenAccount::unpack(&holder_account.data.borrow())?;
+    if holder.mint != position_mint || holder.amount != 1 {
+        return Err(LendingError::InvalidOwner.into());
+    }
+
+    Ok(holder.owner)
+}
+
+fn check_position_owner<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    user_info: &mut UserLendingInfo,
+    authority: &AccountInfo,
+    account_info_iter: &mut I,
+) -> ProgramResult {
+    let owner = position_owner(user_info, account_info_iter)?;
+    if owner != *authority.key {
+        return Err(LendingError::InvalidOwner.into());
+    }
+
+    // Follow the NFT so the recorded owner tracks the latest holder
+    user_info.owner = owner;
+
+    Ok(())
+}
+
+fn load_collateral_asset(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo,
+    asset_account: &AccountInfo,
+) -> Result<CollateralAsset, ProgramError> {
+    if asset_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let asset = CollateralAsset::unpack(&asset_account.data.borrow())?;
+    if asset.pool != *pool_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(asset)
+}
+
+// Price of one collateral token in borrow-token units (scaled by PRICE_SCALE),
+// rejected when stale or too uncertain to value positions against
+fn collateral_price(
+    pool: &LendingPool,
+    asset: &CollateralAsset,
+    current_time: i64,
+) -> Result<u64, ProgramError> {
+    if asset.twap_window > 0 {
+        return twap_price(pool, asset, current_time);
+    }
+
+    if asset.price == 0 {
+        return Err(LendingError::MissingCollateralPrice.into());
+    }
+
+    let age = current_time.saturating_sub(asset.last_price_update);
+    if age > pool.max_price_age {
+        return Err(LendingError::StaleOracle.into());
+    }
+
+    let max_confidence = (asset.price as u128)
+        .checked_mul(pool.max_confidence_bps as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::Overflow)?;
+    if asset.confidence as u128 > max_confidence {
+        return Err(LendingError::UntrustedPrice.into());
+    }
+
+    Ok(asset.price)
+}
+
+// Time-weighted average price between the newest observation and the most
+// recent one at least a full window older
+fn twap_price(
+    pool: &LendingPool,
+    asset: &CollateralAsset,
+    current_time: i64,
+) -> Result<u64, ProgramError> {
+    let newest = asset.observations.get(asset.observation_index as usize)
+        .ok_or(LendingError::InsufficientObservations)?;
+    if current_time.saturating_sub(newest.timestamp) > pool.max_price_age {
+        return Err(LendingError::StaleOracle.into());
+    }
+
+    let window_start = newest.timestamp.saturating_sub(asset.twap_window);
+    let oldest = asset.observations.iter()
+        .filter(|observation| observation.timestamp <= window_start)
+        .max_by_key(|observation| observation.timestamp)
+        .ok_or(LendingError::InsufficientObservations)?;
+
+    let elapsed = newest.timestamp.checked_sub(oldest.timestamp)
+        .ok_or(ProgramError::Overflow)?;
+    if elapsed <= 0 {
+        return Err(LendingError::InsufficientObservations.into());
+    }
+
+    let price = newest.price_cumulative
+        .wrapping_sub(oldest.price_cumulative)
+        .checked_div(elapsed as u128)
+        .ok_or(ProgramError::Overflow)?;
+    if price == 0 {
+        return Err(LendingError::MissingCollateralPrice.into());
+    }
+
+    u64::try_from(price).map_err(|_| ProgramError::Overflow)
+}
+
+// Aggregate value of all collateral in a position, priced through the
+// collateral asset accounts supplied with the instruction
+fn position_collateral_value(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo,
+    pool: &LendingPool,
+    user: &UserLendingInfo,
+    asset_accounts: &[AccountInfo],
+    current_time: i64,
+) -> Result<u128, ProgramError> {
+    let mut total_value: u128 = 0;
+
+    for position in user.collateral.iter().filter(|position| position.amount > 0) {
+        let mut price = None;
+        for asset_account in asset_accounts {
+            let asset = load_collateral_asset(program_id, pool_account, asset_account)?;
+            if asset.mint == position.mint {
+                price = Some(collateral_price(pool, &asset, current_time)?);
+                break;
+            }
+        }
+        let price = price.ok_or(LendingError::MissingCollateralPrice)?;
+
+        total_value = total_value
+            .checked_add(collateral_value(position.amount, price)?)
+            .ok_or(ProgramError::Overflow)?;
+    }
+
+    Ok(total_value)
+}
+
+// Value of collateral expressed in units of the borrowed token
+fn collateral_value(
+    collateral_amount: u64,
+    price: u64,
+) -> Result<u128, ProgramError> {
+    (collateral_amount as u128)
+        .checked_mul(price as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(PRICE_SCALE as u128)
+        .ok_or(ProgramError::Overflow)
+}
+
+// Collateral amount worth the given value of the borrowed token
+fn collateral_for_value(
+    value: u64,
+    price: u64,
+) -> Result<u64, ProgramError> {
+    let amount = (value as u128)
+        .checked_mul(PRICE_SCALE as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(price as u128)
+        .ok_or(ProgramError::Overflow)?;
+
+    u64::try_from(amount).map_err(|_| ProgramError::Overflow)
+}
+
+fn process_add_collateral(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_lending_info = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
+    let user_collateral_account = next_account_info(account_info_iter)?;
+    let asset_account = next_account_info(account_info_iter)?;
+    let pool_collateral_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let amount = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        u64::from_le_bytes(data)
+    };
+
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    let mut asset = load_collateral_asset(program_id, pool_account, asset_account)?;
+    if *pool_collateral_account.key != asset.vault {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut user_info = if user_lending_info.data_is_empty() {
+        create_user_info(
+            program_id,
+            pool_account,
+            &pool,
+            user_lending_info,
+            user_authority,
+            system_program,
+            clock.unix_timestamp,
+        )?
+    } else {
+        let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+        check_position_owner(&mut user_info, user_authority, account_info_iter)?;
+        user_info
+    };
+
+    // Update interest before adding collateral
+    update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
+
+    // Transfer collateral tokens to the collateral vault
+    transfer_to_vault(
+        user_authority,
+        user_collateral_account,
+        pool_collateral_account,
+        token_program,
+        amount,
+    )?;
+
+    match user_info.position_mut(&asset.mint) {
+        Some(position) => {
+            position.amount = position.amount.checked_add(amount)
+                .ok_or(ProgramError::Overflow)?;
+        }
+        None => {
+            if user_info.collateral.len() >= MAX_COLLATERAL_POSITIONS {
+                return Err(LendingError::TooManyCollateralPositions.into());
+            }
+            user_info.collateral.push(CollateralPosition {
+                mint: asset.mint,
+                vault: asset.vault,
+                amount,
+            });
+        }
+    }
+    asset.total_amount = asset.total_amount.checked_add(amount)
+        .ok_or(ProgramError::Overflow)?;
+
+    asset.serialize(&mut *asset_account.data.borrow_mut())?;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_withdraw_collateral(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_lending_info = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
+    let user_collateral_account = next_account_info(account_info_iter)?;
+    let asset_account = next_account_info(account_info_iter)?;
+    let pool_collateral_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    if !user_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let amount = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        u64::from_le_bytes(data)
+    };
+
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if pool.is_paused {
+        return Err(LendingError::PoolPaused.into());
+    }
+
+    let mut asset = load_collateral_asset(program_id, pool_account, asset_account)?;
+    if *pool_collateral_account.key != asset.vault {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+    check_position_owner(&mut user_info, user_authority, account_info_iter)?;
+    // Remaining accounts: collateral asset accounts for the other positions
+    let other_asset_accounts = account_info_iter.as_slice();
+
+    // Update interest before withdrawing collateral
+    update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
+
+    let position = user_info.position_mut(&asset.mint)
+        .ok_or(LendingError::InsufficientCollateral)?;
+    if amount > position.amount {
+        return Err(LendingError::InsufficientCollateral.into());
+    }
+    position.amount = position.amount.checked_sub(amount)
+        .ok_or(ProgramError::Overflow)?;
+    user_info.collateral.retain(|position| position.amount > 0);
+
+    // Check collateral ratio after withdrawal
+    let mut asset_accounts = vec![asset_account.clone()];
+    asset_accounts.extend_from_slice(other_asset_accounts);
+    let collateral_value = position_collateral_value(program_id, pool_account, &pool, &user_info, &asset_accounts, clock.unix_timestamp)?;
+    if !check_collateral_ratio(&pool, collateral_value, total_debt(&user_info)?)? {
+        return Err(LendingError::InsufficientCollateral.into());
+    }
+
+    // Transfer collateral tokens back to user
+    check_vault_authority(program_id, pool_account, &pool, vault_authority)?;
+    transfer_from_vault(
+        pool_account,
+        &pool,
+        vault_authority,
+        pool_collateral_account,
+        user_collateral_account,
+        token_program,
+        amount,
+    )?;
+
+    asset.total_amount = asset.total_amount.checked_sub(amount)
+        .ok_or(ProgramError::Overflow)?;
+
+    asset.serialize(&mut *asset_account.data.borrow_mut())?;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_liquidate_position(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let borrower_lending_info = next_account_info(account_info_iter)?;
+    let liquidator_account = next_account_info(account_info_iter)?;
+    let liquidator_token_account = next_account_info(account_info_iter)?;
+    let liquidator_collateral_account = next_account_info(account_info_iter)?;
+    let pool_token_account = next_account_info(account_info_iter)?;
+    let seize_asset_account = next_account_info(account_info_iter)?;
+    let pool_collateral_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    let amount = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        u64::from_le_bytes(data)
+    };
+    // An optional trailing byte of 1 passes one of the borrower's fixed loans after
+    // the token program, repaid once variable and stable debt are cleared
+    let fixed_loan_account = if instruction_data.get(8) == Some(&1) {
+        Some(next_account_info(account_info_iter)?)
+    } else {
+        None
+    };
+    // Remaining accounts: collateral asset accounts for the other positions
+    let other_asset_accounts = account_info_iter.as_slice();
+    let clock = Clock::get()?;
+
+    if !liquidator_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    let mut seize_asset = load_collateral_asset(program_id, pool_account, seize_asset_account)?;
+    if *pool_token_account.key != pool.lending_token_account
+        || *pool_collateral_account.key != seize_asset.vault
+    {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut borrower_info = load_user_info(program_id, pool_account, borrower_lending_info)?;
+    let mut fixed_loan = match fixed_loan_account {
+        Some(fixed_loan_account) => {
+            let loan = load_fixed_loan(program_id, pool_account, fixed_loan_account)?;
+            if loan.owner != borrower_info.owner {
+                return Err(LendingError::InvalidOwner.into());
+            }
+            Some(loan)
+        }
+        None => None,
+    };
+
+    // Update interest before liquidation
+    update_interest(&mut pool, &mut borrower_info, clock.unix_timestamp)?;
+
+    // Only positions below the liquidation threshold can be liquidated
+    let mut asset_accounts = vec![seize_asset_account.clone()];
+    asset_accounts.extend_from_slice(other_asset_accounts);
+    let collateral_value = position_collateral_value(program_id, pool_account, &pool, &borrower_info, &asset_accounts, clock.unix_timestamp)?;
+    let debt = total_debt(&borrower_info)?;
+    if check_liquidation_threshold(&pool, collateral_value, debt)? {
+        return Err(LendingError::PositionNotLiquidatable.into());
+    }
+
+    // Above the hard floor a position must first be marked and given the
+    // grace period to recover; the auction opens once the grace period ends
+    let bonus_bps = if meets_ratio(collateral_value, debt, pool.hard_floor_bps)? {
+        if borrower_info.marked_for_liquidation_at == 0 {
+            return Err(LendingError::PositionNotLiquidatable.into());
+        }
+        let grace_end = borrower_info.marked_for_liquidation_at
+            .checked_add(pool.liquidation_grace_period)
+            .ok_or(ProgramError::Overflow)?;
+        if clock.unix_timestamp < grace_end {
+            return Err(LendingError::GracePeriodActive.into());
+        }
+        auction_bonus(&pool, clock.unix_timestamp - grace_end)?
+    } else {
+        // Positions below the hard floor are cleared at the full discount
+        pool.liquidation_bonus_bps
+    };
+
+    // Liquidator can repay at most the close factor of the outstanding debt,
+    // and fixed debt only up to the principal of the loan passed in
+    let max_repay = debt
+        .checked_mul(CLOSE_FACTOR_BPS)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::Overflow)?;
+    let repayable = borrower_info.borrowed_amount
+        .checked_add(borrower_info.stable_borrowed_amount)
+        .ok_or(ProgramError::Overflow)?
+        .checked_add(fixed_loan.as_ref().map_or(0, |loan| loan.principal))
+        .ok_or(ProgramError::Overflow)?;
+    let repay_amount = amount.min(max_repay).min(repayable);
+    if repay_amount == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Variable debt is cleared first, then stable debt, then fixed principal
+    let variable_repay = std::cmp::min(repay_amount, borrower_info.borrowed_amount);
+    let stable_repay = std::cmp::min(
+        repay_amount - variable_repay,
+        borrower_info.stable_borrowed_amount,
+    );
+    let fixed_repay = repay_amount - variable_repay - stable_repay;
+
+    // Fixed principal is repaid together with the interest it has accrued so far
+    let fixed_interest_paid = match &fixed_loan {
+        Some(loan) if fixed_repay > 0 => {
+            let interest = (fixed_interest_due(&pool, loan, clock.unix_timestamp)? as u128)
+                .checked_mul(fixed_repay as u128)
+                .ok_or(ProgramError::Overflow)?
+                / loan.principal as u128;
+            interest as u64
+        }
+        _ => 0,
+    };
+    let paid_amount = repay_amount.checked_add(fixed_interest_paid)
+        .ok_or(ProgramError::Overflow)?;
+
+    // Seize collateral of the chosen mint worth the repaid debt plus the
+    // current auction discount
+    let seize_value = paid_amount
+        .checked_mul(10000 + bonus_bps)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::Overflow)?;
+    let seize_price = collateral_price(&pool, &seize_asset, clock.unix_timestamp)?;
+    let position = borrower_info.position_mut(&seize_asset.mint)
+        .ok_or(LendingError::InsufficientCollateral)?;
+    let seize_amount = std::cmp::min(
+        collateral_for_value(seize_value, seize_price)?,
+        position.amount,
+    );
+    position.amount = position.amount.checked_sub(seize_amount)
+        .ok_or(ProgramError::Overflow)?;
+    borrower_info.collateral.retain(|position| position.amount > 0);
+
+    // Transfer repayment from liquidator to pool
+    transfer_to_vault(
+        liquidator_account,
+        liquidator_token_account,
+        pool_token_account,
+        token_program,
+        paid_amount,
+    )?;
+
+    // Transfer seized collateral to liquidator
+    check_vault_authority(program_id, pool_account, &pool, vault_authority)?;
+    transfer_from_vault(
+        pool_account,
+        &pool,
+        vault_authority,
+        pool_collateral_account,
+        liquidator_collateral_account,
+        token_program,
+        seize_amount,
+    )?;
+
+    settle_interest(&mut pool, &mut borrower_info, variable_repay + stable_repay)?;
+    borrower_info.borrowed_amount = borrower_info.borrowed_amount.checked_sub(variable_repay)
+        .ok_or(ProgramError::Overflow)?;
+    pool.total_borrows = pool.total_borrows.checked_sub(variable_repay)
+        .ok_or(ProgramError::Overflow)?;
+    if stable_repay > 0 {
+        remove_stable_borrow(&mut pool, stable_repay, borrower_info.stable_rate)?;
+        borrower_info.stable_borrowed_amount = borrower_info.stable_borrowed_amount
+            .checked_sub(stable_repay)
+            .ok_or(ProgramError::Overflow)?;
+    }
+    // A fully repaid loan stays open at zero principal until the borrower closes it
+    // with RepayFixed, which returns its rent
+    if let (Some(loan), Some(fixed_loan_account)) = (fixed_loan.as_mut(), fixed_loan_account) {
+        if fixed_repay > 0 {
+            distribute_fixed_earnings(&mut pool, fixed_interest_paid)?;
+            loan.principal = loan.principal.checked_sub(fixed_repay)
+                .ok_or(ProgramError::Overflow)?;
+            borrower_info.fixed_borrowed_amount = borrower_info.fixed_borrowed_amount
+                .checked_sub(fixed_repay)
+                .ok_or(ProgramError::Overflow)?;
+            pool.total_fixed_borrows = pool.total_fixed_borrows.checked_sub(fixed_repay)
+                .ok_or(ProgramError::Overflow)?;
+            loan.serialize(&mut *fixed_loan_account.data.borrow_mut())?;
+        }
+    }
+    seize_asset.total_amount = seize_asset.total_amount.checked_sub(seize_amount)
+        .ok_or(ProgramError::Overflow)?;
+
+    // A position restored above the threshold starts over on the next dip
+    let collateral_value = position_collateral_value(program_id, pool_account, &pool, &borrower_info, &asset_accounts, clock.unix_timestamp)?;
+    if check_liquidation_threshold(&pool, collateral_value, total_debt(&borrower_info)?)? {
+        borrower_info.marked_for_liquidation_at = 0;
+    }
+
+    msg!("Liquidated: repaid {}, seized {}", repay_amount, seize_amount);
+
+    seize_asset.serialize(&mut *seize_asset_account.data.borrow_mut())?;
+    check_solvency(&pool, pool_token_account)?;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    borrower_info.serialize(&mut *borrower_lending_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_withdraw_reserves(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let pool_token_account = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let amount = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        u64::from_le_bytes(data)
+    };
+
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if pool.pool_authority != *authority_account.key {
+        return Err(LendingError::InvalidAuthority.into());
+    }
+    if !authority_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *pool_token_account.key != pool.lending_token_account {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    // Bring reserves up to date before withdrawing
+    accrue_pool_interest(&mut pool, clock.unix_timestamp)?;
+    update_rates(&mut pool)?;
+
+    if amount > pool.total_reserves {
+        return Err(LendingError::InsufficientReserves.into());
+    }
+
+    // Reserves can only be paid out of idle vault liquidity
+    let vault_liquidity = available_liquidity(&pool)?
+        .checked_add(pool.total_reserves)
+        .ok_or(ProgramError::Overflow)?;
+    if amount > vault_liquidity {
+        return Err(LendingError::InsufficientLiquidity.into());
+    }
+
+    // Transfer reserves out of the pool vault
+    check_vault_authority(program_id, pool_account, &pool, vault_authority)?;
+    transfer_from_vault(
+        pool_account,
+        &pool,
+        vault_authority,
+        pool_token_account,
+        destination_account,
+        token_program,
+        amount,
+    )?;
+
+    pool.total_reserves = pool.total_reserves.checked_sub(amount)
+        .ok_or(ProgramError::Overflow)?;
+
+    check_solvency(&pool, pool_token_account)?;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_update_liquidation_params(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if pool.pool_authority != *authority_account.key {
+        return Err(LendingError::InvalidAuthority.into());
+    }
+    if !authority_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let params = LiquidationParams::try_from_slice(instruction_data)?;
+    validate_liquidation_params(pool.collateral_ratio, &params)?;
+
+    pool.liquidation_threshold_bps = params.liquidation_threshold_bps;
+    pool.liquidation_bonus_bps = params.liquidation_bonus_bps;
+    pool.auction_start_bonus_bps = params.auction_start_bonus_bps;
+    pool.auction_duration = params.auction_duration;
+    pool.liquidation_grace_period = params.grace_period;
+    pool.hard_floor_bps = params.hard_floor_bps;
+    pool.keeper_tip = params.keeper_tip;
+
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_delegate_borrow_power(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let delegator_lending_info = next_account_info(account_info_iter)?;
+    let delegator_account = next_account_info(account_info_iter)?;
+    let delegate_account = next_account_info(account_info_iter)?;
+    let delegation_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !delegator_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let borrow_allowance = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        u64::from_le_bytes(data)
+    };
+
+    let delegator_info = load_user_info(program_id, pool_account, delegator_lending_info)?;
+    if position_owner(&delegator_info, account_info_iter)? != *delegator_account.key {
+        return Err(LendingError::InvalidOwner.into());
+    }
+
+    let (expected_delegation, bump) = Pubkey::find_program_address(
+        &[
+            DELEGATION_SEED,
+            pool_account.key.as_ref(),
+            delegator_account.key.as_ref(),
+            delegate_account.key.as_ref(),
+        ],
+        program_id,
+    );
+    if expected_delegation != *delegation_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Create the delegation account on first approval
+    if delegation_account.data_is_empty() {
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                delegator_account.key,
+                delegation_account.key,
+                rent.minimum_balance(BorrowDelegation::LEN),
+                BorrowDelegation::LEN as u64,
+                program_id,
+            ),
+            &[
+                delegator_account.clone(),
+                delegation_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                DELEGATION_SEED,
+                pool_account.key.as_ref(),
+                delegator_account.key.as_ref(),
+                delegate_account.key.as_ref(),
+                &[bump],
+            ]],
+        )?;
+    }
+
+    // Approving overwrites any remaining allowance; zero revokes it
+    let delegation = BorrowDelegation {
+        pool: *pool_account.key,
+        delegator: *delegator_account.key,
+        delegate: *delegate_account.key,
+        borrow_allowance,
+    };
+    delegation.serialize(&mut *delegation_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_borrow_fixed(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_lending_info = next_account_info(account_info_iter)?;
+    let borrower_account = next_account_info(account_info_iter)?;
+    let fixed_loan_account = next_account_info(account_info_iter)?;
+    let user_token_account = next_account_info(account_info_iter)?;
+    let pool_token_account = next_account_info(account_info_iter)?;
+    let fee_wallet_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    // Remaining accounts: collateral asset accounts for each position entry
+    let asset_accounts = account_info_iter.as_slice();
+    let clock = Clock::get()?;
+
+    if !borrower_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // Verify fee wallet
+    if fee_wallet_account.key.to_string() != FEE_WALLET {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let args = FixedBorrowArgs::try_from_slice(instruction_data)?;
+    if args.term < MIN_FIXED_TERM || args.term > MAX_FIXED_TERM {
+        return Err(LendingError::InvalidLoanTerm.into());
+    }
+
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if pool.is_paused {
+        return Err(LendingError::PoolPaused.into());
+    }
+    if *pool_token_account.key != pool.lending_token_account {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+    if user_info.owner != *borrower_account.key {
+        return Err(LendingError::InvalidOwner.into());
+    }
+    // Fixed loans are bound to the wallet that takes them, so they cannot
+    // be opened against a transferable position
+    if user_info.position_mint.is_some() {
+        return Err(LendingError::PositionTokenized.into());
+    }
+
+    // Update interest before borrowing
+    update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
+
+    // Check if pool has enough liquidity
+    if args.amount > available_liquidity(&pool)? {
+        return Err(LendingError::InsufficientLiquidity.into());
+    }
+
+    // Check if user has enough collateral for all outstanding debt
+    let new_fixed_amount = user_info.fixed_borrowed_amount.checked_add(args.amount)
+        .ok_or(ProgramError::Overflow)?;
+    let new_total_debt = total_debt(&user_info)?.checked_add(args.amount)
+        .ok_or(ProgramError::Overflow)?;
+    check_borrow_limit(&pool, new_total_debt)?;
+    let collateral_value = position_collateral_value(program_id, pool_account, &pool, &user_info, asset_accounts, clock.unix_timestamp)?;
+    if !check_collateral_ratio(&pool, collateral_value, new_total_debt)? {
+        return Err(LendingError::InsufficientCollateral.into());
+    }
+
+    let (expected_loan, bump) = Pubkey::find_program_address(
+        &[
+            FIXED_LOAN_SEED,
+            pool_account.key.as_ref(),
+            borrower_account.key.as_ref(),
+            &args.loan_id.to_le_bytes(),
+        ],
+        program_id,
+    );
+    if expected_loan != *fixed_loan_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !fixed_loan_account.data_is_empty() {
+        return Err(LendingError::AlreadyInUse.into());
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            borrower_account.key,
+            fixed_loan_account.key,
+            rent.minimum_balance(FixedLoan::LEN),
+            FixedLoan::LEN as u64,
+            program_id,
+        ),
+        &[
+            borrower_account.clone(),
+            fixed_loan_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[
+            FIXED_LOAN_SEED,
+            pool_account.key.as_ref(),
+            borrower_account.key.as_ref(),
+            &args.loan_id.to_le_bytes(),
+            &[bump],
+        ]],
+    )?;
+
+    // Lock the current variable rate plus the fixed-rate premium
+    let loan = FixedLoan {
+        pool: *pool_account.key,
+        owner: *borrower_account.key,
+        loan_id: args.loan_id,
+        principal: args.amount,
+        fixed_rate: pool.borrowing_rate.checked_add(FIXED_RATE_PREMIUM_BPS)
+            .ok_or(ProgramError::Overflow)?,
+        start_time: clock.unix_timestamp,
+        maturity_time: clock.unix_timestamp.checked_add(args.term)
+            .ok_or(ProgramError::Overflow)?,
+        early_repayment_penalty_bps: EARLY_REPAYMENT_PENALTY_BPS,
+    };
+
+    // Calculate service fee
+    let fee_amount = args.amount
+        .checked_mul(SERVICE_FEE_BPS)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::Overflow)?;
+    let user_borrow_amount = args.amount.checked_sub(fee_amount)
+        .ok_or(ProgramError::Overflow)?;
+
+    check_vault_authority(program_id, pool_account, &pool, vault_authority)?;
+
+    // Transfer tokens to user
+    transfer_from_vault(
+        pool_account,
+        &pool,
+        vault_authority,
+        pool_token_account,
+        user_token_account,
+        token_program,
+        user_borrow_amount,
+    )?;
+
+    // Transfer fee to fee wallet
+    transfer_from_vault(
+        pool_account,
+        &pool,
+        vault_authority,
+        pool_token_account,
+        fee_wallet_account,
+        token_program,
+        fee_amount,
+    )?;
+
+    user_info.fixed_borrowed_amount = new_fixed_amount;
+    pool.total_fixed_borrows = pool.total_fixed_borrows.checked_add(args.amount)
+        .ok_or(ProgramError::Overflow)?;
+
+    loan.serialize(&mut *fixed_loan_account.data.borrow_mut())?;
+    check_solvency(&pool, pool_token_account)?;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_repay_fixed(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_lending_info = next_account_info(account_info_iter)?;
+    let borrower_account = next_account_info(account_info_iter)?;
+    let fixed_loan_account = next_account_info(account_info_iter)?;
+    let user_token_account = next_account_info(account_info_iter)?;
+    let pool_token_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    if !borrower_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if *pool_token_account.key != pool.lending_token_account {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+    let loan = load_fixed_loan(program_id, pool_account, fixed_loan_account)?;
+    if loan.owner != *borrower_account.key || user_info.owner != *borrower_account.key {
+        return Err(LendingError::InvalidOwner.into());
+    }
+
+    // Update interest before repayment
+    update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
+
+    // Interest accrues at the locked rate up to maturity and at the overdue
+    // rate after it; repaying early costs a penalty on the principal instead
+    let interest = fixed_interest_due(&pool, &loan, clock.unix_timestamp)?;
+    let penalty = if clock.unix_timestamp < loan.maturity_time {
+        loan.principal
+            .checked_mul(loan.early_repayment_penalty_bps)
+            .ok_or(ProgramError::Overflow)?
+            .checked_div(10000)
+            .ok_or(ProgramError::Overflow)?
+    } else {
+        0
+    };
+    let earnings = interest.checked_add(penalty)
+        .ok_or(ProgramError::Overflow)?;
+    let amount_due = loan.principal.checked_add(earnings)
+        .ok_or(ProgramError::Overflow)?;
+
+    // Transfer principal, interest and penalty to pool
+    transfer_to_vault(
+        borrower_account,
+        user_token_account,
+        pool_token_account,
+        token_program,
+        amount_due,
+    )?;
+
+    distribute_fixed_earnings(&mut pool, earnings)?;
+
+    user_info.fixed_borrowed_amount = user_info.fixed_borrowed_amount.checked_sub(loan.principal)
+        .ok_or(ProgramError::Overflow)?;
+    pool.total_fixed_borrows = pool.total_fixed_borrows.checked_sub(loan.principal)
+        .ok_or(ProgramError::Overflow)?;
+
+    check_solvency(&pool, pool_token_account)?;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
+
+    // Close the loan account and return rent to the borrower
+    let dest_starting_lamports = borrower_account.lamports();
+    **borrower_account.lamports.borrow_mut() = dest_starting_lamports
+        .checked_add(fixed_loan_account.lamports())
+        .ok_or(ProgramError::Overflow)?;
+    **fixed_loan_account.lamports.borrow_mut() = 0;
+    fixed_loan_account.data.borrow_mut().fill(0);
+
+    Ok(())
+}
+
+fn process_rollover_fixed_loan(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_lending_info = next_account_info(account_info_iter)?;
+    let borrower_account = next_account_info(account_info_iter)?;
+    let fixed_loan_account = next_account_info(account_info_iter)?;
+    let user_token_account = next_account_info(account_info_iter)?;
+    let pool_token_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    // Remaining accounts: collateral asset accounts for each position entry
+    let asset_accounts = account_info_iter.as_slice();
+    let clock = Clock::get()?;
+
+    if !borrower_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let term = i64::try_from_slice(instruction_data)?;
+    if term < MIN_FIXED_TERM || term > MAX_FIXED_TERM {
+        return Err(LendingError::InvalidLoanTerm.into());
+    }
+
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if *pool_token_account.key != pool.lending_token_account {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+    let mut loan = load_fixed_loan(program_id, pool_account, fixed_loan_account)?;
+    if loan.owner != *borrower_account.key || user_info.owner != *borrower_account.key {
+        return Err(LendingError::InvalidOwner.into());
+    }
+
+    if clock.unix_timestamp < loan.maturity_time {
+        return Err(LendingError::LoanNotMatured.into());
+    }
+
+    // Update interest before rollover
+    update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
+
+    // The rolled-over principal must still be covered by collateral
+    let collateral_value = position_collateral_value(program_id, pool_account, &pool, &user_info, asset_accounts, clock.unix_timestamp)?;
+    if !check_collateral_ratio(&pool, collateral_value, total_debt(&user_info)?)? {
+        return Err(LendingError::InsufficientCollateral.into());
+    }
+
+    // Settle interest for the finished term and any overdue time; principal carries over
+    let interest = fixed_interest_due(&pool, &loan, clock.unix_timestamp)?;
+    transfer_to_vault(
+        borrower_account,
+        user_token_account,
+        pool_token_account,
+        token_program,
+        interest,
+    )?;
+
+    distribute_fixed_earnings(&mut pool, interest)?;
+
+    loan.fixed_rate = pool.borrowing_rate.checked_add(FIXED_RATE_PREMIUM_BPS)
+        .ok_or(ProgramError::Overflow)?;
+    loan.start_time = clock.unix_timestamp;
+    loan.maturity_time = clock.unix_timestamp.checked_add(term)
+        .ok_or(ProgramError::Overflow)?;
+    loan.early_repayment_penalty_bps = EARLY_REPAYMENT_PENALTY_BPS;
+
+    loan.serialize(&mut *fixed_loan_account.data.borrow_mut())?;
+    check_solvency(&pool, pool_token_account)?;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn load_fixed_loan(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo,
+    fixed_loan_account: &AccountInfo,
+) -> Result<FixedLoan, ProgramError> {
+    if fixed_loan_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let loan = FixedLoan::try_from_slice(&fixed_loan_account.data.borrow())?;
+    if loan.pool != *pool_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(loan)
+}
+
+// Interest owed at the locked rate from origination until `end_time`
+fn fixed_interest(
+    loan: &FixedLoan,
+    end_time: i64,
+) -> Result<u64, ProgramError> {
+    let time_elapsed = std::cmp::max(end_time - loan.start_time, 0) as u128;
+    let interest = (loan.principal as u128)
+        .checked_mul(loan.fixed_rate as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_mul(time_elapsed)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(SECONDS_PER_YEAR as u128 * 10000)
+        .ok_or(ProgramError::Overflow)?;
+
+    u64::try_from(interest).map_err(|_| ProgramError::Overflow)
+}
+
+// Interest owed on a fixed loan at `now`. Past maturity the loan keeps accruing
+// at the variable rate plus OVERDUE_PENALTY_BPS until it is repaid or rolled over
+fn fixed_interest_due(
+    pool: &LendingPool,
+    loan: &FixedLoan,
+    now: i64,
+) -> Result<u64, ProgramError> {
+    let interest = fixed_interest(loan, std::cmp::min(now, loan.maturity_time))?;
+    if now <= loan.maturity_time {
+        return Ok(interest);
+    }
+
+    let overdue_rate = pool.borrowing_rate.checked_add(OVERDUE_PENALTY_BPS)
+        .ok_or(ProgramError::Overflow)?;
+    let overdue_interest = (loan.principal as u128)
+        .checked_mul(overdue_rate as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_mul((now - loan.maturity_time) as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(SECONDS_PER_YEAR as u128 * 10000)
+        .ok_or(ProgramError::Overflow)?;
+    let overdue_interest = u64::try_from(overdue_interest).map_err(|_| ProgramError::Overflow)?;
+
+    interest.checked_add(overdue_interest).ok_or(ProgramError::Overflow)
+}
+
+// Repayments settle accrued interest before principal; the supplier share
+// of settled interest becomes claimable
+fn settle_interest(
+    pool: &mut LendingPool,
+    user: &mut UserLendingInfo,
+    repay_amount: u64,
+) -> ProgramResult {
+    let interest_paid = std::cmp::min(repay_amount, user.unpaid_borrow_interest);
+    user.unpaid_borrow_interest = user.unpaid_borrow_interest.checked_sub(interest_paid)
+        .ok_or(ProgramError::Overflow)?;
+
+    let supplier_share = interest_paid
+        .checked_mul(10000 - pool.reserve_factor)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::Overflow)?;
+
+    // Pool and user accruals round independently, so never underflow here
+    pool.unpaid_interest = pool.unpaid_interest.saturating_sub(interest_paid);
+    pool.funded_interest = pool.funded_interest.checked_add(supplier_share)
+        .ok_or(ProgramError::Overflow)?;
+
+    Ok(())
+}
+
+// Vault cash plus outstanding loans must cover what is owed to suppliers
+// and the protocol
+fn check_solvency(
+    pool: &LendingPool,
+    pool_token_account: &AccountInfo,
+) -> ProgramResult {
+    let cash = TokenAccount::unpack(&pool_token_account.data.borrow())?.amount as u128;
+    let assets = cash
+        .checked_add(pool.total_borrows as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_add(pool.total_fixed_borrows as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_add(pool.total_stable_borrows as u128)
+        .ok_or(ProgramError::Overflow)?;
+    let liabilities = (pool.total_deposits as u128)
+        .checked_add(pool.total_reserves as u128)
+        .ok_or(ProgramError::Overflow)?;
+
+    if assets < liabilities {
+        msg!("Solvency check failed: assets {}, liabilities {}", assets, liabilities);
+        return Err(LendingError::PoolInsolvent.into());
+    }
+
+    Ok(())
+}
+
+// Splits fixed-loan interest and penalties between suppliers and reserves
+fn distribute_fixed_earnings(
+    pool: &mut LendingPool,
+    earnings: u64,
+) -> ProgramResult {
+    let reserve_share = earnings
+        .checked_mul(pool.reserve_factor)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::Overflow)?;
+    let supplier_share = earnings.checked_sub(reserve_share)
+        .ok_or(ProgramError::Overflow)?;
+
+    pool.supply_index = grow_index(
+        pool.supply_index,
+        supplier_share as u128,
+        pool.total_deposits as u128,
+    )?;
+    pool.total_deposits = pool.total_deposits.checked_add(supplier_share)
+        .ok_or(ProgramError::Overflow)?;
+    pool.total_reserves = pool.total_reserves.checked_add(reserve_share)
+        .ok_or(ProgramError::Overflow)?;
+    // Fixed-rate interest is collected in full at repayment or rollover
+    pool.funded_interest = pool.funded_interest.checked_add(supplier_share)
+        .ok_or(ProgramError::Overflow)?;
+
+    Ok(())
+}
+
+fn process_set_paused(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    paused: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let guardian_account = next_account_info(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if pool.guardian != *guardian_account.key {
+        return Err(LendingError::InvalidAuthority.into());
+    }
+    if !guardian_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // Repay and liquidation stay available while paused
+    pool.is_paused = paused;
+
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_claim_interest(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_lending_info = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
+    let user_token_account = next_account_info(account_info_iter)?;
+    let pool_token_account = next_account_info(account_info_iter)?;
+    let receipt_mint = next_account_info(account_info_iter)?;
+    let user_receipt_account = next_account_info(account_info_iter)?;
+    let fee_wallet_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    // Verify fee wallet
+    if fee_wallet_account.key.to_string() != FEE_WALLET {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if pool.is_paused {
+        return Err(LendingError::PoolPaused.into());
+    }
+    if *pool_token_account.key != pool.lending_token_account
+        || *receipt_mint.key != pool.receipt_mint
+    {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+    check_position_owner(&mut user_info, user_authority, account_info_iter)?;
+
+    // Update interest before claiming
+    update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
+
+    // Only interest borrowers have actually paid in can be claimed
+    let interest = std::cmp::min(user_info.cumulative_deposit_interest, user_info.deposited_amount);
+    let interest = std::cmp::min(interest, pool.funded_interest);
+    if interest == 0 {
+        return Ok(());
+    }
+
+    if interest > available_liquidity(&pool)? {
+        return Err(LendingError::InsufficientLiquidity.into());
+    }
+
+    // Interest is part of the receipt token claim, so redeem the matching
+    // receipts (rounded up in favour of the pool)
+    let receipt_supply = spl_token::state::Mint::unpack(&receipt_mint.data.borrow())?.supply;
+    let receipt_amount = (interest as u128)
+        .checked_mul(receipt_supply as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_add(pool.total_deposits as u128 - 1)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(pool.total_deposits as u128)
+        .ok_or(ProgramError::Overflow)?;
+    let receipt_amount = u64::try_from(receipt_amount).map_err(|_| ProgramError::Overflow)?;
+
+    let user_receipts = TokenAccount::unpack(&user_receipt_account.data.borrow())?.amount;
+    if receipt_amount > user_receipts {
+        return Err(LendingError::InsufficientLiquidity.into());
+    }
+
+    invoke(
+        &spl_token::instruction::burn(
+            token_program.key,
+            user_receipt_account.key,
+            receipt_mint.key,
+            user_authority.key,
+            &[],
+            receipt_amount,
+        )?,
+        &[
+            user_receipt_account.clone(),
+            receipt_mint.clone(),
+            user_authority.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    // Calculate service fee
+    let fee_amount = interest
+        .checked_mul(SERVICE_FEE_BPS)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::Overflow)?;
+    let user_interest = interest.checked_sub(fee_amount)
+        .ok_or(ProgramError::Overflow)?;
+
+    check_vault_authority(program_id, pool_account, &pool, vault_authority)?;
+
+    // Transfer interest to user
+    transfer_from_vault(
+        pool_account,
+        &pool,
+        vault_authority,
+        pool_token_account,
+        user_token_account,
+        token_program,
+        user_interest,
+    )?;
+
+    // Transfer fee to fee wallet
+    transfer_from_vault(
+        pool_account,
+        &pool,
+        vault_authority,
+        pool_token_account,
+        fee_wallet_account,
+        token_program,
+        fee_amount,
+    )?;
+
+    user_info.deposited_amount = user_info.deposited_amount.checked_sub(interest)
+        .ok_or(ProgramError::Overflow)?;
+    user_info.cumulative_deposit_interest = user_info.cumulative_deposit_interest
+        .checked_sub(interest)
+        .ok_or(ProgramError::Overflow)?;
+    pool.funded_interest = pool.funded_interest.checked_sub(interest)
+        .ok_or(ProgramError::Overflow)?;
+    pool.total_deposits = pool.total_deposits.checked_sub(interest)
+        .ok_or(ProgramError::Overflow)?;
+
+    check_solvency(&pool, pool_token_account)?;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_add_collateral_asset(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let collateral_mint = next_account_info(account_info_iter)?;
+    let collateral_vault = next_account_info(account_info_iter)?;
+    let price_authority = next_account_info(account_info_iter)?;
+    let asset_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if pool.pool_authority != *authority_account.key {
+        return Err(LendingError::InvalidAuthority.into());
+    }
+    if !authority_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let price = u64::try_from_slice(instruction_data)?;
+    if price == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // The vault must be owned by the pool's vault authority PDA
+    let vault_authority = Pubkey::create_program_address(
+        &[VAULT_AUTHORITY_SEED, pool_account.key.as_ref(), &[pool.vault_authority_bump]],
+        program_id,
+    )?;
+    let vault = TokenAccount::unpack(&collateral_vault.data.borrow())?;
+    if vault.owner != vault_authority || vault.mint != *collateral_mint.key {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let (expected_asset, bump) = Pubkey::find_program_address(
+        &[COLLATERAL_ASSET_SEED, pool_account.key.as_ref(), collateral_mint.key.as_ref()],
+        program_id,
+    );
+    if expected_asset != *asset_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !asset_account.data_is_empty() {
+        return Err(LendingError::AlreadyInUse.into());
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            authority_account.key,
+            asset_account.key,
+            rent.minimum_balance(CollateralAsset::LEN),
+            CollateralAsset::LEN as u64,
+            program_id,
+        ),
+        &[
+            authority_account.clone(),
+            asset_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[
+            COLLATERAL_ASSET_SEED,
+            pool_account.key.as_ref(),
+            collateral_mint.key.as_ref(),
+            &[bump],
+        ]],
+    )?;
+
+    let asset = CollateralAsset {
+        pool: *pool_account.key,
+        mint: *collateral_mint.key,
+        vault: *collateral_vault.key,
+        price_authority: *price_authority.key,
+        price,
+        confidence: 0,
+        last_price_update: clock.unix_timestamp,
+        total_amount: 0,
+        twap_window: 0,
+        twap_pool: Pubkey::default(),
+        twap_program: Pubkey::default(),
+        twap_collateral_is_a: false,
+        observation_index: 0,
+        observations: Vec::new(),
+    };
+    asset.serialize(&mut *asset_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_update_price(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let asset_account = next_account_info(account_info_iter)?;
+    let price_authority = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    if asset_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut asset = CollateralAsset::unpack(&asset_account.data.borrow())?;
+    if asset.price_authority != *price_authority.key {
+        return Err(LendingError::InvalidAuthority.into());
+    }
+    if !price_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let update = PriceUpdate::try_from_slice(instruction_data)?;
+    if update.price == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    asset.price = update.price;
+    asset.confidence = update.confidence;
+    asset.last_price_update = clock.unix_timestamp;
+
+    asset.serialize(&mut *asset_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_mark_for_liquidation(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let borrower_lending_info = next_account_info(account_info_iter)?;
+    let keeper_account = next_account_info(account_info_iter)?;
+    let keeper_token_account = next_account_info(account_info_iter)?;
+    let pool_token_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    // Remaining accounts: collateral asset accounts for each position entry
+    let asset_accounts = account_info_iter.as_slice();
+    let clock = Clock::get()?;
+
+    if !keeper_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if *pool_token_account.key != pool.lending_token_account {
+        return Err(LendingError::InvalidTokenAccount.into());
+    }
+
+    let mut borrower_info = load_user_info(program_id, pool_account, borrower_lending_info)?;
+    update_interest(&mut pool, &mut borrower_info, clock.unix_timestamp)?;
+
+    let collateral_value = position_collateral_value(program_id, pool_account, &pool, &borrower_info, asset_accounts, clock.unix_timestamp)?;
+    let healthy = check_liquidation_threshold(&pool, collateral_value, total_debt(&borrower_info)?)?;
+
+    if healthy {
+        // Clear a stale mark on a position that has recovered; no tip is paid
+        if borrower_info.marked_for_liquidation_at == 0 {
+            return Err(LendingError::PositionNotLiquidatable.into());
+        }
+        borrower_info.marked_for_liquidation_at = 0;
+    } else {
+        if borrower_info.marked_for_liquidation_at != 0 {
+            return Err(LendingError::AlreadyMarked.into());
+        }
+        borrower_info.marked_for_liquidation_at = clock.unix_timestamp;
+
+        // Tip the keeper out of reserves, if any are available and the position
+        // has not paid one recently
+        let tip_due = borrower_info.last_tip_paid_at == 0
+            || clock.unix_timestamp - borrower_info.last_tip_paid_at >= KEEPER_TIP_INTERVAL;
+        let tip = if tip_due { std::cmp::min(pool.keeper_tip, pool.total_reserves) } else { 0 };
+        if tip > 0 {
+            borrower_info.last_tip_paid_at = clock.unix_timestamp;
+            check_vault_authority(program_id, pool_account, &pool, vault_authority)?;
+            transfer_from_vault(
+                pool_account,
+                &pool,
+                vault_authority,
+                pool_token_account,
+                keeper_token_account,
+                token_program,
+                tip,
+            )?;
+            pool.total_reserves = pool.total_reserves.checked_sub(tip)
+                .ok_or(ProgramError::Overflow)?;
+        }
+
+        msg!("Position marked for liquidation at {}", clock.unix_timestamp);
+    }
+
+    check_solvency(&pool, pool_token_account)?;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    borrower_info.serialize(&mut *borrower_lending_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_close_user_account(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_lending_info = next_account_info(account_info_iter)?;
+    let owner_account = next_account_info(account_info_iter)?;
+
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+    if position_owner(&user_info, account_info_iter)? != *owner_account.key {
+        return Err(LendingError::InvalidOwner.into());
+    }
+
+    // Deposits are held as receipt tokens outside the position and survive its closing
+    if user_info.borrowed_amount != 0
+        || user_info.fixed_borrowed_amount != 0
+        || user_info.stable_borrowed_amount != 0
+        || user_info.collateral.iter().any(|position| position.amount != 0)
+    {
+        return Err(LendingError::NonZeroBalance.into());
+    }
+
+    // Close the account and return rent to the owner
+    let dest_starting_lamports = owner_account.lamports();
+    **owner_account.lamports.borrow_mut() = dest_starting_lamports
+        .checked_add(user_lending_info.lamports())
+        .ok_or(ProgramError::Overflow)?;
+    **user_lending_info.lamports.borrow_mut() = 0;
+    user_lending_info.data.borrow_mut().fill(0);
+
+    Ok(())
+}
+
+fn process_tokenize_position(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_lending_info = next_account_info(account_info_iter)?;
+    let owner_account = next_account_info(account_info_iter)?;
+    let position_mint = next_account_info(account_info_iter)?;
+    let position_token_account = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+    if user_info.owner != *owner_account.key {
+        return Err(LendingError::InvalidOwner.into());
+    }
+    if user_info.position_mint.is_some() || user_info.fixed_borrowed_amount != 0 {
+        return Err(LendingError::PositionTokenized.into());
+    }
+
+    check_vault_authority(program_id, pool_account, &pool, vault_authority)?;
+
+    // Create the position mint and the owner's token account holding the NFT
+    let rent = Rent::get()?;
+    invoke(
+        &system_instruction::create_account(
+            owner_account.key,
+            position_mint.key,
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            token_program.key,
+        ),
+        &[
+            owner_account.clone(),
+            position_mint.clone(),
+            system_program.clone(),
+        ],
+    )?;
+    invoke(
+        &spl_token::instruction::initialize_mint2(
+            token_program.key,
+            position_mint.key,
+            vault_authority.key,
+            None,
+            0,
+        )?,
+        &[position_mint.clone(), token_program.clone()],
+    )?;
+
+    invoke(
+        &system_instruction::create_account(
+            owner_account.key,
+            position_token_account.key,
+            rent.minimum_balance(TokenAccount::LEN),
+            TokenAccount::LEN as u64,
+            token_program.key,
+        ),
+        &[
+            owner_account.clone(),
+            position_token_account.clone(),
+            system_program.clone(),
+        ],
+    )?;
+    invoke(
+        &spl_token::instruction::initialize_account3(
+            token_program.key,
+            position_token_account.key,
+            position_mint.key,
+            owner_account.key,
+        )?,
+        &[
+            position_token_account.clone(),
+            position_mint.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    mint_receipt(
+        pool_account,
+        &pool,
+        vault_authority,
+        position_mint,
+        position_token_account,
+        token_program,
+        1,
+    )?;
+
+    // Drop the mint authority so the supply stays fixed at one
+    invoke_signed(
+        &spl_token::instruction::set_authority(
+            token_program.key,
+            position_mint.key,
+            None,
+            spl_token::instruction::AuthorityType::MintTokens,
+            vault_authority.key,
+            &[],
+        )?,
+        &[
+            position_mint.clone(),
+            vault_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&[VAULT_AUTHORITY_SEED, pool_account.key.as_ref(), &[pool.vault_authority_bump]]],
+    )?;
+
+    user_info.position_mint = Some(*position_mint.key);
+    user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_configure_twap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let asset_account = next_account_info(account_info_iter)?;
+    let amm_pool_account = next_account_info(account_info_iter)?;
+
+    let pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if pool.pool_authority != *authority_account.key {
+        return Err(LendingError::InvalidAuthority.into());
+    }
+    if !authority_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut asset = load_collateral_asset(program_id, pool_account, asset_account)?;
+
+    // A zero window switches the asset back to spot pricing
+    let twap_window = i64::try_from_slice(instruction_data)?;
+    if twap_window < 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if twap_window > 0 {
+        // Only the workspace AMM lays out PoolState as read below; any other owner
+        // could serve a forged accumulator
+        if *amm_pool_account.owner != LIQUIDITY_POOL_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        // The liquidity pool must pair the collateral with the borrowed token
+        let amm_pool = LiquidityPoolState::deserialize(&mut &amm_pool_account.data.borrow()[..])?;
+        let collateral_is_a = if amm_pool.token_a_mint == asset.mint
+            && amm_pool.token_b_mint == pool.token_mint
+        {
+            true
+        } else if amm_pool.token_b_mint == asset.mint
+            && amm_pool.token_a_mint == pool.token_mint
+        {
+            false
+        } else {
+            return Err(ProgramError::InvalidAccountData);
+        };
+
+        // Start a fresh buffer when the source changes
+        if asset.twap_pool != *amm_pool_account.key {
+            asset.observations.clear();
+            asset.observation_index = 0;
+        }
+        asset.twap_pool = *amm_pool_account.key;
+        asset.twap_program = *amm_pool_account.owner;
+        asset.twap_collateral_is_a = collateral_is_a;
+    }
+    asset.twap_window = twap_window;
+
+    asset.serialize(&mut *asset_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_record_price_observation(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let asset_account = next_account_info(account_info_iter)?;
+    let amm_pool_account = next_account_info(account_info_iter)?;
+
+    if asset_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut asset = CollateralAsset::unpack(&asset_account.data.borrow())?;
+    if asset.twap_window == 0
+        || asset.twap_pool != *amm_pool_account.key
+        || asset.twap_program != *amm_pool_account.owner
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let amm_pool = LiquidityPoolState::deserialize(&mut &amm_pool_account.data.borrow()[..])?;
+    let observation = PriceObservation {
+        timestamp: amm_pool.last_update_time,
+        price_cumulative: if asset.twap_collateral_is_a {
+            amm_pool.price_a_cumulative
+        } else {
+            amm_pool.price_b_cumulative
+        },
+    };
+
+    if record_observation(&mut asset, observation) {
+        asset.serialize(&mut *asset_account.data.borrow_mut())?;
+    }
+
+    Ok(())
+}
+
+// Adds `observation` to the ring buffer, dropping it when it comes sooner than
+// 1/(MAX_PRICE_OBSERVATIONS - 1) of the window after the latest one. A full buffer
+// then always reaches a window back, so spamming cannot evict the window start.
+// Returns whether the observation was kept
+fn record_observation(asset: &mut CollateralAsset, observation: PriceObservation) -> bool {
+    if let Some(latest) = asset.observations.get(asset.observation_index as usize) {
+        let gaps = (MAX_PRICE_OBSERVATIONS - 1) as i64;
+        let min_spacing = (asset.twap_window + gaps - 1) / gaps;
+        if observation.timestamp.saturating_sub(latest.timestamp) < min_spacing.max(1) {
+            return false;
+        }
+    }
+
+    // Fill the buffer, then overwrite the oldest slot
+    if asset.observations.len() < MAX_PRICE_OBSERVATIONS {
+        asset.observations.push(observation);
+        asset.observation_index = (asset.observations.len() - 1) as u8;
+    } else {
+        let index = (asset.observation_index as usize + 1) % MAX_PRICE_OBSERVATIONS;
+        asset.observations[index] = observation;
+        asset.observation_index = index as u8;
+    }
+    true
+}
+
+fn process_set_borrow_limit(
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    // Implement liquidation logic for undercollateralized positions
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    if pool.pool_authority != *authority_account.key {
+        return Err(LendingError::InvalidAuthority.into());
+    }
+    if !authority_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // None lifts the ceiling; existing positions above a new limit can
+    // still repay but not borrow more
+    pool.max_borrow_per_user = Option::<u64>::try_from_slice(instruction_data)?
+        .unwrap_or(u64::MAX);
+
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_rebalance_stable_rate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_lending_info = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let mut pool = LendingPool::try_from_slice(&pool_account.data.borrow())?;
+    let mut user_info = load_user_info(program_id, pool_account, user_lending_info)?;
+
+    update_interest(&mut pool, &mut user_info, clock.unix_timestamp)?;
+
+    if user_info.stable_borrowed_amount == 0 {
+        return Err(LendingError::RebalanceNotAllowed.into());
+    }
+
+    // Anyone may lift a stable rate to market once the market has risen past it by
+    // more than the threshold; a borrower whose rate is too high can repay and re-borrow
+    let market_rate = pool.borrowing_rate.checked_add(STABLE_RATE_PREMIUM_BPS)
+        .ok_or(ProgramError::Overflow)?;
+    let drift = market_rate.saturating_sub(user_info.stable_rate);
+    if drift <= STABLE_REBALANCE_THRESHOLD_BPS {
+        return Err(LendingError::RebalanceNotAllowed.into());
+    }
+
+    remove_stable_borrow(&mut pool, user_info.stable_borrowed_amount, user_info.stable_rate)?;
+    add_stable_borrow(&mut pool, user_info.stable_borrowed_amount, market_rate)?;
+
+    msg!("Stable rate rebalanced from {} to {}", user_info.stable_rate, market_rate);
+    user_info.stable_rate = market_rate;
+
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    user_info.serialize(&mut *user_lending_info.data.borrow_mut())?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn twap_asset(twap_window: i64) -> CollateralAsset {
+        CollateralAsset {
+            pool: Pubkey::default(),
+            mint: Pubkey::default(),
+            vault: Pubkey::default(),
+            price_authority: Pubkey::default(),
+            price: 0,
+            confidence: 0,
+            last_price_update: 0,
+            total_amount: 0,
+            twap_window,
+            twap_pool: Pubkey::default(),
+            twap_program: Pubkey::default(),
+            twap_collateral_is_a: false,
+            observation_index: 0,
+            observations: Vec::new(),
+        }
+    }
+
+    fn test_pool() -> LendingPool {
+        LendingPool {
+            is_initialized: true,
+            token_mint: Pubkey::default(),
+            pool_authority: Pubkey::default(),
+            guardian: Pubkey::default(),
+            is_paused: false,
+            vault_authority_bump: 0,
+            lending_token_account: Pubkey::default(),
+            receipt_mint: Pubkey::default(),
+            total_deposits: 0,
+            total_borrows: 0,
+            total_fixed_borrows: 0,
+            total_stable_borrows: 0,
+            average_stable_rate: 0,
+            total_reserves: 0,
+            unpaid_interest: 0,
+            funded_interest: 0,
+            last_update_time: 0,
+            borrow_index: INDEX_SCALE,
+            supply_index: INDEX_SCALE,
+            lending_rate: 0,
+            borrowing_rate: 0,
+            collateral_ratio: 0,
+            reserve_factor: 0,
+            liquidation_threshold_bps: 0,
+            liquidation_bonus_bps: 0,
+            auction_start_bonus_bps: 0,
+            auction_duration: 0,
+            liquidation_grace_period: 0,
+            hard_floor_bps: 0,
+            keeper_tip: 0,
+            max_price_age: 0,
+            max_confidence_bps: 0,
+            max_borrow_per_user: u64::MAX,
+            rate_model: InterestRateModel {
+                base_rate: 0,
+                slope1: 0,
+                slope2: 0,
+                optimal_utilization: 0,
+            },
+        }
+    }
+
+    fn test_user() -> UserLendingInfo {
+        UserLendingInfo {
+            wallet: Pubkey::default(),
+            bump: 0,
+            owner: Pubkey::default(),
+            deposited_amount: 0,
+            borrowed_amount: 0,
+            fixed_borrowed_amount: 0,
+            stable_borrowed_amount: 0,
+            stable_rate: 0,
+            last_update_time: 0,
+            borrow_index: INDEX_SCALE,
+            supply_index: INDEX_SCALE,
+            cumulative_deposit_interest: 0,
+            cumulative_borrow_interest: 0,
+            unpaid_borrow_interest: 0,
+            marked_for_liquidation_at: 0,
+            last_tip_paid_at: 0,
+            position_mint: None,
+            collateral: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn spammed_observations_cannot_evict_the_window_start() {
+        let window = 3_600;
+        let mut asset = twap_asset(window);
+
+        // One attempt per second for three windows
+        for timestamp in 0..=3 * window {
+            let observation = PriceObservation { timestamp, price_cumulative: timestamp as u128 };
+            record_observation(&mut asset, observation);
+        }
+
+        assert_eq!(asset.observations.len(), MAX_PRICE_OBSERVATIONS);
+        let newest = &asset.observations[asset.observation_index as usize];
+        let window_start = newest.timestamp - window;
+        assert!(asset.observations.iter().any(|observation| observation.timestamp <= window_start));
+    }
+
+    #[test]
+    fn observations_closer_than_the_spacing_are_dropped() {
+        let mut asset = twap_asset(1_500);
+        let observation = |timestamp| PriceObservation { timestamp, price_cumulative: 0 };
+
+        assert!(record_observation(&mut asset, observation(1_000)));
+        assert!(!record_observation(&mut asset, observation(1_099)));
+        assert!(record_observation(&mut asset, observation(1_100)));
+        assert_eq!(asset.observations.len(), 2);
+    }
+
+    #[test]
+    fn withdrawals_reduce_the_position_and_its_claimable_interest() {
+        let mut pool = test_pool();
+        let mut user = test_user();
+        record_deposit(&mut pool, &mut user, 1_000).unwrap();
+
+        // 100 of interest accrues to the deposit and is repaid by borrowers
+        user.deposited_amount += 100;
+        user.cumulative_deposit_interest = 100;
+        pool.total_deposits += 100;
+        pool.funded_interest = 100;
+
+        record_withdrawal(&mut pool, &mut user, 550).unwrap();
+        assert_eq!(user.deposited_amount, 550);
+        assert_eq!(user.cumulative_deposit_interest, 50);
+        assert_eq!(pool.funded_interest, 50);
+        assert_eq!(pool.total_deposits, 550);
+
+        record_withdrawal(&mut pool, &mut user, 550).unwrap();
+        assert_eq!(user.deposited_amount, 0);
+        assert_eq!(user.cumulative_deposit_interest, 0);
+        assert_eq!(pool.funded_interest, 0);
+        assert_eq!(pool.total_deposits, 0);
+    }
+
+    #[test]
+    fn redeeming_transferred_receipts_clears_only_the_recorded_deposit() {
+        let mut pool = test_pool();
+        let mut user = test_user();
+        let mut other = test_user();
+        record_deposit(&mut pool, &mut user, 400).unwrap();
+        record_deposit(&mut pool, &mut other, 600).unwrap();
+
+        // The first depositor also redeems receipts received from the other
+        record_withdrawal(&mut pool, &mut user, 700).unwrap();
+        assert_eq!(user.deposited_amount, 0);
+        assert_eq!(other.deposited_amount, 600);
+        assert_eq!(pool.total_deposits, 300);
+    }
+}
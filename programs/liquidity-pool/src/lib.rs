@@ -1,20 +1,81 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use num_derive::FromPrimitive;
+use num_traits::FromPrimitive as _;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::Clock,
     entrypoint,
     entrypoint::ProgramResult,
     msg,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
     pubkey::Pubkey,
-    program_pack::{IsInitialized, Pack, Sealed},
+    program_pack::Pack,
+    program_option::COption,
+    system_instruction,
     sysvar::{rent::Rent, Sysvar},
 };
 use spl_token::state::Account as TokenAccount;
 use thiserror::Error;
 
 // Program ID
-solana_program::declare_id!("LiquidityPool11111111111111111111111111111111");
+solana_program::declare_id!("LiquidityPoo1111111111111111111111111111111");
+
+// Fixed-point scale of the price accumulators
+pub const PRICE_SCALE: u128 = 1_000_000_000;
+
+// StableSwap amplification bounds and Newton iteration cap
+pub const MAX_AMP: u64 = 10_000;
+pub const STABLE_ITERATIONS: usize = 64;
+
+// Most of the swap fee the protocol may take, in basis points of the fee
+pub const MAX_PROTOCOL_FEE_BPS: u64 = 5_000;
+
+// Longest chain of swaps a single Route instruction executes
+pub const MAX_ROUTE_HOPS: u8 = 3;
+
+// Owner of the pool vaults and mint authority of the LP mint: [POOL_AUTHORITY_SEED, pool]
+pub const POOL_AUTHORITY_SEED: &[u8] = b"pool_authority";
+
+// Concentrated liquidity: tick bounds, sqrt prices in Q64.64 and the per-pool tick capacity
+pub const MIN_TICK: i32 = -443_636;
+pub const MAX_TICK: i32 = 443_636;
+pub const MAX_TICK_SPACING: u64 = 16_384;
+pub const MAX_INITIALIZED_TICKS: usize = 128;
+pub const Q64: u128 = 1 << 64;
+
+// 2^128 / sqrt(1.0001)^(2^bit) for each bit of a tick's magnitude
+const SQRT_RATIO_FACTORS: [u128; 19] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x09aa508b5b7a84e1c677de54f3e99bc9,
+    0x005d6af8dedb81196699c329225ee604,
+    0x00002216e584f5fa1ea926041bedfe98,
+];
+
+// Ticks per unit of log2(sqrt price), 2 / log2(1.0001), in Q32.32
+const TICKS_PER_LOG2_X32: i128 = 59_543_866_431_248;
+const LOG2_FRACTION_BITS: u32 = 16;
+
+// Initialized ticks of a concentrated pool: [TICKS_SEED, pool]
+pub const TICKS_SEED: &[u8] = b"ticks";
+
+// One account per LP range: [POSITION_SEED, pool, owner, tick_lower LE, tick_upper LE]
+pub const POSITION_SEED: &[u8] = b"position";
 
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
 pub struct PoolState {
@@ -27,6 +88,84 @@ pub struct PoolState {
     pub token_a_amount: u64,
     pub token_b_amount: u64,
     pub fee_rate: u64,  // Fee rate in basis points (1/10000)
+    pub price_a_cumulative: u128, // Sum of (token B per token A * PRICE_SCALE) * seconds
+    pub price_b_cumulative: u128, // Sum of (token A per token B * PRICE_SCALE) * seconds
+    pub last_update_time: i64,    // Time the accumulators were last advanced
+    pub curve_type: CurveType,
+    pub amp: u64,                 // StableSwap amplification coefficient
+    pub tick_spacing: u64,        // Concentrated pools: position bounds are multiples of this
+    pub sqrt_price_x64: u128,     // Concentrated pools: sqrt(token B per token A) in Q64.64
+    pub current_tick: i32,
+    pub liquidity: u128,          // Liquidity of the positions whose range holds the price
+    pub fee_growth_global_a: u128, // Fees per unit of liquidity in Q64.64; wraps
+    pub fee_growth_global_b: u128,
+    pub fee_authority: Pubkey,    // May withdraw the protocol fee balances
+    pub protocol_fee_bps: u64,    // Share of each swap fee kept for the protocol
+    pub protocol_fees_a: u64,     // Held in the vaults but excluded from the reserves
+    pub protocol_fees_b: u64,
+}
+
+#[derive(Clone, Copy, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub enum CurveType {
+    ConstantProduct,
+    StableSwap,  // Amplified invariant for pegged pairs
+    Concentrated,  // Liquidity placed over tick ranges by individual positions
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct TickState {
+    pub tick: i32,
+    pub liquidity_net: i128,    // Added to the active liquidity when crossed upward
+    pub liquidity_gross: u128,  // Liquidity of all positions bounded by this tick
+    pub fee_growth_outside_a: u128, // Fee growth on the side of the tick away from the price
+    pub fee_growth_outside_b: u128,
+}
+
+impl TickState {
+    pub const LEN: usize = 4 + 16 + 16 + 16 + 16;
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct TickList {
+    pub pool: Pubkey,
+    pub ticks: Vec<TickState>,  // Sorted by tick
+}
+
+impl TickList {
+    pub const LEN: usize = 32 + 4 + MAX_INITIALIZED_TICKS * TickState::LEN;
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct Position {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    pub fee_growth_inside_a_last: u128,
+    pub fee_growth_inside_b_last: u128,
+    pub tokens_owed_a: u64,
+    pub tokens_owed_b: u64,
+}
+
+impl Position {
+    pub const LEN: usize = 32 + 32 + 4 + 4 + 16 + 16 + 16 + 8 + 8;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct PositionArgs {
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    pub amount_a_limit: u64,  // Most deposited on increase, least returned on decrease
+    pub amount_b_limit: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct RouteArgs {
+    pub amount_in: u64,
+    pub minimum_amount_out: u64,  // Checked against the last hop's output only
+    pub hops: u8,
 }
 
 #[derive(FromPrimitive, Debug)]
@@ -35,6 +174,11 @@ pub enum PoolInstruction {
     AddLiquidity,
     RemoveLiquidity,
     Swap,
+    Sync,
+    IncreasePosition,
+    DecreasePosition,
+    CollectProtocolFees,
+    Route,
 }
 
 #[derive(Error, Debug, Copy, Clone)]
@@ -51,6 +195,24 @@ pub enum PoolError {
     InvalidTokenAccount,
     #[error("Insufficient liquidity")]
     InsufficientLiquidity,
+    #[error("Invalid pool authority")]
+    InvalidAuthority,
+    #[error("Slippage exceeded")]
+    SlippageExceeded,
+    #[error("Deposit ratio deviates too far from pool price")]
+    PriceDeviationExceeded,
+    #[error("Curve calculation failed")]
+    CalculationFailure,
+    #[error("Operation not supported by the pool curve")]
+    UnsupportedCurve,
+    #[error("Invalid tick range")]
+    InvalidTickRange,
+    #[error("Too many initialized ticks")]
+    TickListFull,
+    #[error("Invalid tick list account")]
+    InvalidTickList,
+    #[error("Invalid position account")]
+    InvalidPosition,
 }
 
 impl From<PoolError> for ProgramError {
@@ -66,13 +228,14 @@ pub fn process_instruction(
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    let instruction = PoolInstruction::try_from_primitive(instruction_data[0])
-        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    let instruction = instruction_data.first()
+        .and_then(|tag| PoolInstruction::from_u8(*tag))
+        .ok_or(ProgramError::InvalidInstructionData)?;
 
     match instruction {
         PoolInstruction::Initialize => {
             msg!("Instruction: Initialize Pool");
-            process_initialize(program_id, accounts)
+            process_initialize(program_id, accounts, &instruction_data[1..])
         }
         PoolInstruction::AddLiquidity => {
             msg!("Instruction: Add Liquidity");
@@ -86,12 +249,33 @@ pub fn process_instruction(
             msg!("Instruction: Swap");
             process_swap(program_id, accounts, &instruction_data[1..])
         }
+        PoolInstruction::Sync => {
+            msg!("Instruction: Sync");
+            process_sync(program_id, accounts)
+        }
+        PoolInstruction::IncreasePosition => {
+            msg!("Instruction: Increase Position");
+            process_increase_position(program_id, accounts, &instruction_data[1..])
+        }
+        PoolInstruction::DecreasePosition => {
+            msg!("Instruction: Decrease Position");
+            process_decrease_position(program_id, accounts, &instruction_data[1..])
+        }
+        PoolInstruction::CollectProtocolFees => {
+            msg!("Instruction: Collect Protocol Fees");
+            process_collect_protocol_fees(program_id, accounts)
+        }
+        PoolInstruction::Route => {
+            msg!("Instruction: Route");
+            process_route(program_id, accounts, &instruction_data[1..])
+        }
     }
 }
 
 fn process_initialize(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
+    instruction_data: &[u8],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let pool_account = next_account_info(account_info_iter)?;
@@ -101,8 +285,14 @@ fn process_initialize(
     let pool_token_b = next_account_info(account_info_iter)?;
     let pool_mint = next_account_info(account_info_iter)?;
     let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let pool_authority = next_account_info(account_info_iter)?;
+    // Pools created without a fee authority never take a protocol fee
+    let fee_authority = next_account_info(account_info_iter).ok();
 
     // Verify account ownership and rent exemption
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
     if !rent.is_exempt(pool_account.lamports(), pool_account.data_len()) {
         return Err(PoolError::NotRentExempt.into());
     }
@@ -112,6 +302,50 @@ fn process_initialize(
         return Err(PoolError::AlreadyInUse.into());
     }
 
+    // The vaults and LP mint must already be controlled by the pool authority PDA
+    check_pool_authority(program_id, pool_account, pool_authority)?;
+    let vault_a = TokenAccount::unpack(&pool_token_a.data.borrow())?;
+    let vault_b = TokenAccount::unpack(&pool_token_b.data.borrow())?;
+    if vault_a.owner != *pool_authority.key
+        || vault_b.owner != *pool_authority.key
+        || vault_a.mint != *token_a_mint.key
+        || vault_b.mint != *token_b_mint.key
+    {
+        return Err(PoolError::InvalidTokenAccount.into());
+    }
+    let lp_mint = spl_token::state::Mint::unpack(&pool_mint.data.borrow())?;
+    if lp_mint.mint_authority != COption::Some(*pool_authority.key) {
+        return Err(PoolError::InvalidAuthority.into());
+    }
+
+    // No data keeps the constant-product curve; [1, amp] selects StableSwap and
+    // [2, tick_spacing, initial_tick as i64] concentrated liquidity. A u64 protocol
+    // fee share may follow those three fields.
+    let curve_type = match instruction_data.first() {
+        None | Some(0) => CurveType::ConstantProduct,
+        Some(1) => CurveType::StableSwap,
+        Some(2) => CurveType::Concentrated,
+        Some(_) => return Err(PoolError::InvalidInstruction.into()),
+    };
+    let curve_param = read_optional_u64(instruction_data, 1);
+    let initial_tick = read_optional_u64(instruction_data, 9) as i64;
+    let valid = match curve_type {
+        CurveType::ConstantProduct => true,
+        CurveType::StableSwap => (1..=MAX_AMP).contains(&curve_param),
+        CurveType::Concentrated => {
+            (1..=MAX_TICK_SPACING).contains(&curve_param)
+                && (MIN_TICK as i64..=MAX_TICK as i64).contains(&initial_tick)
+        }
+    };
+    let protocol_fee_bps = read_optional_u64(instruction_data, 17);
+    if !valid
+        || protocol_fee_bps > MAX_PROTOCOL_FEE_BPS
+        || (protocol_fee_bps > 0 && fee_authority.is_none())
+    {
+        return Err(PoolError::InvalidInstruction.into());
+    }
+    let concentrated = curve_type == CurveType::Concentrated;
+
     pool_state.is_initialized = true;
     pool_state.token_a_mint = *token_a_mint.key;
     pool_state.token_b_mint = *token_b_mint.key;
@@ -121,8 +355,24 @@ fn process_initialize(
     pool_state.token_a_amount = 0;
     pool_state.token_b_amount = 0;
     pool_state.fee_rate = 30; // 0.3% fee
+    pool_state.price_a_cumulative = 0;
+    pool_state.price_b_cumulative = 0;
+    pool_state.last_update_time = Clock::get()?.unix_timestamp;
+    pool_state.curve_type = curve_type;
+    pool_state.amp = if curve_type == CurveType::StableSwap { curve_param } else { 0 };
+    pool_state.tick_spacing = if concentrated { curve_param } else { 0 };
+    pool_state.sqrt_price_x64 =
+        if concentrated { sqrt_price_at_tick(initial_tick as i32) } else { 0 };
+    pool_state.current_tick = initial_tick as i32;
+    pool_state.liquidity = 0;
+    pool_state.fee_growth_global_a = 0;
+    pool_state.fee_growth_global_b = 0;
+    pool_state.fee_authority = fee_authority.map(|account| *account.key).unwrap_or_default();
+    pool_state.protocol_fee_bps = protocol_fee_bps;
+    pool_state.protocol_fees_a = 0;
+    pool_state.protocol_fees_b = 0;
 
-    pool_state.serialize(&mut *pool_account.data.borrow_mut())?;
+    pool_state.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
 
     Ok(())
 }
@@ -141,6 +391,8 @@ fn process_add_liquidity(
     let pool_mint = next_account_info(account_info_iter)?;
     let user_pool_token = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
+    let pool_authority = next_account_info(account_info_iter)?;
 
     let (amount_a, amount_b) = {
         let mut data = [0u8; 16];
@@ -149,48 +401,89 @@ fn process_add_liquidity(
         (u64::from_le_bytes(data[..8].try_into().unwrap()),
          u64::from_le_bytes(data[8..16].try_into().unwrap()))
     };
+    // Optional protections; callers sending only the two amounts get none
+    let min_lp_out = read_optional_u64(instruction_data, 16);
+    let max_deviation_bps = read_optional_u64(instruction_data, 24);
 
-    let pool_state = PoolState::try_from_slice(&pool_account.data.borrow())?;
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut pool_state = PoolState::try_from_slice(&pool_account.data.borrow())?;
+    check_pool_accounts(&pool_state, pool_token_a, pool_token_b)?;
+    // Concentrated pools take liquidity through positions instead of LP tokens
+    if pool_state.curve_type == CurveType::Concentrated {
+        return Err(PoolError::UnsupportedCurve.into());
+    }
+    if *pool_mint.key != pool_state.pool_mint {
+        return Err(PoolError::InvalidTokenAccount.into());
+    }
+    let authority_bump = check_pool_authority(program_id, pool_account, pool_authority)?;
+
+    // Advance the price accumulators with the reserves before this deposit
+    let reserve_a = pool_state.token_a_amount;
+    let reserve_b = pool_state.token_b_amount;
+    update_price_accumulators(&mut pool_state, reserve_a, reserve_b, Clock::get()?.unix_timestamp);
+
+    // Reject deposits whose B-per-A price strays too far from the pool's
+    if max_deviation_bps > 0 && reserve_a > 0 && reserve_b > 0 {
+        let deposit_price = amount_b as u128 * reserve_a as u128;
+        let pool_price = reserve_b as u128 * amount_a as u128;
+        let deviation = deposit_price
+            .abs_diff(pool_price)
+            .checked_mul(10000)
+            .ok_or(PoolError::CalculationFailure)?;
+        let tolerance = pool_price
+            .checked_mul(max_deviation_bps as u128)
+            .ok_or(PoolError::CalculationFailure)?;
+        if deviation > tolerance {
+            return Err(PoolError::PriceDeviationExceeded.into());
+        }
+    }
 
-    // Calculate pool tokens to mint
-    let pool_token_amount = if pool_state.token_a_amount == 0 {
+    // Later deposits get LP tokens in proportion to the smaller of their two shares
+    let lp_supply = spl_token::state::Mint::unpack(&pool_mint.data.borrow())?.supply;
+    let pool_token_amount = if lp_supply == 0 || reserve_a == 0 || reserve_b == 0 {
         (amount_a as f64 * amount_b as f64).sqrt() as u64
     } else {
         std::cmp::min(
-            amount_a * pool_state.token_b_amount / pool_state.token_a_amount,
-            amount_b * pool_state.token_a_amount / pool_state.token_b_amount,
+            (amount_a as u128 * lp_supply as u128 / reserve_a as u128) as u64,
+            (amount_b as u128 * lp_supply as u128 / reserve_b as u128) as u64,
         )
     };
 
-    // Transfer tokens to pool
-    spl_token::instruction::transfer(
-        token_program.key,
-        user_token_a.key,
-        pool_token_a.key,
-        &pool_account.key,
-        &[],
-        amount_a,
-    )?;
+    if pool_token_amount < min_lp_out {
+        return Err(PoolError::SlippageExceeded.into());
+    }
 
-    spl_token::instruction::transfer(
-        token_program.key,
-        user_token_b.key,
-        pool_token_b.key,
-        &pool_account.key,
-        &[],
-        amount_b,
-    )?;
+    // Transfer tokens to pool
+    transfer_from_user(token_program, user_token_a, pool_token_a, user_authority, amount_a)?;
+    transfer_from_user(token_program, user_token_b, pool_token_b, user_authority, amount_b)?;
 
     // Mint pool tokens to user
-    spl_token::instruction::mint_to(
-        token_program.key,
-        pool_mint.key,
-        user_pool_token.key,
-        &pool_account.key,
-        &[],
-        pool_token_amount,
+    invoke_signed(
+        &spl_token::instruction::mint_to(
+            token_program.key,
+            pool_mint.key,
+            user_pool_token.key,
+            pool_authority.key,
+            &[],
+            pool_token_amount,
+        )?,
+        &[
+            pool_mint.clone(),
+            user_pool_token.clone(),
+            pool_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&[POOL_AUTHORITY_SEED, pool_account.key.as_ref(), &[authority_bump]]],
     )?;
 
+    pool_state.token_a_amount =
+        reserve_a.checked_add(amount_a).ok_or(PoolError::CalculationFailure)?;
+    pool_state.token_b_amount =
+        reserve_b.checked_add(amount_b).ok_or(PoolError::CalculationFailure)?;
+    pool_state.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
+
     Ok(())
 }
 
@@ -205,50 +498,95 @@ fn process_remove_liquidity(
     let user_token_b = next_account_info(account_info_iter)?;
     let pool_token_a = next_account_info(account_info_iter)?;
     let pool_token_b = next_account_info(account_info_iter)?;
+    let pool_mint = next_account_info(account_info_iter)?;
     let user_pool_token = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
+    let pool_authority = next_account_info(account_info_iter)?;
 
     let pool_token_amount = {
         let mut data = [0u8; 8];
         data.copy_from_slice(&instruction_data[..8]);
         u64::from_le_bytes(data)
     };
+    let min_amount_a = read_optional_u64(instruction_data, 8);
+    let min_amount_b = read_optional_u64(instruction_data, 16);
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut pool_state = PoolState::try_from_slice(&pool_account.data.borrow())?;
+    check_pool_accounts(&pool_state, pool_token_a, pool_token_b)?;
+    // Concentrated pools take liquidity through positions instead of LP tokens
+    if pool_state.curve_type == CurveType::Concentrated {
+        return Err(PoolError::UnsupportedCurve.into());
+    }
+    if *pool_mint.key != pool_state.pool_mint {
+        return Err(PoolError::InvalidTokenAccount.into());
+    }
+    let authority_bump = check_pool_authority(program_id, pool_account, pool_authority)?;
 
-    let pool_state = PoolState::try_from_slice(&pool_account.data.borrow())?;
+    // Advance the price accumulators with the reserves before this withdrawal
+    let reserve_a = pool_state.token_a_amount;
+    let reserve_b = pool_state.token_b_amount;
+    update_price_accumulators(&mut pool_state, reserve_a, reserve_b, Clock::get()?.unix_timestamp);
 
     // Calculate token amounts to return
-    let total_pool_tokens = spl_token::state::Mint::unpack(&pool_account.data.borrow())?.supply;
-    let amount_a = pool_token_amount * pool_state.token_a_amount / total_pool_tokens;
-    let amount_b = pool_token_amount * pool_state.token_b_amount / total_pool_tokens;
+    let total_pool_tokens = spl_token::state::Mint::unpack(&pool_mint.data.borrow())?.supply;
+    if total_pool_tokens == 0 {
+        return Err(PoolError::InsufficientLiquidity.into());
+    }
+    let share = |reserve: u64| {
+        (pool_token_amount as u128 * reserve as u128 / total_pool_tokens as u128) as u64
+    };
+    let amount_a = share(reserve_a);
+    let amount_b = share(reserve_b);
+
+    if amount_a < min_amount_a || amount_b < min_amount_b {
+        return Err(PoolError::SlippageExceeded.into());
+    }
+
+    // Burn pool tokens
+    invoke(
+        &spl_token::instruction::burn(
+            token_program.key,
+            user_pool_token.key,
+            pool_mint.key,
+            user_authority.key,
+            &[],
+            pool_token_amount,
+        )?,
+        &[
+            user_pool_token.clone(),
+            pool_mint.clone(),
+            user_authority.clone(),
+            token_program.clone(),
+        ],
+    )?;
 
     // Transfer tokens from pool to user
-    spl_token::instruction::transfer(
-        token_program.key,
-        pool_token_a.key,
-        user_token_a.key,
-        &pool_account.key,
-        &[],
+    transfer_from_pool(
+        token_program,
+        pool_account,
+        pool_token_a,
+        user_token_a,
+        pool_authority,
+        authority_bump,
         amount_a,
     )?;
-
-    spl_token::instruction::transfer(
-        token_program.key,
-        pool_token_b.key,
-        user_token_b.key,
-        &pool_account.key,
-        &[],
+    transfer_from_pool(
+        token_program,
+        pool_account,
+        pool_token_b,
+        user_token_b,
+        pool_authority,
+        authority_bump,
         amount_b,
     )?;
 
-    // Burn pool tokens
-    spl_token::instruction::burn(
-        token_program.key,
-        user_pool_token.key,
-        &pool_account.key,
-        &pool_account.key,
-        &[],
-        pool_token_amount,
-    )?;
+    pool_state.token_a_amount = reserve_a - amount_a;
+    pool_state.token_b_amount = reserve_b - amount_b;
+    pool_state.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
 
     Ok(())
 }
@@ -265,57 +603,1391 @@ fn process_swap(
     let pool_source = next_account_info(account_info_iter)?;
     let pool_destination = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
+    let pool_authority = next_account_info(account_info_iter)?;
 
-    let amount_in = {
-        let mut data = [0u8; 8];
-        data.copy_from_slice(&instruction_data[..8]);
-        u64::from_le_bytes(data)
+    let (amount_in, minimum_amount_out) = {
+        let mut data = [0u8; 16];
+        data.copy_from_slice(&instruction_data[..16]);
+        (u64::from_le_bytes(data[..8].try_into().unwrap()),
+         u64::from_le_bytes(data[8..16].try_into().unwrap()))
     };
 
-    let pool_state = PoolState::try_from_slice(&pool_account.data.borrow())?;
+    // Concentrated pools take their tick list as a trailing account
+    let tick_list_account = next_account_info(account_info_iter).ok();
 
-    // Calculate amount out using constant product formula
-    let source_amount = TokenAccount::unpack(&pool_source.data.borrow())?.amount;
-    let destination_amount = TokenAccount::unpack(&pool_destination.data.borrow())?.amount;
-    
-    let amount_out = calculate_output_amount(
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    swap_through_pool(
+        program_id,
+        pool_account,
+        user_source,
+        user_destination,
+        pool_source,
+        pool_destination,
+        token_program,
+        user_authority,
+        pool_authority,
+        tick_list_account,
         amount_in,
-        source_amount,
-        destination_amount,
-        pool_state.fee_rate,
+        minimum_amount_out,
     )?;
 
+    Ok(())
+}
+
+// Chains up to MAX_ROUTE_HOPS swaps, each hop paying into the user token account the
+// next one spends from, so pairs without a shared pool can be traded in one instruction.
+// Accounts: token_program, user_authority, user_source, then per hop: pool, pool_source,
+// pool_destination, pool_authority, user_destination and, for a concentrated pool, its
+// tick list.
+fn process_route(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let token_program = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
+    let mut user_source = next_account_info(account_info_iter)?;
+
+    let args = RouteArgs::try_from_slice(instruction_data)?;
+    if !(1..=MAX_ROUTE_HOPS).contains(&args.hops) {
+        return Err(PoolError::InvalidInstruction.into());
+    }
+
+    let mut amount = args.amount_in;
+    for _ in 0..args.hops {
+        let pool_account = next_account_info(account_info_iter)?;
+        let pool_source = next_account_info(account_info_iter)?;
+        let pool_destination = next_account_info(account_info_iter)?;
+        let pool_authority = next_account_info(account_info_iter)?;
+        let user_destination = next_account_info(account_info_iter)?;
+        if pool_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let curve_type = PoolState::try_from_slice(&pool_account.data.borrow())?.curve_type;
+        let tick_list_account = if curve_type == CurveType::Concentrated {
+            Some(next_account_info(account_info_iter)?)
+        } else {
+            None
+        };
+
+        // Intermediate hops carry no bound of their own
+        amount = swap_through_pool(
+            program_id,
+            pool_account,
+            user_source,
+            user_destination,
+            pool_source,
+            pool_destination,
+            token_program,
+            user_authority,
+            pool_authority,
+            tick_list_account,
+            amount,
+            0,
+        )?;
+        user_source = user_destination;
+    }
+
+    if amount < args.minimum_amount_out {
+        return Err(PoolError::SlippageExceeded.into());
+    }
+
+    msg!("Route output: {}", amount);
+    Ok(())
+}
+
+// Swaps `amount_in` through one pool and returns the output paid to `user_destination`
+#[allow(clippy::too_many_arguments)]
+fn swap_through_pool<'a>(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo<'a>,
+    user_source: &AccountInfo<'a>,
+    user_destination: &AccountInfo<'a>,
+    pool_source: &AccountInfo<'a>,
+    pool_destination: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    user_authority: &AccountInfo<'a>,
+    pool_authority: &AccountInfo<'a>,
+    tick_list_account: Option<&AccountInfo<'a>>,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<u64, ProgramError> {
+    let mut pool_state = PoolState::try_from_slice(&pool_account.data.borrow())?;
+    // Either direction, but the two sides must be the pool's own vaults
+    if *pool_source.key == pool_state.token_a_account {
+        check_pool_accounts(&pool_state, pool_source, pool_destination)?;
+    } else {
+        check_pool_accounts(&pool_state, pool_destination, pool_source)?;
+    }
+    let authority_bump = check_pool_authority(program_id, pool_account, pool_authority)?;
+
+    // Calculate amount out using constant product formula
+    let a_to_b = *pool_source.key == pool_state.token_a_account;
+    let (source_amount, destination_amount) = if a_to_b {
+        (pool_state.token_a_amount, pool_state.token_b_amount)
+    } else {
+        (pool_state.token_b_amount, pool_state.token_a_amount)
+    };
+
+    // Advance the price accumulators with the reserves before this swap
+    let (reserve_a, reserve_b) = (pool_state.token_a_amount, pool_state.token_b_amount);
+    update_price_accumulators(&mut pool_state, reserve_a, reserve_b, Clock::get()?.unix_timestamp);
+
+    let (amount_in, amount_out, protocol_fee) = match pool_state.curve_type {
+        CurveType::ConstantProduct => (
+            amount_in,
+            calculate_output_amount(
+                amount_in,
+                source_amount,
+                destination_amount,
+                pool_state.fee_rate,
+            )?,
+            protocol_fee_on(&pool_state, amount_in),
+        ),
+        CurveType::StableSwap => (
+            amount_in,
+            calculate_stable_output_amount(
+                amount_in,
+                source_amount,
+                destination_amount,
+                pool_state.fee_rate,
+                pool_state.amp,
+            )?,
+            protocol_fee_on(&pool_state, amount_in),
+        ),
+        // Concentrated swaps may consume less than amount_in if the price reaches its bound
+        CurveType::Concentrated => {
+            let tick_list_account = tick_list_account.ok_or(PoolError::InvalidTickList)?;
+            let mut tick_list = load_tick_list(program_id, pool_account, tick_list_account)?;
+            let amounts = swap_concentrated(&mut pool_state, &mut tick_list, a_to_b, amount_in)?;
+            tick_list.serialize(&mut &mut tick_list_account.data.borrow_mut()[..])?;
+            amounts
+        }
+    };
+    if amount_out < minimum_amount_out {
+        return Err(PoolError::SlippageExceeded.into());
+    }
+
     // Transfer tokens
-    spl_token::instruction::transfer(
-        token_program.key,
-        user_source.key,
-        pool_source.key,
-        &pool_account.key,
-        &[],
-        amount_in,
+    transfer_from_user(token_program, user_source, pool_source, user_authority, amount_in)?;
+    transfer_from_pool(
+        token_program,
+        pool_account,
+        pool_destination,
+        user_destination,
+        pool_authority,
+        authority_bump,
+        amount_out,
     )?;
 
-    spl_token::instruction::transfer(
-        token_program.key,
-        pool_destination.key,
-        user_destination.key,
-        &pool_account.key,
-        &[],
-        amount_out,
+    // The protocol's cut of the fee stays in the vault but leaves the reserves
+    let new_source = amount_in
+        .checked_sub(protocol_fee)
+        .and_then(|retained| source_amount.checked_add(retained))
+        .ok_or(PoolError::CalculationFailure)?;
+    let new_destination =
+        destination_amount.checked_sub(amount_out).ok_or(PoolError::CalculationFailure)?;
+    if a_to_b {
+        pool_state.token_a_amount = new_source;
+        pool_state.token_b_amount = new_destination;
+        pool_state.protocol_fees_a = pool_state.protocol_fees_a
+            .checked_add(protocol_fee)
+            .ok_or(PoolError::CalculationFailure)?;
+    } else {
+        pool_state.token_a_amount = new_destination;
+        pool_state.token_b_amount = new_source;
+        pool_state.protocol_fees_b = pool_state.protocol_fees_b
+            .checked_add(protocol_fee)
+            .ok_or(PoolError::CalculationFailure)?;
+    }
+    pool_state.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
+
+    Ok(amount_out)
+}
+
+// Permissionless: adopts the vault balances as reserves, e.g. after direct transfers into a vault.
+// Constant-product and stable pools only
+fn process_sync(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let pool_token_a = next_account_info(account_info_iter)?;
+    let pool_token_b = next_account_info(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut pool_state = PoolState::try_from_slice(&pool_account.data.borrow())?;
+    check_pool_accounts(&pool_state, pool_token_a, pool_token_b)?;
+    // Concentrated vaults also hold tokens owed to positions, so their balances are not reserves
+    if pool_state.curve_type == CurveType::Concentrated {
+        return Err(PoolError::UnsupportedCurve.into());
+    }
+
+    // Time before the sync was spent at the previously tracked reserves
+    let (reserve_a, reserve_b) = (pool_state.token_a_amount, pool_state.token_b_amount);
+    update_price_accumulators(&mut pool_state, reserve_a, reserve_b, Clock::get()?.unix_timestamp);
+
+    // Uncollected protocol fees sit in the vaults without being reserves
+    pool_state.token_a_amount = TokenAccount::unpack(&pool_token_a.data.borrow())?
+        .amount
+        .saturating_sub(pool_state.protocol_fees_a);
+    pool_state.token_b_amount = TokenAccount::unpack(&pool_token_b.data.borrow())?
+        .amount
+        .saturating_sub(pool_state.protocol_fees_b);
+    pool_state.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
+
+    msg!("Synced reserves: {} / {}", pool_state.token_a_amount, pool_state.token_b_amount);
+    Ok(())
+}
+
+// Creates the caller's position for a range on first use, then deposits the tokens
+// backing `liquidity` at the current price
+fn process_increase_position(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let position_account = next_account_info(account_info_iter)?;
+    let tick_list_account = next_account_info(account_info_iter)?;
+    let user_token_a = next_account_info(account_info_iter)?;
+    let user_token_b = next_account_info(account_info_iter)?;
+    let pool_token_a = next_account_info(account_info_iter)?;
+    let pool_token_b = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    let args = PositionArgs::try_from_slice(instruction_data)?;
+    if !user_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut pool_state = PoolState::try_from_slice(&pool_account.data.borrow())?;
+    check_pool_accounts(&pool_state, pool_token_a, pool_token_b)?;
+    check_tick_range(&pool_state, args.tick_lower, args.tick_upper)?;
+    if args.liquidity == 0 {
+        return Err(PoolError::InsufficientLiquidity.into());
+    }
+
+    // The pool's first position creates its tick list
+    let (tick_list_key, tick_list_bump) =
+        Pubkey::find_program_address(&[TICKS_SEED, pool_account.key.as_ref()], program_id);
+    if *tick_list_account.key != tick_list_key {
+        return Err(PoolError::InvalidTickList.into());
+    }
+    if tick_list_account.data_is_empty() {
+        create_pda_account(
+            program_id,
+            user_authority,
+            tick_list_account,
+            system_program,
+            TickList::LEN,
+            &[TICKS_SEED, pool_account.key.as_ref(), &[tick_list_bump]],
+        )?;
+        TickList { pool: *pool_account.key, ticks: Vec::new() }
+            .serialize(&mut &mut tick_list_account.data.borrow_mut()[..])?;
+    }
+    let mut tick_list = load_tick_list(program_id, pool_account, tick_list_account)?;
+
+    let lower_bytes = args.tick_lower.to_le_bytes();
+    let upper_bytes = args.tick_upper.to_le_bytes();
+    let (position_key, position_bump) = Pubkey::find_program_address(
+        &[
+            POSITION_SEED,
+            pool_account.key.as_ref(),
+            user_authority.key.as_ref(),
+            &lower_bytes,
+            &upper_bytes,
+        ],
+        program_id,
+    );
+    if *position_account.key != position_key {
+        return Err(PoolError::InvalidPosition.into());
+    }
+    let mut position = if position_account.data_is_empty() {
+        create_pda_account(
+            program_id,
+            user_authority,
+            position_account,
+            system_program,
+            Position::LEN,
+            &[
+                POSITION_SEED,
+                pool_account.key.as_ref(),
+                user_authority.key.as_ref(),
+                &lower_bytes,
+                &upper_bytes,
+                &[position_bump],
+            ],
+        )?;
+        Position {
+            pool: *pool_account.key,
+            owner: *user_authority.key,
+            tick_lower: args.tick_lower,
+            tick_upper: args.tick_upper,
+            liquidity: 0,
+            fee_growth_inside_a_last: 0,
+            fee_growth_inside_b_last: 0,
+            tokens_owed_a: 0,
+            tokens_owed_b: 0,
+        }
+    } else {
+        Position::try_from_slice(&position_account.data.borrow())?
+    };
+
+    let (reserve_a, reserve_b) = (pool_state.token_a_amount, pool_state.token_b_amount);
+    update_price_accumulators(&mut pool_state, reserve_a, reserve_b, Clock::get()?.unix_timestamp);
+
+    let liquidity_delta =
+        i128::try_from(args.liquidity).map_err(|_| PoolError::CalculationFailure)?;
+    modify_position(&mut pool_state, &mut tick_list, &mut position, liquidity_delta)?;
+    let (amount_a, amount_b) =
+        position_amounts(&pool_state, args.tick_lower, args.tick_upper, args.liquidity, true)?;
+    if amount_a > args.amount_a_limit || amount_b > args.amount_b_limit {
+        return Err(PoolError::SlippageExceeded.into());
+    }
+
+    transfer_from_user(token_program, user_token_a, pool_token_a, user_authority, amount_a)?;
+    transfer_from_user(token_program, user_token_b, pool_token_b, user_authority, amount_b)?;
+
+    pool_state.token_a_amount =
+        reserve_a.checked_add(amount_a).ok_or(PoolError::CalculationFailure)?;
+    pool_state.token_b_amount =
+        reserve_b.checked_add(amount_b).ok_or(PoolError::CalculationFailure)?;
+    pool_state.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
+    tick_list.serialize(&mut &mut tick_list_account.data.borrow_mut()[..])?;
+    position.serialize(&mut &mut position_account.data.borrow_mut()[..])?;
+
+    Ok(())
+}
+
+// Withdraws `liquidity` from a position together with every fee it has earned; a zero
+// amount just collects fees. Emptied positions are closed to the owner.
+fn process_decrease_position(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let position_account = next_account_info(account_info_iter)?;
+    let tick_list_account = next_account_info(account_info_iter)?;
+    let user_token_a = next_account_info(account_info_iter)?;
+    let user_token_b = next_account_info(account_info_iter)?;
+    let pool_token_a = next_account_info(account_info_iter)?;
+    let pool_token_b = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let user_authority = next_account_info(account_info_iter)?;
+    let pool_authority = next_account_info(account_info_iter)?;
+
+    let args = PositionArgs::try_from_slice(instruction_data)?;
+    if !user_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut pool_state = PoolState::try_from_slice(&pool_account.data.borrow())?;
+    check_pool_accounts(&pool_state, pool_token_a, pool_token_b)?;
+    check_tick_range(&pool_state, args.tick_lower, args.tick_upper)?;
+    let authority_bump = check_pool_authority(program_id, pool_account, pool_authority)?;
+    let mut tick_list = load_tick_list(program_id, pool_account, tick_list_account)?;
+
+    // The address binds the position to its owner, pool and range
+    let (position_key, _) = Pubkey::find_program_address(
+        &[
+            POSITION_SEED,
+            pool_account.key.as_ref(),
+            user_authority.key.as_ref(),
+            &args.tick_lower.to_le_bytes(),
+            &args.tick_upper.to_le_bytes(),
+        ],
+        program_id,
+    );
+    if *position_account.key != position_key || position_account.owner != program_id {
+        return Err(PoolError::InvalidPosition.into());
+    }
+    let mut position = Position::try_from_slice(&position_account.data.borrow())?;
+    if args.liquidity > position.liquidity {
+        return Err(PoolError::InsufficientLiquidity.into());
+    }
+
+    let (reserve_a, reserve_b) = (pool_state.token_a_amount, pool_state.token_b_amount);
+    update_price_accumulators(&mut pool_state, reserve_a, reserve_b, Clock::get()?.unix_timestamp);
+
+    let (amount_a, amount_b) =
+        position_amounts(&pool_state, args.tick_lower, args.tick_upper, args.liquidity, false)?;
+    if amount_a < args.amount_a_limit || amount_b < args.amount_b_limit {
+        return Err(PoolError::SlippageExceeded.into());
+    }
+    modify_position(&mut pool_state, &mut tick_list, &mut position, -(args.liquidity as i128))?;
+
+    let payout_a = amount_a
+        .checked_add(position.tokens_owed_a)
+        .ok_or(PoolError::CalculationFailure)?;
+    let payout_b = amount_b
+        .checked_add(position.tokens_owed_b)
+        .ok_or(PoolError::CalculationFailure)?;
+    position.tokens_owed_a = 0;
+    position.tokens_owed_b = 0;
+
+    transfer_from_pool(
+        token_program,
+        pool_account,
+        pool_token_a,
+        user_token_a,
+        pool_authority,
+        authority_bump,
+        payout_a,
     )?;
+    transfer_from_pool(
+        token_program,
+        pool_account,
+        pool_token_b,
+        user_token_b,
+        pool_authority,
+        authority_bump,
+        payout_b,
+    )?;
+
+    pool_state.token_a_amount =
+        reserve_a.checked_sub(payout_a).ok_or(PoolError::InsufficientLiquidity)?;
+    pool_state.token_b_amount =
+        reserve_b.checked_sub(payout_b).ok_or(PoolError::InsufficientLiquidity)?;
+    pool_state.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
+    tick_list.serialize(&mut &mut tick_list_account.data.borrow_mut()[..])?;
+
+    if position.liquidity == 0 {
+        let dest_starting_lamports = user_authority.lamports();
+        **user_authority.lamports.borrow_mut() = dest_starting_lamports
+            .checked_add(position_account.lamports())
+            .ok_or(PoolError::CalculationFailure)?;
+        **position_account.lamports.borrow_mut() = 0;
+        position_account.data.borrow_mut().fill(0);
+    } else {
+        position.serialize(&mut &mut position_account.data.borrow_mut()[..])?;
+    }
 
     Ok(())
 }
 
+// Pays the accrued protocol fees of both tokens to the fee authority's accounts
+fn process_collect_protocol_fees(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let fee_authority = next_account_info(account_info_iter)?;
+    let pool_token_a = next_account_info(account_info_iter)?;
+    let pool_token_b = next_account_info(account_info_iter)?;
+    let destination_a = next_account_info(account_info_iter)?;
+    let destination_b = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pool_authority = next_account_info(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut pool_state = PoolState::try_from_slice(&pool_account.data.borrow())?;
+    check_pool_accounts(&pool_state, pool_token_a, pool_token_b)?;
+    if !fee_authority.is_signer || *fee_authority.key != pool_state.fee_authority {
+        return Err(PoolError::InvalidAuthority.into());
+    }
+    let authority_bump = check_pool_authority(program_id, pool_account, pool_authority)?;
+
+    let (amount_a, amount_b) = (pool_state.protocol_fees_a, pool_state.protocol_fees_b);
+    transfer_from_pool(
+        token_program,
+        pool_account,
+        pool_token_a,
+        destination_a,
+        pool_authority,
+        authority_bump,
+        amount_a,
+    )?;
+    transfer_from_pool(
+        token_program,
+        pool_account,
+        pool_token_b,
+        destination_b,
+        pool_authority,
+        authority_bump,
+        amount_b,
+    )?;
+
+    pool_state.protocol_fees_a = 0;
+    pool_state.protocol_fees_b = 0;
+    pool_state.serialize(&mut &mut pool_account.data.borrow_mut()[..])?;
+
+    msg!("Collected protocol fees: {} / {}", amount_a, amount_b);
+    Ok(())
+}
+
+// Little-endian u64 at `offset`, or zero when the caller left the field off
+fn read_optional_u64(instruction_data: &[u8], offset: usize) -> u64 {
+    instruction_data
+        .get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .unwrap_or(0)
+}
+
+// Protocol share of the swap fee charged on `amount_in`
+fn protocol_fee_on(pool_state: &PoolState, amount_in: u64) -> u64 {
+    let fee = amount_in as u128 * pool_state.fee_rate as u128 / 10000;
+    (fee * pool_state.protocol_fee_bps as u128 / 10000) as u64
+}
+
 fn calculate_output_amount(
     amount_in: u64,
     reserve_in: u64,
     reserve_out: u64,
     fee_rate: u64,
 ) -> Result<u64, ProgramError> {
-    let amount_in_with_fee = amount_in * (10000 - fee_rate);
-    let numerator = amount_in_with_fee * reserve_out;
-    let denominator = (reserve_in * 10000) + amount_in_with_fee;
-    
-    Ok((numerator / denominator) as u64)
+    // Widened to u128 so realistic amounts fit; anything beyond that fails instead of panicking
+    let amount_in_with_fee = amount_in as u128 * (10000 - fee_rate) as u128;
+    let numerator = amount_in_with_fee
+        .checked_mul(reserve_out as u128)
+        .ok_or(PoolError::CalculationFailure)?;
+    let denominator = (reserve_in as u128 * 10000)
+        .checked_add(amount_in_with_fee)
+        .filter(|denominator| *denominator > 0)
+        .ok_or(PoolError::InsufficientLiquidity)?;
+    u64::try_from(numerator / denominator).map_err(|_| PoolError::CalculationFailure.into())
+}
+
+fn calculate_stable_output_amount(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_rate: u64,
+    amp: u64,
+) -> Result<u64, ProgramError> {
+    let amount_in_with_fee = amount_in as u128 * (10000 - fee_rate) as u128 / 10000;
+    let d = compute_stable_d(amp, reserve_in as u128, reserve_out as u128)
+        .ok_or(PoolError::CalculationFailure)?;
+    let new_reserve_out = compute_stable_y(amp, reserve_in as u128 + amount_in_with_fee, d)
+        .ok_or(PoolError::CalculationFailure)?;
+
+    // Round against the trader
+    Ok((reserve_out as u128)
+        .saturating_sub(new_reserve_out)
+        .saturating_sub(1) as u64)
+}
+
+// Solves the two-coin invariant 4A(x + y) + D = 4AD + D^3 / (4xy) for D by Newton's method
+fn compute_stable_d(amp: u64, x: u128, y: u128) -> Option<u128> {
+    if x == 0 || y == 0 {
+        return None;
+    }
+    let ann = amp as u128 * 4;
+    let sum = x + y;
+    // Products of three balances overflow u128 for lopsided pools, hence mul_div; dividing
+    // by the smaller balance first keeps the truncation small enough to converge
+    let (small, large) = (x.min(y), x.max(y));
+    let mut d = sum;
+    for _ in 0..STABLE_ITERATIONS {
+        let d_p = mul_div(d, d, small * 2, false).ok()?;
+        let d_p = mul_div(d_p, d, large * 2, false).ok()?;
+        let previous = d;
+        d = mul_div(
+            ann.checked_mul(sum)?.checked_add(d_p.checked_mul(2)?)?,
+            d,
+            (ann - 1).checked_mul(d)?.checked_add(d_p.checked_mul(3)?)?,
+            false,
+        )
+        .ok()?;
+        if d.abs_diff(previous) <= 1 {
+            return Some(d);
+        }
+    }
+    None
+}
+
+// Balance of the other coin that keeps the invariant at `d` once this side holds `x`
+fn compute_stable_y(amp: u64, x: u128, d: u128) -> Option<u128> {
+    let ann = amp as u128 * 4;
+    let c = mul_div(d, d, ann * 2, false).ok()?;
+    let c = mul_div(c, d, x * 2, false).ok()?;
+    let b = x + d / ann;
+    let mut y = d;
+    for _ in 0..STABLE_ITERATIONS {
+        let previous = y;
+        y = y.checked_mul(y)?.checked_add(c)? / (y * 2 + b).checked_sub(d)?;
+        if y.abs_diff(previous) <= 1 {
+            return Some(y);
+        }
+    }
+    None
+}
+
+// Exact-input swap across the initialized ticks. The LP part of each step's fee accrues
+// to the liquidity active during it. Returns (input consumed, output, protocol fee), where
+// the input falls short of `amount_in` only if the price runs into its bound.
+fn swap_concentrated(
+    pool_state: &mut PoolState,
+    tick_list: &mut TickList,
+    a_to_b: bool,
+    amount_in: u64,
+) -> Result<(u64, u64, u64), ProgramError> {
+    let fee_rate = pool_state.fee_rate as u128;
+    let mut remaining = amount_in as u128;
+    let mut amount_out = 0u128;
+    let mut protocol_fee = 0u128;
+
+    while remaining > 0 {
+        // Selling A lowers the price toward the next tick at or below it; selling B raises it
+        let next = if a_to_b {
+            tick_list.ticks.iter().rposition(|t| t.tick <= pool_state.current_tick)
+        } else {
+            tick_list.ticks.iter().position(|t| t.tick > pool_state.current_tick)
+        };
+        let target_tick = match next {
+            Some(index) => tick_list.ticks[index].tick,
+            None if a_to_b => MIN_TICK,
+            None => MAX_TICK,
+        };
+        let target = sqrt_price_at_tick(target_tick);
+        let (sqrt_price, liquidity) = (pool_state.sqrt_price_x64, pool_state.liquidity);
+
+        let available = remaining * (10000 - fee_rate) / 10000;
+        let (step_in, step_out, new_sqrt_price) = if liquidity == 0 {
+            (0, 0, target)
+        } else if a_to_b {
+            let to_target = amount_a_delta(target, sqrt_price, liquidity, true)?;
+            if available >= to_target {
+                (to_target, amount_b_delta(target, sqrt_price, liquidity, false)?, target)
+            } else {
+                let added = mul_div(available, sqrt_price, Q64, false)?;
+                let denominator =
+                    liquidity.checked_add(added).ok_or(PoolError::CalculationFailure)?;
+                let price = mul_div(liquidity, sqrt_price, denominator, true)?;
+                (available, amount_b_delta(price, sqrt_price, liquidity, false)?, price)
+            }
+        } else {
+            let to_target = amount_b_delta(sqrt_price, target, liquidity, true)?;
+            if available >= to_target {
+                (to_target, amount_a_delta(sqrt_price, target, liquidity, false)?, target)
+            } else {
+                let price = sqrt_price
+                    .checked_add(mul_div(available, Q64, liquidity, false)?)
+                    .ok_or(PoolError::CalculationFailure)?;
+                (available, amount_a_delta(sqrt_price, price, liquidity, false)?, price)
+            }
+        };
+
+        // A step that stops short of its tick consumes the whole remainder, dust included
+        let reached = new_sqrt_price == target;
+        let fee = if reached {
+            mul_div(step_in, fee_rate, 10000 - fee_rate, true)?.min(remaining - step_in)
+        } else {
+            remaining - step_in
+        };
+        remaining -= step_in + fee;
+        amount_out += step_out;
+        if liquidity > 0 {
+            let step_protocol_fee = fee * pool_state.protocol_fee_bps as u128 / 10000;
+            protocol_fee += step_protocol_fee;
+            let growth = mul_div(fee - step_protocol_fee, Q64, liquidity, false)?;
+            if a_to_b {
+                pool_state.fee_growth_global_a =
+                    pool_state.fee_growth_global_a.wrapping_add(growth);
+            } else {
+                pool_state.fee_growth_global_b =
+                    pool_state.fee_growth_global_b.wrapping_add(growth);
+            }
+        }
+        pool_state.sqrt_price_x64 = new_sqrt_price;
+
+        if !reached {
+            pool_state.current_tick = tick_at_sqrt_price(new_sqrt_price);
+            break;
+        }
+        let index = match next {
+            Some(index) => index,
+            None => {
+                pool_state.current_tick = target_tick;
+                break;
+            }
+        };
+        let tick = &mut tick_list.ticks[index];
+        tick.fee_growth_outside_a =
+            pool_state.fee_growth_global_a.wrapping_sub(tick.fee_growth_outside_a);
+        tick.fee_growth_outside_b =
+            pool_state.fee_growth_global_b.wrapping_sub(tick.fee_growth_outside_b);
+        let liquidity_delta = if a_to_b { -tick.liquidity_net } else { tick.liquidity_net };
+        pool_state.liquidity = apply_liquidity_delta(pool_state.liquidity, liquidity_delta)?;
+        pool_state.current_tick = if a_to_b { tick.tick - 1 } else { tick.tick };
+    }
+
+    let consumed = amount_in - remaining as u64;
+    let amount_out = u64::try_from(amount_out).map_err(|_| PoolError::CalculationFailure)?;
+    Ok((consumed, amount_out, protocol_fee as u64))
+}
+
+// Settles the fees a position earned since it was last touched, then applies the
+// liquidity change to its ticks and, when its range holds the price, to the pool
+fn modify_position(
+    pool_state: &mut PoolState,
+    tick_list: &mut TickList,
+    position: &mut Position,
+    liquidity_delta: i128,
+) -> ProgramResult {
+    for tick in [position.tick_lower, position.tick_upper] {
+        if let Err(index) = tick_list.ticks.binary_search_by_key(&tick, |t| t.tick) {
+            if tick_list.ticks.len() >= MAX_INITIALIZED_TICKS {
+                return Err(PoolError::TickListFull.into());
+            }
+            // By convention all growth before a tick exists happened below it
+            let below = tick <= pool_state.current_tick;
+            tick_list.ticks.insert(index, TickState {
+                tick,
+                liquidity_net: 0,
+                liquidity_gross: 0,
+                fee_growth_outside_a: if below { pool_state.fee_growth_global_a } else { 0 },
+                fee_growth_outside_b: if below { pool_state.fee_growth_global_b } else { 0 },
+            });
+        }
+    }
+    let find = |tick: i32| {
+        tick_list.ticks.binary_search_by_key(&tick, |t| t.tick)
+            .map_err(|_| PoolError::CalculationFailure)
+    };
+    let (lower, upper) = (find(position.tick_lower)?, find(position.tick_upper)?);
+
+    let (inside_a, inside_b) =
+        fee_growth_inside(pool_state, &tick_list.ticks[lower], &tick_list.ticks[upper]);
+    let earned = |inside: u128, last: u128| {
+        mul_div(position.liquidity, inside.wrapping_sub(last), Q64, false)
+            .and_then(|amount| {
+                u64::try_from(amount).map_err(|_| PoolError::CalculationFailure.into())
+            })
+    };
+    let earned_a = earned(inside_a, position.fee_growth_inside_a_last)?;
+    let earned_b = earned(inside_b, position.fee_growth_inside_b_last)?;
+    position.tokens_owed_a = position.tokens_owed_a.saturating_add(earned_a);
+    position.tokens_owed_b = position.tokens_owed_b.saturating_add(earned_b);
+    position.fee_growth_inside_a_last = inside_a;
+    position.fee_growth_inside_b_last = inside_b;
+    position.liquidity = apply_liquidity_delta(position.liquidity, liquidity_delta)?;
+
+    let lower_tick = &mut tick_list.ticks[lower];
+    lower_tick.liquidity_gross =
+        apply_liquidity_delta(lower_tick.liquidity_gross, liquidity_delta)?;
+    lower_tick.liquidity_net = lower_tick.liquidity_net
+        .checked_add(liquidity_delta)
+        .ok_or(PoolError::CalculationFailure)?;
+    let upper_tick = &mut tick_list.ticks[upper];
+    upper_tick.liquidity_gross =
+        apply_liquidity_delta(upper_tick.liquidity_gross, liquidity_delta)?;
+    upper_tick.liquidity_net = upper_tick.liquidity_net
+        .checked_sub(liquidity_delta)
+        .ok_or(PoolError::CalculationFailure)?;
+
+    if (position.tick_lower..position.tick_upper).contains(&pool_state.current_tick) {
+        pool_state.liquidity = apply_liquidity_delta(pool_state.liquidity, liquidity_delta)?;
+    }
+
+    // Ticks no position references any more stop being crossed
+    tick_list.ticks.retain(|t| t.liquidity_gross > 0);
+    Ok(())
+}
+
+// Fee growth per unit of liquidity accrued while the price was between the two ticks
+fn fee_growth_inside(pool_state: &PoolState, lower: &TickState, upper: &TickState) -> (u128, u128) {
+    let current = pool_state.current_tick;
+    let inside = |global: u128, lower_outside: u128, upper_outside: u128| {
+        let below = if current >= lower.tick {
+            lower_outside
+        } else {
+            global.wrapping_sub(lower_outside)
+        };
+        let above = if current < upper.tick {
+            upper_outside
+        } else {
+            global.wrapping_sub(upper_outside)
+        };
+        global.wrapping_sub(below).wrapping_sub(above)
+    };
+    (
+        inside(
+            pool_state.fee_growth_global_a,
+            lower.fee_growth_outside_a,
+            upper.fee_growth_outside_a,
+        ),
+        inside(
+            pool_state.fee_growth_global_b,
+            lower.fee_growth_outside_b,
+            upper.fee_growth_outside_b,
+        ),
+    )
+}
+
+// Token amounts backing `liquidity` over a range at the pool's current price
+fn position_amounts(
+    pool_state: &PoolState,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<(u64, u64), ProgramError> {
+    let sqrt_lower = sqrt_price_at_tick(tick_lower);
+    let sqrt_upper = sqrt_price_at_tick(tick_upper);
+    let sqrt_price = pool_state.sqrt_price_x64;
+    let (amount_a, amount_b) = if pool_state.current_tick < tick_lower {
+        (amount_a_delta(sqrt_lower, sqrt_upper, liquidity, round_up)?, 0)
+    } else if pool_state.current_tick < tick_upper {
+        (
+            amount_a_delta(sqrt_price, sqrt_upper, liquidity, round_up)?,
+            amount_b_delta(sqrt_lower, sqrt_price, liquidity, round_up)?,
+        )
+    } else {
+        (0, amount_b_delta(sqrt_lower, sqrt_upper, liquidity, round_up)?)
+    };
+    let to_u64 = |amount: u128| u64::try_from(amount).map_err(|_| PoolError::CalculationFailure);
+    Ok((to_u64(amount_a)?, to_u64(amount_b)?))
+}
+
+// Token A between two sqrt prices: L * (upper - lower) / (upper * lower)
+fn amount_a_delta(
+    lower: u128,
+    upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<u128, ProgramError> {
+    let scaled = mul_div(liquidity, upper.saturating_sub(lower), upper, round_up)?;
+    mul_div(scaled, Q64, lower, round_up)
+}
+
+// Token B between two sqrt prices: L * (upper - lower)
+fn amount_b_delta(
+    lower: u128,
+    upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<u128, ProgramError> {
+    mul_div(liquidity, upper.saturating_sub(lower), Q64, round_up)
+}
+
+fn apply_liquidity_delta(liquidity: u128, delta: i128) -> Result<u128, ProgramError> {
+    let result = if delta >= 0 {
+        liquidity.checked_add(delta as u128)
+    } else {
+        liquidity.checked_sub(delta.unsigned_abs())
+    };
+    result.ok_or_else(|| PoolError::InsufficientLiquidity.into())
+}
+
+// sqrt(1.0001^tick) in Q64.64. Multiplies the Q128 factors 1 / sqrt(1.0001)^(2^bit) of the
+// bits set in |tick|, then inverts for positive ticks.
+fn sqrt_price_at_tick(tick: i32) -> u128 {
+    let abs_tick = tick.unsigned_abs();
+    let ratio = SQRT_RATIO_FACTORS
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| abs_tick & (1 << bit) != 0)
+        .map(|(_, factor)| *factor)
+        .reduce(|ratio, factor| full_mul(ratio, factor).0);
+    match ratio {
+        None => Q64,
+        Some(ratio) if tick > 0 => {
+            // 2^192 / ratio; the ratio never drops below 2^95 inside the tick bounds
+            mul_div(1 << 96, 1 << 96, ratio, false).unwrap_or(u128::MAX)
+        }
+        Some(ratio) => ratio >> 64,
+    }
+}
+
+// Greatest tick whose sqrt price does not exceed `sqrt_price_x64`. A fixed-point log2
+// estimates the tick to within one, and sqrt_price_at_tick settles it exactly.
+fn tick_at_sqrt_price(sqrt_price_x64: u128) -> i32 {
+    if sqrt_price_x64 == 0 {
+        return MIN_TICK;
+    }
+    // log2(sqrt_price_x64 / 2^64) in Q64.64: the integer part from the most significant
+    // bit, then LOG2_FRACTION_BITS fractional bits by repeated squaring of the mantissa
+    let msb = 127 - sqrt_price_x64.leading_zeros() as i32;
+    let mut log2_x64 = ((msb - 64) as i128) << 64;
+    let mut mantissa = if msb >= 63 {
+        sqrt_price_x64 >> (msb - 63)
+    } else {
+        sqrt_price_x64 << (63 - msb)
+    };
+    for bit in 1..=LOG2_FRACTION_BITS {
+        let square = mantissa * mantissa;
+        if square >> 127 == 1 {
+            log2_x64 += 1 << (64 - bit);
+            mantissa = square >> 64;
+        } else {
+            mantissa = square >> 63;
+        }
+    }
+
+    let estimate = ((log2_x64 * TICKS_PER_LOG2_X32) >> 96) as i32;
+    let mut tick = estimate.clamp(MIN_TICK, MAX_TICK);
+    while tick > MIN_TICK && sqrt_price_at_tick(tick) > sqrt_price_x64 {
+        tick -= 1;
+    }
+    while tick < MAX_TICK && sqrt_price_at_tick(tick + 1) <= sqrt_price_x64 {
+        tick += 1;
+    }
+    tick
+}
+
+// Full 256-bit product of two u128s as (high, low) halves
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    let mask = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & mask);
+    let (b_hi, b_lo) = (b >> 64, b & mask);
+    let (low, cross_1, cross_2) = (a_lo * b_lo, a_lo * b_hi, a_hi * b_lo);
+    let mid = (low >> 64) + (cross_1 & mask) + (cross_2 & mask);
+    let hi = a_hi * b_hi + (cross_1 >> 64) + (cross_2 >> 64) + (mid >> 64);
+    let lo = (low & mask) | (mid << 64);
+    (hi, lo)
+}
+
+// a * b / denominator with a 256-bit intermediate product
+fn mul_div(a: u128, b: u128, denominator: u128, round_up: bool) -> Result<u128, ProgramError> {
+    if denominator == 0 {
+        return Err(PoolError::CalculationFailure.into());
+    }
+    let (hi, lo) = full_mul(a, b);
+    if hi >= denominator {
+        return Err(PoolError::CalculationFailure.into());
+    }
+
+    // Shift-subtract long division of the 256-bit product
+    let (quotient, remainder) = if hi == 0 {
+        (lo / denominator, lo % denominator)
+    } else {
+        let mut remainder = hi;
+        let mut quotient = 0u128;
+        for bit in (0..128).rev() {
+            let carry = remainder >> 127;
+            remainder = (remainder << 1) | ((lo >> bit) & 1);
+            quotient <<= 1;
+            if carry == 1 || remainder >= denominator {
+                remainder = remainder.wrapping_sub(denominator);
+                quotient |= 1;
+            }
+        }
+        (quotient, remainder)
+    };
+    if round_up && remainder > 0 {
+        quotient.checked_add(1).ok_or_else(|| PoolError::CalculationFailure.into())
+    } else {
+        Ok(quotient)
+    }
+}
+
+// Accumulates spot prices weighted by the time they were in effect, so
+// readers can derive a time-weighted average between two observations
+fn update_price_accumulators(
+    pool_state: &mut PoolState,
+    reserve_a: u64,
+    reserve_b: u64,
+    current_time: i64,
+) {
+    let elapsed = current_time.saturating_sub(pool_state.last_update_time);
+    let prices = if pool_state.curve_type == CurveType::Concentrated {
+        // A concentrated pool's spot price is its sqrt price squared, not its vault ratio
+        let sqrt_price = pool_state.sqrt_price_x64;
+        mul_div(sqrt_price, PRICE_SCALE, Q64, false)
+            .and_then(|scaled| mul_div(scaled, sqrt_price, Q64, false))
+            .ok()
+            .filter(|price_a| *price_a > 0)
+            .map(|price_a| (price_a, PRICE_SCALE * PRICE_SCALE / price_a))
+    } else if reserve_a > 0 && reserve_b > 0 {
+        Some((
+            reserve_b as u128 * PRICE_SCALE / reserve_a as u128,
+            reserve_a as u128 * PRICE_SCALE / reserve_b as u128,
+        ))
+    } else {
+        None
+    };
+    if let (true, Some((price_a, price_b))) = (elapsed > 0, prices) {
+        // Accumulators are expected to wrap; readers take differences
+        pool_state.price_a_cumulative = pool_state.price_a_cumulative
+            .wrapping_add(price_a.wrapping_mul(elapsed as u128));
+        pool_state.price_b_cumulative = pool_state.price_b_cumulative
+            .wrapping_add(price_b.wrapping_mul(elapsed as u128));
+    }
+    pool_state.last_update_time = current_time;
+}
+
+// Returns the bump of the pool authority PDA after checking the passed account is it
+fn check_pool_authority(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo,
+    pool_authority: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let (expected, bump) = Pubkey::find_program_address(
+        &[POOL_AUTHORITY_SEED, pool_account.key.as_ref()],
+        program_id,
+    );
+    if *pool_authority.key != expected {
+        return Err(PoolError::InvalidAuthority.into());
+    }
+    Ok(bump)
+}
+
+fn check_tick_range(pool_state: &PoolState, tick_lower: i32, tick_upper: i32) -> ProgramResult {
+    if pool_state.curve_type != CurveType::Concentrated {
+        return Err(PoolError::UnsupportedCurve.into());
+    }
+    // The bounds themselves are excluded so a range can always be crossed out of
+    let spacing = pool_state.tick_spacing as i32;
+    if tick_lower >= tick_upper
+        || tick_lower <= MIN_TICK
+        || tick_upper >= MAX_TICK
+        || tick_lower % spacing != 0
+        || tick_upper % spacing != 0
+    {
+        return Err(PoolError::InvalidTickRange.into());
+    }
+    Ok(())
+}
+
+fn load_tick_list(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo,
+    tick_list_account: &AccountInfo,
+) -> Result<TickList, ProgramError> {
+    let (expected, _) =
+        Pubkey::find_program_address(&[TICKS_SEED, pool_account.key.as_ref()], program_id);
+    if *tick_list_account.key != expected || tick_list_account.owner != program_id {
+        return Err(PoolError::InvalidTickList.into());
+    }
+    // The account is sized for a full list, so only its prefix is read
+    Ok(TickList::deserialize(&mut &tick_list_account.data.borrow()[..])?)
+}
+
+fn check_pool_accounts(
+    pool_state: &PoolState,
+    pool_token_a: &AccountInfo,
+    pool_token_b: &AccountInfo,
+) -> ProgramResult {
+    if !pool_state.is_initialized
+        || *pool_token_a.key != pool_state.token_a_account
+        || *pool_token_b.key != pool_state.token_b_account
+    {
+        return Err(PoolError::InvalidTokenAccount.into());
+    }
+    Ok(())
+}
+
+// The user authority signs directly, or through invoke_signed when the caller is a program
+fn transfer_from_user<'a>(
+    token_program: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    user_authority: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            source.key,
+            destination.key,
+            user_authority.key,
+            &[],
+            amount,
+        )?,
+        &[
+            source.clone(),
+            destination.clone(),
+            user_authority.clone(),
+            token_program.clone(),
+        ],
+    )
+}
+
+fn transfer_from_pool<'a>(
+    token_program: &AccountInfo<'a>,
+    pool_account: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    pool_authority: &AccountInfo<'a>,
+    authority_bump: u8,
+    amount: u64,
+) -> ProgramResult {
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            source.key,
+            destination.key,
+            pool_authority.key,
+            &[],
+            amount,
+        )?,
+        &[
+            source.clone(),
+            destination.clone(),
+            pool_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&[POOL_AUTHORITY_SEED, pool_account.key.as_ref(), &[authority_bump]]],
+    )
+}
+
+fn create_pda_account<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    space: usize,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            account.key,
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            program_id,
+        ),
+        &[payer.clone(), account.clone(), system_program.clone()],
+        &[seeds],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_amount_handles_nine_decimal_reserves() {
+        // 1M tokens in against 1B / 2B reserves, all at 9 decimals
+        let reserve_in = 1_000_000_000 * 1_000_000_000;
+        let reserve_out = 2_000_000_000 * 1_000_000_000;
+        let amount_in = 1_000_000 * 1_000_000_000;
+        let expected = (amount_in as u128 * 9970 * reserve_out as u128
+            / (reserve_in as u128 * 10000 + amount_in as u128 * 9970)) as u64;
+        assert_eq!(
+            calculate_output_amount(amount_in, reserve_in, reserve_out, 30).unwrap(),
+            expected,
+        );
+        assert!(calculate_output_amount(u64::MAX, u64::MAX, u64::MAX, 30).is_err());
+    }
+
+    #[test]
+    fn tick_math_round_trips_at_tick_boundaries() {
+        let ticks = [
+            MIN_TICK, MIN_TICK + 1, -100_000, -60, -1, 0, 1, 60, 100_000, MAX_TICK - 1, MAX_TICK,
+        ];
+        for tick in ticks {
+            let sqrt_price = sqrt_price_at_tick(tick);
+            assert_eq!(tick_at_sqrt_price(sqrt_price), tick);
+            if tick > MIN_TICK {
+                // One unit below a tick's price still belongs to the tick beneath it
+                assert_eq!(tick_at_sqrt_price(sqrt_price - 1), tick - 1);
+            }
+            if tick < MAX_TICK {
+                assert!(sqrt_price < sqrt_price_at_tick(tick + 1));
+            }
+        }
+        assert_eq!(sqrt_price_at_tick(0), Q64);
+    }
+
+    #[test]
+    fn tick_math_matches_the_real_sqrt_price() {
+        for tick in (MIN_TICK..=MAX_TICK).step_by(7919) {
+            let exact = 1.0001f64.powf(tick as f64 / 2.0) * Q64 as f64;
+            let computed = sqrt_price_at_tick(tick) as f64;
+            assert!((computed - exact).abs() / exact < 1e-9, "tick {}", tick);
+        }
+    }
+
+    fn concentrated_pool(current_tick: i32) -> PoolState {
+        PoolState {
+            is_initialized: true,
+            token_a_mint: Pubkey::new_unique(),
+            token_b_mint: Pubkey::new_unique(),
+            token_a_account: Pubkey::new_unique(),
+            token_b_account: Pubkey::new_unique(),
+            pool_mint: Pubkey::default(),
+            token_a_amount: 0,
+            token_b_amount: 0,
+            fee_rate: 30,
+            price_a_cumulative: 0,
+            price_b_cumulative: 0,
+            last_update_time: 0,
+            curve_type: CurveType::Concentrated,
+            amp: 0,
+            tick_spacing: 60,
+            sqrt_price_x64: sqrt_price_at_tick(current_tick),
+            current_tick,
+            liquidity: 0,
+            fee_growth_global_a: 0,
+            fee_growth_global_b: 0,
+            fee_authority: Pubkey::default(),
+            protocol_fee_bps: 0,
+            protocol_fees_a: 0,
+            protocol_fees_b: 0,
+        }
+    }
+
+    fn position(pool_state: &PoolState, tick_lower: i32, tick_upper: i32) -> Position {
+        Position {
+            pool: Pubkey::default(),
+            owner: Pubkey::new_unique(),
+            tick_lower,
+            tick_upper,
+            liquidity: 0,
+            fee_growth_inside_a_last: pool_state.fee_growth_global_a,
+            fee_growth_inside_b_last: pool_state.fee_growth_global_b,
+            tokens_owed_a: 0,
+            tokens_owed_b: 0,
+        }
+    }
+
+    #[test]
+    fn positions_follow_the_price_across_ticks() {
+        let liquidity = 1_000_000_000_000u128;
+        let mut pool_state = concentrated_pool(0);
+        let mut tick_list = TickList { pool: Pubkey::default(), ticks: Vec::new() };
+        let mut lower = position(&pool_state, -60, 60);
+        let mut upper = position(&pool_state, 60, 120);
+        modify_position(&mut pool_state, &mut tick_list, &mut lower, liquidity as i128).unwrap();
+        modify_position(&mut pool_state, &mut tick_list, &mut upper, liquidity as i128).unwrap();
+
+        // Only the range holding the price is active
+        assert_eq!(pool_state.liquidity, liquidity);
+        let (amount_a, amount_b) = position_amounts(&pool_state, 60, 120, liquidity, true).unwrap();
+        assert!(amount_a > 0);
+        assert_eq!(amount_b, 0);
+
+        // Buying A with B lifts the price through tick 60 into the upper range
+        let target = sqrt_price_at_tick(90);
+        let amount_b_in =
+            amount_b_delta(pool_state.sqrt_price_x64, target, liquidity, false).unwrap() as u64;
+        let (consumed, amount_out, _) =
+            swap_concentrated(&mut pool_state, &mut tick_list, false, amount_b_in).unwrap();
+        assert_eq!(consumed, amount_b_in);
+        assert!(amount_out > 0);
+        assert!((60..120).contains(&pool_state.current_tick));
+        assert_eq!(pool_state.liquidity, liquidity);
+
+        // Growth from before the crossing stays with the lower range; the upper one
+        // earned only what was paid after it became active
+        modify_position(&mut pool_state, &mut tick_list, &mut lower, 0).unwrap();
+        modify_position(&mut pool_state, &mut tick_list, &mut upper, 0).unwrap();
+        assert!(lower.tokens_owed_b > 0);
+        assert!(upper.tokens_owed_b > 0);
+        assert_eq!(lower.tokens_owed_a + upper.tokens_owed_a, 0);
+
+        // Decreasing the range the price left does not touch the active liquidity,
+        // decreasing the range holding it does
+        modify_position(&mut pool_state, &mut tick_list, &mut lower, -(liquidity as i128)).unwrap();
+        assert_eq!(pool_state.liquidity, liquidity);
+        let half = -(liquidity as i128 / 2);
+        modify_position(&mut pool_state, &mut tick_list, &mut upper, half).unwrap();
+        assert_eq!(pool_state.liquidity, liquidity / 2);
+        assert_eq!(tick_list.ticks.iter().map(|t| t.tick).collect::<Vec<_>>(), vec![60, 120]);
+
+        // Selling A back below 60 crosses out of the upper range and leaves nothing active
+        let target = sqrt_price_at_tick(30);
+        let amount_a_in =
+            amount_a_delta(target, pool_state.sqrt_price_x64, liquidity, true).unwrap() as u64;
+        swap_concentrated(&mut pool_state, &mut tick_list, true, amount_a_in).unwrap();
+        assert!(pool_state.current_tick < 60);
+        assert_eq!(pool_state.liquidity, 0);
+    }
+
+    #[test]
+    fn position_at_its_lower_tick_is_active() {
+        let liquidity = 1_000_000u128;
+        let mut pool_state = concentrated_pool(60);
+        let mut tick_list = TickList { pool: Pubkey::default(), ticks: Vec::new() };
+        let mut at_lower = position(&pool_state, 60, 120);
+        let mut at_upper = position(&pool_state, 0, 60);
+        modify_position(&mut pool_state, &mut tick_list, &mut at_lower, liquidity as i128).unwrap();
+        modify_position(&mut pool_state, &mut tick_list, &mut at_upper, liquidity as i128).unwrap();
+        assert_eq!(pool_state.liquidity, liquidity);
+
+        // A range starting at the price needs only token A, one ending there only token B
+        let (amount_a, amount_b) = position_amounts(&pool_state, 60, 120, liquidity, true).unwrap();
+        assert!(amount_a > 0);
+        assert_eq!(amount_b, 0);
+        let (amount_a, amount_b) = position_amounts(&pool_state, 0, 60, liquidity, true).unwrap();
+        assert_eq!(amount_a, 0);
+        assert!(amount_b > 0);
+    }
+
+    #[test]
+    fn stable_swap_preserves_the_invariant() {
+        let (reserve_in, reserve_out, amount_in) = (1_000_000_000u64, 1_200_000_000u64, 50_000_000);
+        for amp in [1, 10, 100, MAX_AMP] {
+            let d = compute_stable_d(amp, reserve_in as u128, reserve_out as u128).unwrap();
+            let amount_out =
+                calculate_stable_output_amount(amount_in, reserve_in, reserve_out, 0, amp).unwrap();
+            let d_after = compute_stable_d(
+                amp,
+                (reserve_in + amount_in) as u128,
+                (reserve_out - amount_out) as u128,
+            )
+            .unwrap();
+            // Rounding favours the pool, by no more than a couple of units
+            assert!(d_after >= d, "amp {}", amp);
+            assert!(d_after - d <= 4, "amp {}", amp);
+        }
+    }
+
+    #[test]
+    fn stable_swap_converges_for_imbalanced_pools() {
+        for amp in [1, 100, MAX_AMP] {
+            for (x, y) in [(1_000_000u128, 1_000_000_000_000_000), (u64::MAX as u128, 1_000)] {
+                let d = compute_stable_d(amp, x, y).unwrap();
+                // D is exact to a unit, which lopsided pools amplify in y
+                let y_solved = compute_stable_y(amp, x, d).unwrap();
+                let tolerance = 1 + y / 1_000_000_000;
+                assert!(y_solved.abs_diff(y) <= tolerance, "amp {} x {} y {}", amp, x, y);
+            }
+            let amount_out =
+                calculate_stable_output_amount(1_000_000, 1_000_000, 1_000_000_000_000, 30, amp)
+                    .unwrap();
+            assert!(amount_out > 0);
+        }
+    }
+
+    #[test]
+    fn stable_swap_with_amp_one_is_closest_to_constant_product() {
+        // The invariant only becomes constant product as amp tends to zero, so amp = 1 is
+        // the allowed setting nearest to it: on a 1:4 pool it prices B above one-for-one,
+        // toward the constant-product price of 4, and higher amps pull it back to parity
+        let (reserve_a, reserve_b, amount_in) = (1_000_000_000u64, 4_000_000_000u64, 100_000u64);
+        let constant_product = calculate_output_amount(amount_in, reserve_a, reserve_b, 0).unwrap();
+        let outputs: Vec<u64> = [1, 10, 100, MAX_AMP]
+            .iter()
+            .map(|amp| {
+                calculate_stable_output_amount(amount_in, reserve_a, reserve_b, 0, *amp).unwrap()
+            })
+            .collect();
+        assert!(outputs[0] < constant_product);
+        assert!(outputs[0] > amount_in * 3 / 2);
+        assert!(outputs.windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(outputs[3] > amount_in);
+
+        // On a balanced pool a large trade slips least at high amp and most at amp = 1,
+        // which still slips less than constant product
+        let (reserve, amount_in) = (1_000_000_000u64, 100_000_000u64);
+        let constant_product = calculate_output_amount(amount_in, reserve, reserve, 0).unwrap();
+        let low_amp = calculate_stable_output_amount(amount_in, reserve, reserve, 0, 1).unwrap();
+        let high_amp =
+            calculate_stable_output_amount(amount_in, reserve, reserve, 0, MAX_AMP).unwrap();
+        assert!(constant_product < low_amp);
+        assert!(low_amp < high_amp);
+        assert!(high_amp < amount_in);
+    }
 }
@@ -0,0 +1,630 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use liquidity_pool::{process_instruction, PoolState, RouteArgs, POOL_AUTHORITY_SEED};
+use solana_program_test::{processor, BanksClient, ProgramTest};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, sysvar,
+    transaction::Transaction,
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+// Serialized PoolState size; try_from_slice needs the account to match it exactly
+const POOL_STATE_LEN: usize = 1 + 32 * 5 + 8 * 3 + 16 * 2 + 8
+    + 1 + 8
+    + 8 + 16 + 4 + 16
+    + 16 * 2
+    + 32 + 8 + 8 * 2;
+
+const USER_BALANCE: u64 = 1_000_000;
+
+// Initialize's curve fields: curve type, amp or tick spacing, initial tick
+type Curve = (u8, u64, i64);
+const CONSTANT_PRODUCT: Curve = (0, 0, 0);
+const CONCENTRATED: Curve = (2, 60, 0);
+const FEE_RATE_BPS: u64 = 30;
+
+struct Pool {
+    pool: Pubkey,
+    authority: Pubkey,
+    vault_a: Pubkey,
+    vault_b: Pubkey,
+    lp_mint: Pubkey,
+}
+
+struct User {
+    wallet: Keypair,
+    token_a: Pubkey,
+    token_b: Pubkey,
+    lp: Pubkey,
+}
+
+struct Env {
+    banks: BanksClient,
+    payer: Keypair,
+    blockhash: Hash,
+    mint_a: Pubkey,
+    mint_b: Pubkey,
+    pool: Pool,
+    user: User,
+}
+
+impl Env {
+    async fn start() -> Self {
+        Env::start_with(CONSTANT_PRODUCT, None).await
+    }
+
+    // `protocol_fee` gives the pool a fee authority and its share of the swap fee
+    async fn start_with(curve: Curve, protocol_fee: Option<(Pubkey, u64)>) -> Self {
+        let program_test = ProgramTest::new(
+            "liquidity_pool",
+            liquidity_pool::id(),
+            processor!(process_instruction),
+        );
+        let (mut banks, payer, blockhash) = program_test.start().await;
+
+        let mint_a = create_mint(&mut banks, &payer, blockhash, &payer.pubkey()).await;
+        let mint_b = create_mint(&mut banks, &payer, blockhash, &payer.pubkey()).await;
+        let pool =
+            create_pool(&mut banks, &payer, blockhash, &mint_a, &mint_b, curve, protocol_fee).await;
+
+        let wallet = Keypair::new();
+        let owner = wallet.pubkey();
+        let token_a = create_token_account(&mut banks, &payer, blockhash, &mint_a, &owner).await;
+        let token_b = create_token_account(&mut banks, &payer, blockhash, &mint_b, &owner).await;
+        let lp = create_token_account(&mut banks, &payer, blockhash, &pool.lp_mint, &owner).await;
+        for (mint, account) in [(mint_a, token_a), (mint_b, token_b)] {
+            mint_to(&mut banks, &payer, blockhash, &mint, &account, USER_BALANCE).await;
+        }
+
+        Env {
+            banks,
+            payer,
+            blockhash,
+            mint_a,
+            mint_b,
+            pool,
+            user: User { wallet, token_a, token_b, lp },
+        }
+    }
+
+    async fn add_liquidity(&mut self, amount_a: u64, amount_b: u64) {
+        let mut data = vec![1];
+        data.extend_from_slice(&amount_a.to_le_bytes());
+        data.extend_from_slice(&amount_b.to_le_bytes());
+        let instruction = liquidity_instruction(
+            &self.pool,
+            &self.user.wallet.pubkey(),
+            self.user.token_a,
+            self.user.token_b,
+            self.user.lp,
+            data,
+        );
+        self.send(instruction).await.unwrap();
+    }
+
+    async fn remove_liquidity(&mut self, lp_amount: u64) {
+        let mut data = vec![2];
+        data.extend_from_slice(&lp_amount.to_le_bytes());
+        let instruction = liquidity_instruction(
+            &self.pool,
+            &self.user.wallet.pubkey(),
+            self.user.token_a,
+            self.user.token_b,
+            self.user.lp,
+            data,
+        );
+        self.send(instruction).await.unwrap();
+    }
+
+    async fn swap_a_to_b(
+        &mut self,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Result<(), solana_program_test::BanksClientError> {
+        let mut data = vec![3];
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        let instruction = Instruction::new_with_bytes(
+            liquidity_pool::id(),
+            &data,
+            vec![
+                AccountMeta::new(self.pool.pool, false),
+                AccountMeta::new(self.user.token_a, false),
+                AccountMeta::new(self.user.token_b, false),
+                AccountMeta::new(self.pool.vault_a, false),
+                AccountMeta::new(self.pool.vault_b, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(self.user.wallet.pubkey(), true),
+                AccountMeta::new_readonly(self.pool.authority, false),
+            ],
+        );
+        self.send(instruction).await
+    }
+
+    async fn sync(&mut self) -> Result<(), solana_program_test::BanksClientError> {
+        let instruction = Instruction::new_with_bytes(
+            liquidity_pool::id(),
+            &[4],
+            vec![
+                AccountMeta::new(self.pool.pool, false),
+                AccountMeta::new_readonly(self.pool.vault_a, false),
+                AccountMeta::new_readonly(self.pool.vault_b, false),
+            ],
+        );
+        // Anyone can sync, so only the payer signs
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.payer.pubkey()),
+            &[&self.payer],
+            self.blockhash,
+        );
+        self.banks.process_transaction(transaction).await
+    }
+
+    async fn send(
+        &mut self,
+        instruction: Instruction,
+    ) -> Result<(), solana_program_test::BanksClientError> {
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.payer.pubkey()),
+            &[&self.payer, &self.user.wallet],
+            self.blockhash,
+        );
+        self.banks.process_transaction(transaction).await
+    }
+
+    async fn balance(&mut self, account: Pubkey) -> u64 {
+        let account = self.banks.get_account(account).await.unwrap().unwrap();
+        TokenAccount::unpack(&account.data).unwrap().amount
+    }
+
+    async fn pool_state(&mut self) -> PoolState {
+        let account = self.banks.get_account(self.pool.pool).await.unwrap().unwrap();
+        PoolState::try_from_slice(&account.data).unwrap()
+    }
+
+    async fn lp_supply(&mut self) -> u64 {
+        let account = self.banks.get_account(self.pool.lp_mint).await.unwrap().unwrap();
+        Mint::unpack(&account.data).unwrap().supply
+    }
+}
+
+// Shared account list of AddLiquidity and RemoveLiquidity
+fn liquidity_instruction(
+    pool: &Pool,
+    wallet: &Pubkey,
+    token_a: Pubkey,
+    token_b: Pubkey,
+    lp: Pubkey,
+    data: Vec<u8>,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        liquidity_pool::id(),
+        &data,
+        vec![
+            AccountMeta::new(pool.pool, false),
+            AccountMeta::new(token_a, false),
+            AccountMeta::new(token_b, false),
+            AccountMeta::new(pool.vault_a, false),
+            AccountMeta::new(pool.vault_b, false),
+            AccountMeta::new(pool.lp_mint, false),
+            AccountMeta::new(lp, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*wallet, true),
+            AccountMeta::new_readonly(pool.authority, false),
+        ],
+    )
+}
+
+// Creates the vaults and LP mint under a new pool's authority and initializes a
+// pool with the given curve over them
+#[allow(clippy::too_many_arguments)]
+async fn create_pool(
+    banks: &mut BanksClient,
+    payer: &Keypair,
+    blockhash: Hash,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+    curve: Curve,
+    protocol_fee: Option<(Pubkey, u64)>,
+) -> Pool {
+    let pool = Keypair::new();
+    let (authority, _) = Pubkey::find_program_address(
+        &[POOL_AUTHORITY_SEED, pool.pubkey().as_ref()],
+        &liquidity_pool::id(),
+    );
+    let lp_mint = create_mint(banks, payer, blockhash, &authority).await;
+    let vault_a = create_token_account(banks, payer, blockhash, mint_a, &authority).await;
+    let vault_b = create_token_account(banks, payer, blockhash, mint_b, &authority).await;
+
+    let rent = banks.get_rent().await.unwrap();
+    let (curve_type, curve_param, initial_tick) = curve;
+    let mut data = vec![0, curve_type];
+    data.extend_from_slice(&curve_param.to_le_bytes());
+    data.extend_from_slice(&initial_tick.to_le_bytes());
+    let mut accounts = vec![
+        AccountMeta::new(pool.pubkey(), false),
+        AccountMeta::new_readonly(*mint_a, false),
+        AccountMeta::new_readonly(*mint_b, false),
+        AccountMeta::new_readonly(vault_a, false),
+        AccountMeta::new_readonly(vault_b, false),
+        AccountMeta::new_readonly(lp_mint, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(authority, false),
+    ];
+    if let Some((fee_authority, protocol_fee_bps)) = protocol_fee {
+        data.extend_from_slice(&protocol_fee_bps.to_le_bytes());
+        accounts.push(AccountMeta::new_readonly(fee_authority, false));
+    }
+    let initialize = Instruction::new_with_bytes(liquidity_pool::id(), &data, accounts);
+    process(
+        banks,
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &pool.pubkey(),
+                rent.minimum_balance(POOL_STATE_LEN),
+                POOL_STATE_LEN as u64,
+                &liquidity_pool::id(),
+            ),
+            initialize,
+        ],
+        payer,
+        &[&pool],
+        blockhash,
+    )
+    .await;
+
+    Pool { pool: pool.pubkey(), authority, vault_a, vault_b, lp_mint }
+}
+
+async fn process(
+    banks: &mut BanksClient,
+    instructions: &[Instruction],
+    payer: &Keypair,
+    signers: &[&Keypair],
+    blockhash: Hash,
+) {
+    let mut all_signers = vec![payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    banks.process_transaction(transaction).await.unwrap();
+}
+
+async fn create_mint(
+    banks: &mut BanksClient,
+    payer: &Keypair,
+    blockhash: Hash,
+    authority: &Pubkey,
+) -> Pubkey {
+    let mint = Keypair::new();
+    let rent = banks.get_rent().await.unwrap();
+    let instructions = [
+        system_instruction::create_account(
+            &payer.pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(Mint::LEN),
+            Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint(
+            &spl_token::id(),
+            &mint.pubkey(),
+            authority,
+            None,
+            6,
+        )
+        .unwrap(),
+    ];
+    process(banks, &instructions, payer, &[&mint], blockhash).await;
+    mint.pubkey()
+}
+
+async fn mint_to(
+    banks: &mut BanksClient,
+    payer: &Keypair,
+    blockhash: Hash,
+    mint: &Pubkey,
+    account: &Pubkey,
+    amount: u64,
+) {
+    let instruction = spl_token::instruction::mint_to(
+        &spl_token::id(),
+        mint,
+        account,
+        &payer.pubkey(),
+        &[],
+        amount,
+    )
+    .unwrap();
+    process(banks, &[instruction], payer, &[], blockhash).await;
+}
+
+async fn create_token_account(
+    banks: &mut BanksClient,
+    payer: &Keypair,
+    blockhash: Hash,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Pubkey {
+    let account = Keypair::new();
+    let rent = banks.get_rent().await.unwrap();
+    let instructions = [
+        system_instruction::create_account(
+            &payer.pubkey(),
+            &account.pubkey(),
+            rent.minimum_balance(TokenAccount::LEN),
+            TokenAccount::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_account(&spl_token::id(), &account.pubkey(), mint, owner)
+            .unwrap(),
+    ];
+    process(banks, &instructions, payer, &[&account], blockhash).await;
+    account.pubkey()
+}
+
+// Constant-product output after the 0.3% fee, as the program computes it
+fn expected_output(amount_in: u64, reserve_in: u64, reserve_out: u64) -> u64 {
+    let amount_in_with_fee = amount_in as u128 * (10_000 - FEE_RATE_BPS) as u128;
+    (amount_in_with_fee * reserve_out as u128
+        / (reserve_in as u128 * 10_000 + amount_in_with_fee)) as u64
+}
+
+#[tokio::test]
+async fn add_liquidity_fills_vaults_and_mints_lp() {
+    let mut env = Env::start().await;
+
+    // The first deposit mints sqrt(amount_a * amount_b)
+    env.add_liquidity(100_000, 400_000).await;
+    assert_eq!(env.balance(env.pool.vault_a).await, 100_000);
+    assert_eq!(env.balance(env.pool.vault_b).await, 400_000);
+    assert_eq!(env.balance(env.user.token_a).await, USER_BALANCE - 100_000);
+    assert_eq!(env.balance(env.user.token_b).await, USER_BALANCE - 400_000);
+    assert_eq!(env.balance(env.user.lp).await, 200_000);
+    assert_eq!(env.lp_supply().await, 200_000);
+
+    // Later deposits mint in proportion to the existing supply
+    env.add_liquidity(10_000, 40_000).await;
+    assert_eq!(env.balance(env.pool.vault_a).await, 110_000);
+    assert_eq!(env.balance(env.pool.vault_b).await, 440_000);
+    assert_eq!(env.balance(env.user.token_a).await, USER_BALANCE - 110_000);
+    assert_eq!(env.balance(env.user.token_b).await, USER_BALANCE - 440_000);
+    assert_eq!(env.balance(env.user.lp).await, 220_000);
+    assert_eq!(env.lp_supply().await, 220_000);
+}
+
+#[tokio::test]
+async fn remove_liquidity_burns_lp_and_returns_share() {
+    let mut env = Env::start().await;
+    env.add_liquidity(100_000, 400_000).await;
+
+    env.remove_liquidity(50_000).await;
+    assert_eq!(env.balance(env.pool.vault_a).await, 75_000);
+    assert_eq!(env.balance(env.pool.vault_b).await, 300_000);
+    assert_eq!(env.balance(env.user.token_a).await, USER_BALANCE - 75_000);
+    assert_eq!(env.balance(env.user.token_b).await, USER_BALANCE - 300_000);
+    assert_eq!(env.balance(env.user.lp).await, 150_000);
+    assert_eq!(env.lp_supply().await, 150_000);
+}
+
+#[tokio::test]
+async fn swap_pays_constant_product_output() {
+    let mut env = Env::start().await;
+    env.add_liquidity(100_000, 400_000).await;
+    let user_a = env.balance(env.user.token_a).await;
+    let user_b = env.balance(env.user.token_b).await;
+
+    let amount_out = expected_output(10_000, 100_000, 400_000);
+    env.swap_a_to_b(10_000, amount_out).await.unwrap();
+    assert_eq!(env.balance(env.pool.vault_a).await, 110_000);
+    assert_eq!(env.balance(env.pool.vault_b).await, 400_000 - amount_out);
+    assert_eq!(env.balance(env.user.token_a).await, user_a - 10_000);
+    assert_eq!(env.balance(env.user.token_b).await, user_b + amount_out);
+    assert_eq!(env.lp_supply().await, 200_000);
+}
+
+#[tokio::test]
+async fn swap_below_minimum_output_moves_nothing() {
+    let mut env = Env::start().await;
+    env.add_liquidity(100_000, 400_000).await;
+
+    let amount_out = expected_output(10_000, 100_000, 400_000);
+    assert!(env.swap_a_to_b(10_000, amount_out + 1).await.is_err());
+    assert_eq!(env.balance(env.pool.vault_a).await, 100_000);
+    assert_eq!(env.balance(env.pool.vault_b).await, 400_000);
+    assert_eq!(env.balance(env.user.token_a).await, USER_BALANCE - 100_000);
+    assert_eq!(env.balance(env.user.token_b).await, USER_BALANCE - 400_000);
+}
+
+// Routes A -> B through the env's pool and B -> C through a second pool
+struct RouteFixture {
+    pool_bc: Pool,
+    token_c: Pubkey,
+}
+
+impl RouteFixture {
+    async fn new(env: &mut Env, amount_b: u64, amount_c: u64) -> Self {
+        let payer = env.payer.insecure_clone();
+        let owner = env.user.wallet.pubkey();
+        let mint_c = create_mint(&mut env.banks, &payer, env.blockhash, &payer.pubkey()).await;
+        let (mint_b, blockhash) = (env.mint_b, env.blockhash);
+        let pool_bc =
+            create_pool(&mut env.banks, &payer, blockhash, &mint_b, &mint_c, CONSTANT_PRODUCT, None)
+                .await;
+        let token_c =
+            create_token_account(&mut env.banks, &payer, env.blockhash, &mint_c, &owner).await;
+        let lp =
+            create_token_account(&mut env.banks, &payer, env.blockhash, &pool_bc.lp_mint, &owner)
+                .await;
+        mint_to(&mut env.banks, &payer, env.blockhash, &mint_c, &token_c, USER_BALANCE).await;
+
+        let mut data = vec![1];
+        data.extend_from_slice(&amount_b.to_le_bytes());
+        data.extend_from_slice(&amount_c.to_le_bytes());
+        let instruction =
+            liquidity_instruction(&pool_bc, &owner, env.user.token_b, token_c, lp, data);
+        env.send(instruction).await.unwrap();
+
+        RouteFixture { pool_bc, token_c }
+    }
+
+    fn route_instruction(
+        &self,
+        env: &Env,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Instruction {
+        let args = RouteArgs { amount_in, minimum_amount_out, hops: 2 };
+        let mut data = vec![8];
+        data.extend_from_slice(&args.try_to_vec().unwrap());
+        Instruction::new_with_bytes(
+            liquidity_pool::id(),
+            &data,
+            vec![
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(env.user.wallet.pubkey(), true),
+                AccountMeta::new(env.user.token_a, false),
+                AccountMeta::new(env.pool.pool, false),
+                AccountMeta::new(env.pool.vault_a, false),
+                AccountMeta::new(env.pool.vault_b, false),
+                AccountMeta::new_readonly(env.pool.authority, false),
+                AccountMeta::new(env.user.token_b, false),
+                AccountMeta::new(self.pool_bc.pool, false),
+                AccountMeta::new(self.pool_bc.vault_a, false),
+                AccountMeta::new(self.pool_bc.vault_b, false),
+                AccountMeta::new_readonly(self.pool_bc.authority, false),
+                AccountMeta::new(self.token_c, false),
+            ],
+        )
+    }
+}
+
+#[tokio::test]
+async fn route_chains_two_pools() {
+    let mut env = Env::start().await;
+    env.add_liquidity(100_000, 400_000).await;
+    let fixture = RouteFixture::new(&mut env, 200_000, 300_000).await;
+    let user_b = env.balance(env.user.token_b).await;
+    let user_c = env.balance(fixture.token_c).await;
+
+    // The first hop's output is exactly what the second hop spends
+    let intermediate = expected_output(10_000, 100_000, 400_000);
+    let amount_out = expected_output(intermediate, 200_000, 300_000);
+    let instruction = fixture.route_instruction(&env, 10_000, amount_out);
+    env.send(instruction).await.unwrap();
+
+    assert_eq!(env.balance(env.pool.vault_a).await, 110_000);
+    assert_eq!(env.balance(env.pool.vault_b).await, 400_000 - intermediate);
+    assert_eq!(env.balance(fixture.pool_bc.vault_a).await, 200_000 + intermediate);
+    assert_eq!(env.balance(fixture.pool_bc.vault_b).await, 300_000 - amount_out);
+    assert_eq!(env.balance(env.user.token_b).await, user_b);
+    assert_eq!(env.balance(fixture.token_c).await, user_c + amount_out);
+}
+
+#[tokio::test]
+async fn route_below_minimum_output_moves_nothing() {
+    let mut env = Env::start().await;
+    env.add_liquidity(100_000, 400_000).await;
+    let fixture = RouteFixture::new(&mut env, 200_000, 300_000).await;
+    let user_a = env.balance(env.user.token_a).await;
+    let user_c = env.balance(fixture.token_c).await;
+
+    let intermediate = expected_output(10_000, 100_000, 400_000);
+    let amount_out = expected_output(intermediate, 200_000, 300_000);
+    let instruction = fixture.route_instruction(&env, 10_000, amount_out + 1);
+    assert!(env.send(instruction).await.is_err());
+
+    assert_eq!(env.balance(env.pool.vault_b).await, 400_000);
+    assert_eq!(env.balance(fixture.pool_bc.vault_a).await, 200_000);
+    assert_eq!(env.balance(env.user.token_a).await, user_a);
+    assert_eq!(env.balance(fixture.token_c).await, user_c);
+}
+
+#[tokio::test]
+async fn protocol_fees_accrue_and_are_collected_once() {
+    let fee_authority = Keypair::new();
+    let mut env = Env::start_with(CONSTANT_PRODUCT, Some((fee_authority.pubkey(), 5_000))).await;
+    env.add_liquidity(100_000, 400_000).await;
+    let payer = env.payer.insecure_clone();
+    let owner = fee_authority.pubkey();
+    let (mint_a, mint_b) = (env.mint_a, env.mint_b);
+    let destination_a =
+        create_token_account(&mut env.banks, &payer, env.blockhash, &mint_a, &owner).await;
+    let destination_b =
+        create_token_account(&mut env.banks, &payer, env.blockhash, &mint_b, &owner).await;
+
+    // Half of the 0.3% fee on 10_000 A stays in the vault outside the reserves
+    env.swap_a_to_b(10_000, 0).await.unwrap();
+    let state = env.pool_state().await;
+    assert_eq!(state.protocol_fees_a, 15);
+    assert_eq!(state.protocol_fees_b, 0);
+    assert_eq!(state.token_a_amount, 110_000 - 15);
+    assert_eq!(env.balance(env.pool.vault_a).await, 110_000);
+
+    let collect = Instruction::new_with_bytes(
+        liquidity_pool::id(),
+        &[7],
+        vec![
+            AccountMeta::new(env.pool.pool, false),
+            AccountMeta::new_readonly(fee_authority.pubkey(), true),
+            AccountMeta::new(env.pool.vault_a, false),
+            AccountMeta::new(env.pool.vault_b, false),
+            AccountMeta::new(destination_a, false),
+            AccountMeta::new(destination_b, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(env.pool.authority, false),
+        ],
+    );
+    for attempt in 0..2 {
+        // The differing compute limit keeps the repeat from being deduplicated
+        let limit = ComputeBudgetInstruction::set_compute_unit_limit(200_000 + attempt);
+        let transaction = Transaction::new_signed_with_payer(
+            &[limit, collect.clone()],
+            Some(&payer.pubkey()),
+            &[&payer, &fee_authority],
+            env.blockhash,
+        );
+        env.banks.process_transaction(transaction).await.unwrap();
+
+        // The second collection finds nothing left to pay
+        let state = env.pool_state().await;
+        assert_eq!(state.protocol_fees_a, 0);
+        assert_eq!(state.protocol_fees_b, 0);
+        assert_eq!(state.token_a_amount, 110_000 - 15);
+        assert_eq!(env.balance(destination_a).await, 15);
+        assert_eq!(env.balance(destination_b).await, 0);
+        assert_eq!(env.balance(env.pool.vault_a).await, 110_000 - 15);
+    }
+}
+
+#[tokio::test]
+async fn sync_adopts_direct_transfers_into_the_vaults() {
+    let mut env = Env::start().await;
+    env.add_liquidity(100_000, 400_000).await;
+    let payer = env.payer.insecure_clone();
+    let (mint_a, vault_a) = (env.mint_a, env.pool.vault_a);
+    mint_to(&mut env.banks, &payer, env.blockhash, &mint_a, &vault_a, 5_000).await;
+
+    env.sync().await.unwrap();
+    let state = env.pool_state().await;
+    assert_eq!(state.token_a_amount, 105_000);
+    assert_eq!(state.token_b_amount, 400_000);
+}
+
+#[tokio::test]
+async fn sync_rejects_concentrated_pools() {
+    let mut env = Env::start_with(CONCENTRATED, None).await;
+    assert!(env.sync().await.is_err());
+}
@@ -6,20 +6,40 @@ use solana_program::{
     entrypoint,
     entrypoint::ProgramResult,
     msg,
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     pubkey::Pubkey,
-    sysvar::Sysvar,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
 };
-use spl_token::state::Account as TokenAccount;
+use solana_program::program_pack::Pack;
+use spl_token::state::{Account as TokenAccount, Mint};
 use thiserror::Error;
 
 // Program ID and Fee Wallet
-solana_program::declare_id!("StakingPool111111111111111111111111111111111");
+solana_program::declare_id!("StakingPoo111111111111111111111111111111111");
 pub const FEE_WALLET: &str = "6zkf4DviZZkpWVEh53MrcQV6vGXGpESnNXgAvU6KpBUH";
 pub const SERVICE_FEE_BPS: u64 = 30; // 0.3% fee
+pub const MAX_LOCK_TIERS: usize = 8;
+pub const BASE_MULTIPLIER_BPS: u16 = 10_000; // 1x
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+pub const MAX_EMISSION_STEPS: u32 = 64;
+pub const REWARD_VESTING_SEED: &[u8] = b"reward_vesting";
+pub const STAKE_POOL_SEED: &[u8] = b"stake_pool";
+pub const VAULT_AUTHORITY_SEED: &[u8] = b"vault_authority";
+pub const USER_STAKE_SEED: &[u8] = b"user_stake";
+pub const STAKING_REFERRAL_SEED: &[u8] = b"staking_referral";
+pub const VOTING_POWER_SEED: &[u8] = b"voting_power";
+pub const MAX_EPOCH_HISTORY: usize = 32;
+pub const SNAPSHOT_TOLERANCE: i64 = 60; // Max seconds a voting-power snapshot may lag its time
+
+// Workspace launchpad whose participants can earn a staking boost
+pub const LAUNCHPAD_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("Launchpad1111111111111111111111111111111111");
+pub const PARTICIPANT_SEED: &[u8] = b"participant";
 
 // Program ID
-// solana_program::declare_id!("StakingPool111111111111111111111111111111111");
+// solana_program::declare_id!("StakingPoo111111111111111111111111111111111");
 
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
 pub struct StakePool {
@@ -32,6 +52,136 @@ pub struct StakePool {
     pub reward_rate: u64,  // Rewards per second
     pub last_update_time: i64,
     pub reward_per_token_stored: u128,
+    pub total_weighted_stake: u64,
+    pub lock_tiers: Vec<LockTier>,
+    pub reward_mint: Pubkey,
+    pub stake_decimals: u8,
+    pub reward_decimals: u8,
+    pub period_finish: i64,     // Emissions stop here until the pool is funded again
+    pub rewards_duration: i64,
+    pub emission: EmissionSchedule,
+    pub max_total_staked: u64,  // 0 = uncapped
+    pub guardian: Pubkey,       // May pause staking during incidents
+    pub is_paused: bool,
+    pub reward_vesting_duration: i64,  // 0 pays rewards out directly
+    pub epochs: EpochAccounting,
+    pub boost_launchpad: Pubkey,       // Default pubkey disables the participant boost
+    pub boost_multiplier_bps: u16,
+    pub unstake_cooldown: i64,  // 0 = Unstake pays out immediately
+    pub referral_bps: u16,      // Share of a referee's claimed rewards paid to their referrer
+    pub max_stake_per_wallet: u64,  // 0 = uncapped
+    pub reward_liabilities: u64,    // Distributed rewards not yet paid out of the reward vault
+}
+
+impl StakePool {
+    pub const LEN: usize = 1 + 32 * 4 + 8 + 8 + 8 + 16 + 8
+        + 4 + MAX_LOCK_TIERS * LockTier::LEN
+        + 32 + 1 + 1
+        + 8 + 8
+        + EmissionSchedule::LEN
+        + 8
+        + 32 + 1
+        + 8
+        + EpochAccounting::LEN
+        + 32 + 2
+        + 8
+        + 2
+        + 8
+        + 8;
+
+    // Accounts are sized for MAX_LOCK_TIERS, so trailing bytes are expected
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Ok(Self::deserialize(&mut &data[..])?)
+    }
+
+    // Accumulator scale; widened by the decimal gap so a reward unit per whole
+    // staked token stays representable when the stake mint has more decimals
+    pub fn reward_scale(&self) -> Result<u128, ProgramError> {
+        let gap = self.stake_decimals.saturating_sub(self.reward_decimals) as u32;
+        REWARD_PRECISION
+            .checked_mul(10u128.checked_pow(gap).ok_or(ProgramError::Overflow)?)
+            .ok_or(ProgramError::Overflow)
+    }
+
+    // Vault balance left for future emissions once rewards already owed to stakers,
+    // including vesting schedules and the open epoch's accrual, are set aside
+    pub fn unreserved_rewards(&self, vault_balance: u64) -> u128 {
+        (vault_balance as u128)
+            .saturating_sub(self.reward_liabilities as u128)
+            .saturating_sub(self.epochs.accrued as u128)
+    }
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct LockTier {
+    pub duration: i64,        // Lock period in seconds
+    pub multiplier_bps: u16,  // Reward weight, 10_000 = 1x
+}
+
+impl LockTier {
+    pub const LEN: usize = 8 + 2;
+}
+
+#[derive(Clone, Debug, Default, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct EmissionSchedule {
+    pub decay_interval: i64,  // Seconds between rate cuts, 0 keeps the rate flat
+    pub decay_bps: u16,       // Cut applied each interval, 5_000 = halving
+    pub next_decay_time: i64,
+}
+
+impl EmissionSchedule {
+    pub const LEN: usize = 8 + 2 + 8;
+
+    pub fn is_enabled(&self) -> bool {
+        self.decay_interval > 0 && self.decay_bps > 0
+    }
+
+    fn decay(&self, rate: u128) -> u128 {
+        rate * (10_000 - self.decay_bps as u128) / 10_000
+    }
+}
+
+// Optional accounting mode: emissions collect over an epoch and are split at its end
+// across the weight staked when it began; stake added mid-epoch waits for the next one
+#[derive(Clone, Debug, Default, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct EpochAccounting {
+    pub duration: i64,              // 0 = continuous per-second accounting
+    pub current_epoch: u64,
+    pub end_time: i64,
+    pub accrued: u64,               // Emitted this epoch, not yet distributed
+    pub total_pending_weight: u64,  // Weight added this epoch, eligible from the next
+    pub start_rewards: Vec<u128>,   // reward_per_token_stored at the start of recent epochs
+}
+
+impl EpochAccounting {
+    pub const LEN: usize = 8 + 8 + 8 + 8 + 8 + 4 + MAX_EPOCH_HISTORY * 16;
+
+    pub fn is_enabled(&self) -> bool {
+        self.duration > 0
+    }
+
+    // Accumulator at the start of `epoch`; epochs older than the history fall back
+    // to the oldest recorded start, which under-credits rather than over-credits
+    fn start_reward(&self, epoch: u64) -> Option<u128> {
+        let oldest = self.current_epoch.saturating_sub(MAX_EPOCH_HISTORY as u64 - 1);
+        let epoch = epoch.max(oldest);
+        self.start_rewards.get((epoch % MAX_EPOCH_HISTORY as u64) as usize).copied()
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct SetEmissionScheduleArgs {
+    pub decay_interval: i64,
+    pub decay_bps: u16,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct InitializePoolArgs {
+    pub reward_rate: u64,        // 0 leaves emissions off until FundRewards
+    pub rewards_duration: i64,
+    pub lock_tiers: Vec<LockTier>,
+    pub max_total_staked: u64,   // 0 = uncapped
+    pub max_stake_per_wallet: u64,  // 0 = uncapped
 }
 
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
@@ -42,6 +192,106 @@ pub struct UserStakeInfo {
     pub reward_per_token_paid: u128,
     pub start_time: i64,
     pub lock_period: i64,  // Lock period in seconds
+    pub lock_tier: u8,
+    pub weighted_stake: u64,
+    pub beneficiary: Pubkey,  // Receives rewards; the owner deposits and unstakes
+    pub pending_weight: u64,  // Epoch mode: weight not yet eligible for rewards
+    pub pending_epoch: u64,
+    pub launchpad_boosted: bool,
+    pub cooling_amount: u64,  // Unstaked, earning nothing, withdrawable after cooldown_end
+    pub cooldown_end: i64,
+    pub referrer: Pubkey,     // Set on the first stake only; default when not referred
+}
+
+impl UserStakeInfo {
+    pub const LEN: usize = 32 + 8 + 8 + 16 + 8 + 8 + 1 + 8 + 32 + 8 + 8 + 1 + 8 + 8 + 32;
+}
+
+// Referral earnings per referrer, PDA at [STAKING_REFERRAL_SEED, pool, referrer]
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct StakingReferral {
+    pub pool: Pubkey,
+    pub referrer: Pubkey,
+    pub referee_count: u64,
+    pub total_paid: u64,
+}
+
+impl StakingReferral {
+    pub const LEN: usize = 32 + 32 + 8 + 8;
+}
+
+// Leading fields of the launchpad program's Participant record
+#[derive(BorshDeserialize, Debug)]
+pub struct LaunchpadParticipant {
+    pub wallet: Pubkey,
+    pub amount_contributed: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct SetLaunchpadBoostArgs {
+    pub launchpad: Pubkey,
+    pub multiplier_bps: u16,
+}
+
+// Claimed rewards unlocking linearly, PDA at [REWARD_VESTING_SEED, pool, beneficiary]
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct RewardVesting {
+    pub pool: Pubkey,
+    pub beneficiary: Pubkey,
+    pub locked_total: u64,   // Amount vesting under the current schedule
+    pub start_time: i64,
+    pub duration: i64,
+    pub released: u64,       // Paid out of locked_total so far
+    pub carried: u64,        // Vested under earlier schedules but not yet paid
+}
+
+impl RewardVesting {
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 8 + 8 + 8;
+
+    fn vested(&self, current_time: i64) -> u64 {
+        let elapsed = current_time.saturating_sub(self.start_time);
+        if self.duration <= 0 || elapsed >= self.duration {
+            return self.locked_total;
+        }
+        ((self.locked_total as u128) * (elapsed.max(0) as u128) / (self.duration as u128)) as u64
+    }
+
+    pub fn claimable(&self, current_time: i64) -> u64 {
+        self.carried
+            .saturating_add(self.vested(current_time).saturating_sub(self.released))
+    }
+
+    // Folds what has vested so far into `carried` and restarts the schedule with the new amount
+    fn add(&mut self, amount: u64, current_time: i64, duration: i64) -> ProgramResult {
+        let vested = self.vested(current_time);
+        self.carried = self.carried
+            .checked_add(vested.saturating_sub(self.released))
+            .ok_or(ProgramError::Overflow)?;
+        self.locked_total = self.locked_total
+            .checked_sub(vested)
+            .ok_or(ProgramError::Overflow)?
+            .checked_add(amount)
+            .ok_or(ProgramError::Overflow)?;
+        self.released = 0;
+        self.start_time = current_time;
+        self.duration = duration;
+        Ok(())
+    }
+}
+
+// Immutable voting-power record, PDA at [VOTING_POWER_SEED, pool, wallet, snapshot_time]
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct VotingPowerCheckpoint {
+    pub pool: Pubkey,
+    pub wallet: Pubkey,
+    pub snapshot_time: i64,
+    pub recorded_at: i64,
+    pub stake_amount: u64,
+    pub lock_remaining: i64,  // Seconds until the position unlocks, as of recorded_at
+}
+
+impl VotingPowerCheckpoint {
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 8 + 8;
 }
 
 #[derive(FromPrimitive, Debug)]
@@ -51,6 +301,26 @@ pub enum StakingInstruction {
     Unstake,
     ClaimReward,
     UpdatePool,
+    FundRewards,
+    SetEmissionSchedule,
+    SetRewardRate,
+    SetMaxTotalStaked,
+    SetPaused,
+    SetGuardian,
+    SetRewardVesting,
+    ClaimVested,
+    CreatePool,
+    CloseStakeAccount,
+    ExtendLock,
+    SnapshotVotingPower,
+    SetEpochAccounting,
+    SetLaunchpadBoost,
+    ApplyLaunchpadBoost,
+    SetUnstakeCooldown,
+    WithdrawUnstaked,
+    SetReferralBps,
+    SetMaxStakePerWallet,
+    GetPendingRewards,
 }
 
 #[derive(Error, Debug, Copy, Clone)]
@@ -67,6 +337,44 @@ pub enum StakingError {
     InsufficientStakeBalance,
     #[error("Stake still locked")]
     StakeLocked,
+    #[error("Invalid lock tier")]
+    InvalidLockTier,
+    #[error("Invalid mint")]
+    InvalidMint,
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Reward rate exceeds funded balance")]
+    InsufficientRewardBalance,
+    #[error("Invalid emission schedule")]
+    InvalidEmissionSchedule,
+    #[error("Emission backlog, run UpdatePool first")]
+    EmissionBacklog,
+    #[error("Pool staking cap reached")]
+    PoolCapReached,
+    #[error("Staking is paused")]
+    Paused,
+    #[error("Invalid vesting account")]
+    InvalidVestingAccount,
+    #[error("Invalid pool address")]
+    InvalidPoolAddress,
+    #[error("Stake account still has a balance")]
+    StakeAccountNotEmpty,
+    #[error("Invalid snapshot")]
+    InvalidSnapshot,
+    #[error("Pool must be empty to change accounting mode")]
+    PoolNotEmpty,
+    #[error("Invalid launchpad participant")]
+    InvalidParticipant,
+    #[error("Invalid vault authority")]
+    InvalidVaultAuthority,
+    #[error("Invalid stake account")]
+    InvalidStakeAccount,
+    #[error("Unstake cooldown still running")]
+    CooldownActive,
+    #[error("Invalid referrer")]
+    InvalidReferrer,
+    #[error("Wallet staking cap reached")]
+    WalletCapReached,
 }
 
 impl From<StakingError> for ProgramError {
@@ -88,7 +396,7 @@ pub fn process_instruction(
     match instruction {
         StakingInstruction::Initialize => {
             msg!("Instruction: Initialize Staking Pool");
-            process_initialize(program_id, accounts)
+            process_initialize(program_id, accounts, &instruction_data[1..])
         }
         StakingInstruction::Stake => {
             msg!("Instruction: Stake Tokens");
@@ -106,12 +414,93 @@ pub fn process_instruction(
             msg!("Instruction: Update Pool");
             process_update_pool(program_id, accounts)
         }
+        StakingInstruction::FundRewards => {
+            msg!("Instruction: Fund Rewards");
+            process_fund_rewards(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::SetEmissionSchedule => {
+            msg!("Instruction: Set Emission Schedule");
+            process_set_emission_schedule(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::SetRewardRate => {
+            msg!("Instruction: Set Reward Rate");
+            process_set_reward_rate(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::SetMaxTotalStaked => {
+            msg!("Instruction: Set Max Total Staked");
+            process_set_max_total_staked(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::SetPaused => {
+            msg!("Instruction: Set Paused");
+            process_set_paused(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::SetGuardian => {
+            msg!("Instruction: Set Guardian");
+            process_set_guardian(program_id, accounts)
+        }
+        StakingInstruction::SetRewardVesting => {
+            msg!("Instruction: Set Reward Vesting");
+            process_set_reward_vesting(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::ClaimVested => {
+            msg!("Instruction: Claim Vested");
+            process_claim_vested(program_id, accounts)
+        }
+        StakingInstruction::CreatePool => {
+            msg!("Instruction: Create Pool");
+            process_create_pool(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::CloseStakeAccount => {
+            msg!("Instruction: Close Stake Account");
+            process_close_stake_account(program_id, accounts)
+        }
+        StakingInstruction::ExtendLock => {
+            msg!("Instruction: Extend Lock");
+            process_extend_lock(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::SnapshotVotingPower => {
+            msg!("Instruction: Snapshot Voting Power");
+            process_snapshot_voting_power(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::SetEpochAccounting => {
+            msg!("Instruction: Set Epoch Accounting");
+            process_set_epoch_accounting(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::SetLaunchpadBoost => {
+            msg!("Instruction: Set Launchpad Boost");
+            process_set_launchpad_boost(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::ApplyLaunchpadBoost => {
+            msg!("Instruction: Apply Launchpad Boost");
+            process_apply_launchpad_boost(program_id, accounts)
+        }
+        StakingInstruction::SetUnstakeCooldown => {
+            msg!("Instruction: Set Unstake Cooldown");
+            process_set_unstake_cooldown(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::WithdrawUnstaked => {
+            msg!("Instruction: Withdraw Unstaked");
+            process_withdraw_unstaked(program_id, accounts)
+        }
+        StakingInstruction::SetReferralBps => {
+            msg!("Instruction: Set Referral Bps");
+            process_set_referral_bps(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::SetMaxStakePerWallet => {
+            msg!("Instruction: Set Max Stake Per Wallet");
+            process_set_max_stake_per_wallet(program_id, accounts, &instruction_data[1..])
+        }
+        StakingInstruction::GetPendingRewards => {
+            msg!("Instruction: Get Pending Rewards");
+            process_get_pending_rewards(program_id, accounts)
+        }
     }
 }
 
 fn process_initialize(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
+    instruction_data: &[u8],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let pool_account = next_account_info(account_info_iter)?;
@@ -119,21 +508,86 @@ fn process_initialize(
     let pool_authority = next_account_info(account_info_iter)?;
     let stake_token_account = next_account_info(account_info_iter)?;
     let reward_token_account = next_account_info(account_info_iter)?;
+    let reward_mint = next_account_info(account_info_iter)?;
 
-    let mut pool = StakePool::try_from_slice(&pool_account.data.borrow())?;
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
     if pool.is_initialized {
         return Err(StakingError::AlreadyInUse.into());
     }
 
+    let args = InitializePoolArgs::try_from_slice(instruction_data)?;
+    if args.lock_tiers.is_empty()
+        || args.lock_tiers.len() > MAX_LOCK_TIERS
+        || args.lock_tiers.iter().any(|tier| tier.duration < 0 || tier.multiplier_bps < BASE_MULTIPLIER_BPS)
+    {
+        return Err(StakingError::InvalidLockTier.into());
+    }
+    if args.rewards_duration <= 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Both vaults must hold the mint they are accounted in
+    let stake_mint_info = Mint::unpack(&token_mint.data.borrow())?;
+    let reward_mint_info = Mint::unpack(&reward_mint.data.borrow())?;
+    let stake_vault = TokenAccount::unpack(&stake_token_account.data.borrow())?;
+    let reward_vault = TokenAccount::unpack(&reward_token_account.data.borrow())?;
+    if stake_vault.mint != *token_mint.key || reward_vault.mint != *reward_mint.key {
+        return Err(StakingError::InvalidMint.into());
+    }
+    // Staked principal must never count towards reward funding
+    if stake_token_account.key == reward_token_account.key {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
+
+    // Vault tokens only move through the program's authority PDA
+    let (vault_authority, _) = Pubkey::find_program_address(
+        &[VAULT_AUTHORITY_SEED, pool_account.key.as_ref()],
+        program_id,
+    );
+    if stake_vault.owner != vault_authority || reward_vault.owner != vault_authority {
+        return Err(StakingError::InvalidVaultAuthority.into());
+    }
+
+    // A starting rate opens the first period right away, so it must already be funded
+    let promised = (args.reward_rate as u128)
+        .checked_mul(args.rewards_duration as u128)
+        .ok_or(ProgramError::Overflow)?;
+    if promised > reward_vault.amount as u128 {
+        return Err(StakingError::InsufficientRewardBalance.into());
+    }
+
     pool.is_initialized = true;
     pool.token_mint = *token_mint.key;
     pool.pool_authority = *pool_authority.key;
     pool.stake_token_account = *stake_token_account.key;
     pool.reward_token_account = *reward_token_account.key;
     pool.total_staked = 0;
-    pool.reward_rate = 100; // Example: 100 tokens per second
+    pool.reward_rate = args.reward_rate;
     pool.last_update_time = Clock::get()?.unix_timestamp;
     pool.reward_per_token_stored = 0;
+    pool.total_weighted_stake = 0;
+    pool.lock_tiers = args.lock_tiers;
+    pool.reward_mint = *reward_mint.key;
+    pool.stake_decimals = stake_mint_info.decimals;
+    pool.reward_decimals = reward_mint_info.decimals;
+    pool.period_finish = if args.reward_rate > 0 {
+        pool.last_update_time + args.rewards_duration
+    } else {
+        pool.last_update_time
+    };
+    pool.rewards_duration = args.rewards_duration;
+    pool.emission = EmissionSchedule::default();
+    pool.max_total_staked = args.max_total_staked;
+    pool.guardian = *pool_authority.key;
+    pool.is_paused = false;
+    pool.reward_vesting_duration = 0;
+    pool.epochs = EpochAccounting::default();
+    pool.boost_launchpad = Pubkey::default();
+    pool.boost_multiplier_bps = BASE_MULTIPLIER_BPS;
+    pool.unstake_cooldown = 0;
+    pool.referral_bps = 0;
+    pool.max_stake_per_wallet = args.max_stake_per_wallet;
+    pool.reward_liabilities = 0;
 
     pool.serialize(&mut *pool_account.data.borrow_mut())?;
 
@@ -151,46 +605,149 @@ fn process_stake(
     let user_token_account = next_account_info(account_info_iter)?;
     let pool_token_account = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let depositor = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
     let clock = Clock::get()?;
 
+    if !depositor.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
     let amount = {
         let mut data = [0u8; 8];
         data.copy_from_slice(&instruction_data[..8]);
         u64::from_le_bytes(data)
     };
+    // Optional trailing byte selects the lock tier, defaulting to the shortest
+    let tier_index = instruction_data.get(8).copied().unwrap_or(0);
+    // Optional trailing pubkey stakes on behalf of another reward beneficiary
+    let beneficiary = match instruction_data.get(9..41) {
+        Some(bytes) => Some(Pubkey::try_from(bytes).map_err(|_| ProgramError::InvalidInstructionData)?),
+        None => None,
+    };
+    // Optional pubkey after that names a referrer, honoured on the first stake only
+    let referrer = match instruction_data.get(41..73) {
+        Some(bytes) => Some(Pubkey::try_from(bytes).map_err(|_| ProgramError::InvalidInstructionData)?),
+        None => None,
+    };
+
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    // Unstake and ClaimReward stay open while paused so users can always exit
+    if pool.is_paused {
+        return Err(StakingError::Paused.into());
+    }
+    let tier = pool.lock_tiers.get(tier_index as usize).cloned()
+        .ok_or(StakingError::InvalidLockTier)?;
+    // Positions live at [USER_STAKE_SEED, pool, owner] and are created on first stake
+    let (expected_stake_account, bump) = Pubkey::find_program_address(
+        &[USER_STAKE_SEED, pool_account.key.as_ref(), depositor.key.as_ref()],
+        program_id,
+    );
+    if *user_stake_info.key != expected_stake_account {
+        return Err(StakingError::InvalidStakeAccount.into());
+    }
+    if user_stake_info.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                depositor.key,
+                user_stake_info.key,
+                Rent::get()?.minimum_balance(UserStakeInfo::LEN),
+                UserStakeInfo::LEN as u64,
+                program_id,
+            ),
+            &[depositor.clone(), user_stake_info.clone(), system_program.clone()],
+            &[&[USER_STAKE_SEED, pool_account.key.as_ref(), depositor.key.as_ref(), &[bump]]],
+        )?;
+    }
 
-    let mut pool = StakePool::try_from_slice(&pool_account.data.borrow())?;
-    let mut user_info = if user_stake_info.data_len() > 0 {
-        UserStakeInfo::try_from_slice(&user_stake_info.data.borrow())?
+    let existing = UserStakeInfo::try_from_slice(&user_stake_info.data.borrow())?;
+    let is_new_position = existing.owner == Pubkey::default();
+    let mut user_info = if !is_new_position {
+        existing
     } else {
         UserStakeInfo {
-            owner: *user_token_account.key,
+            owner: *depositor.key,
             stake_amount: 0,
             rewards_earned: 0,
             reward_per_token_paid: 0,
             start_time: clock.unix_timestamp,
-            lock_period: 7 * 24 * 60 * 60, // 7 days lock period
+            lock_period: tier.duration,
+            lock_tier: tier_index,
+            weighted_stake: 0,
+            beneficiary: beneficiary.unwrap_or(*depositor.key),
+            pending_weight: 0,
+            pending_epoch: 0,
+            launchpad_boosted: false,
+            cooling_amount: 0,
+            cooldown_end: 0,
+            referrer: Pubkey::default(),
         }
     };
 
+    // Top-ups may omit the beneficiary but cannot redirect an existing position
+    if user_info.owner != *depositor.key
+        || beneficiary.map_or(false, |key| key != user_info.beneficiary)
+    {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    if let (true, Some(referrer)) = (is_new_position, referrer) {
+        if referrer == *depositor.key {
+            return Err(StakingError::InvalidReferrer.into());
+        }
+        let referral_account = next_account_info(account_info_iter)?;
+        register_referral(program_id, pool_account, depositor, referral_account, system_program, &referrer)?;
+        user_info.referrer = referrer;
+    }
+
+    // An open position stays in its tier; a new tier needs a fully unstaked position
+    if user_info.stake_amount > 0 && user_info.lock_tier != tier_index {
+        return Err(StakingError::InvalidLockTier.into());
+    }
+
+    if *pool_token_account.key != pool.stake_token_account {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
+
     // Update pool and calculate rewards before stake
     update_pool(&mut pool, clock.unix_timestamp)?;
     update_rewards(&mut pool, &mut user_info)?;
 
     // Transfer tokens to pool
-    spl_token::instruction::transfer(
-        token_program.key,
-        user_token_account.key,
-        pool_token_account.key,
-        &user_token_account.key,
-        &[],
-        amount,
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            user_token_account.key,
+            pool_token_account.key,
+            depositor.key,
+            &[],
+            amount,
+        )?,
+        &[
+            user_token_account.clone(),
+            pool_token_account.clone(),
+            depositor.clone(),
+            token_program.clone(),
+        ],
     )?;
 
     user_info.stake_amount = user_info.stake_amount.checked_add(amount)
         .ok_or(ProgramError::Overflow)?;
     pool.total_staked = pool.total_staked.checked_add(amount)
         .ok_or(ProgramError::Overflow)?;
+    if pool.max_total_staked > 0 && pool.total_staked > pool.max_total_staked {
+        return Err(StakingError::PoolCapReached.into());
+    }
+    // One position per wallet per pool, so the position total is the wallet's total
+    if pool.max_stake_per_wallet > 0 && user_info.stake_amount > pool.max_stake_per_wallet {
+        return Err(StakingError::WalletCapReached.into());
+    }
+
+    // Topping up restarts the lock so the multiplier always reflects a full lock
+    user_info.lock_tier = tier_index;
+    user_info.lock_period = tier.duration;
+    user_info.start_time = clock.unix_timestamp;
+    reweight_stake(&mut pool, &mut user_info)?;
 
     pool.serialize(&mut *pool_account.data.borrow_mut())?;
     user_info.serialize(&mut *user_stake_info.data.borrow_mut())?;
@@ -209,6 +766,8 @@ fn process_unstake(
     let user_token_account = next_account_info(account_info_iter)?;
     let pool_token_account = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let depositor = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
     let clock = Clock::get()?;
 
     let amount = {
@@ -217,8 +776,13 @@ fn process_unstake(
         u64::from_le_bytes(data)
     };
 
-    let mut pool = StakePool::try_from_slice(&pool_account.data.borrow())?;
-    let mut user_info = UserStakeInfo::try_from_slice(&user_stake_info.data.borrow())?;
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    let mut user_info = load_user_stake(program_id, pool_account, user_stake_info)?;
+
+    // Only the depositor can pull principal, even when rewards go elsewhere
+    if !depositor.is_signer || user_info.owner != *depositor.key {
+        return Err(StakingError::Unauthorized.into());
+    }
 
     // Check lock period
     if clock.unix_timestamp < user_info.start_time + user_info.lock_period {
@@ -229,24 +793,40 @@ fn process_unstake(
         return Err(StakingError::InsufficientStakeBalance.into());
     }
 
+    if *pool_token_account.key != pool.stake_token_account {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
+
     // Update pool and calculate rewards before unstake
     update_pool(&mut pool, clock.unix_timestamp)?;
     update_rewards(&mut pool, &mut user_info)?;
 
-    // Transfer tokens back to user
-    spl_token::instruction::transfer(
-        token_program.key,
-        pool_token_account.key,
-        user_token_account.key,
-        &pool_account.key,
-        &[],
-        amount,
-    )?;
+    if pool.unstake_cooldown > 0 {
+        // Two-step mode: the amount stops earning now and is released by WithdrawUnstaked;
+        // unstaking more while cooling restarts the timer for the whole amount
+        user_info.cooling_amount = user_info.cooling_amount.checked_add(amount)
+            .ok_or(ProgramError::Overflow)?;
+        user_info.cooldown_end = clock.unix_timestamp
+            .checked_add(pool.unstake_cooldown)
+            .ok_or(ProgramError::Overflow)?;
+    } else {
+        // Transfer tokens back to user
+        transfer_from_vault(
+            program_id,
+            pool_account,
+            pool_token_account,
+            user_token_account,
+            vault_authority,
+            token_program,
+            amount,
+        )?;
+    }
 
     user_info.stake_amount = user_info.stake_amount.checked_sub(amount)
         .ok_or(ProgramError::Overflow)?;
     pool.total_staked = pool.total_staked.checked_sub(amount)
         .ok_or(ProgramError::Overflow)?;
+    reweight_stake(&mut pool, &mut user_info)?;
 
     pool.serialize(&mut *pool_account.data.borrow_mut())?;
     user_info.serialize(&mut *user_stake_info.data.borrow_mut())?;
@@ -265,15 +845,29 @@ fn process_claim_reward(
     let pool_reward_account = next_account_info(account_info_iter)?;
     let fee_wallet_account = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let beneficiary = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
     let clock = Clock::get()?;
 
-    // Verify fee wallet
-    if fee_wallet_account.key.to_string() != FEE_WALLET {
+    // Verify fee wallet; the fee is paid into its token account for the reward mint
+    if TokenAccount::unpack(&fee_wallet_account.data.borrow())?.owner.to_string() != FEE_WALLET {
         return Err(ProgramError::InvalidArgument);
     }
 
-    let mut pool = StakePool::try_from_slice(&pool_account.data.borrow())?;
-    let mut user_info = UserStakeInfo::try_from_slice(&user_stake_info.data.borrow())?;
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    let mut user_info = load_user_stake(program_id, pool_account, user_stake_info)?;
+
+    // Rewards belong to the beneficiary, who may differ from the depositor
+    if !beneficiary.is_signer || user_info.beneficiary != *beneficiary.key {
+        return Err(StakingError::Unauthorized.into());
+    }
+    if TokenAccount::unpack(&user_reward_account.data.borrow())?.owner != user_info.beneficiary {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
+
+    if *pool_reward_account.key != pool.reward_token_account {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
 
     // Update rewards
     update_pool(&mut pool, clock.unix_timestamp)?;
@@ -287,29 +881,83 @@ fn process_claim_reward(
             .ok_or(ProgramError::Overflow)?
             .checked_div(10000)
             .ok_or(ProgramError::Overflow)?;
-        let user_reward = reward_amount.checked_sub(fee_amount)
+        // The referrer's share comes out of the referee's reward, so emissions stay within funding
+        let referral_amount = if user_info.referrer != Pubkey::default() {
+            reward_amount
+                .checked_mul(pool.referral_bps as u64)
+                .ok_or(ProgramError::Overflow)?
+                .checked_div(10000)
+                .ok_or(ProgramError::Overflow)?
+        } else {
+            0
+        };
+        let user_reward = reward_amount
+            .checked_sub(fee_amount)
+            .ok_or(ProgramError::Overflow)?
+            .checked_sub(referral_amount)
             .ok_or(ProgramError::Overflow)?;
 
-        // Transfer rewards to user
-        spl_token::instruction::transfer(
-            token_program.key,
-            pool_reward_account.key,
-            user_reward_account.key,
-            &pool_account.key,
-            &[],
-            user_reward,
-        )?;
+        if pool.reward_vesting_duration > 0 {
+            // Vesting mode: the net reward unlocks over time via ClaimVested
+            let vesting_account = next_account_info(account_info_iter)?;
+            let system_program = next_account_info(account_info_iter)?;
+            vest_reward(
+                program_id,
+                pool_account,
+                beneficiary,
+                vesting_account,
+                system_program,
+                user_reward,
+                clock.unix_timestamp,
+                pool.reward_vesting_duration,
+            )?;
+        } else {
+            // Transfer rewards to user
+            transfer_from_vault(
+                program_id,
+                pool_account,
+                pool_reward_account,
+                user_reward_account,
+                vault_authority,
+                token_program,
+                user_reward,
+            )?;
+        }
 
         // Transfer fee to fee wallet
-        spl_token::instruction::transfer(
-            token_program.key,
-            pool_reward_account.key,
-            fee_wallet_account.key,
-            &pool_account.key,
-            &[],
+        transfer_from_vault(
+            program_id,
+            pool_account,
+            pool_reward_account,
+            fee_wallet_account,
+            vault_authority,
+            token_program,
             fee_amount,
         )?;
 
+        if user_info.referrer != Pubkey::default() {
+            let referral_account = next_account_info(account_info_iter)?;
+            let referrer_reward_account = next_account_info(account_info_iter)?;
+            pay_referral(
+                program_id,
+                pool_account,
+                &user_info.referrer,
+                referral_account,
+                referrer_reward_account,
+                pool_reward_account,
+                vault_authority,
+                token_program,
+                referral_amount,
+            )?;
+        }
+
+        // Vested rewards stay owed until ClaimVested pays them out
+        let paid_out = if pool.reward_vesting_duration > 0 {
+            reward_amount.checked_sub(user_reward).ok_or(ProgramError::Overflow)?
+        } else {
+            reward_amount
+        };
+        pool.reward_liabilities = pool.reward_liabilities.saturating_sub(paid_out);
         user_info.rewards_earned = 0;
     }
 
@@ -327,8 +975,9 @@ fn process_update_pool(
     let pool_account = next_account_info(account_info_iter)?;
     let clock = Clock::get()?;
 
-    let mut pool = StakePool::try_from_slice(&pool_account.data.borrow())?;
-    update_pool(&mut pool, clock.unix_timestamp)?;
+    // Catches up as far as MAX_EMISSION_STEPS allows; repeat to clear a backlog
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    advance_pool(&mut pool, clock.unix_timestamp)?;
     pool.serialize(&mut *pool_account.data.borrow_mut())?;
 
     Ok(())
@@ -338,40 +987,177 @@ fn update_pool(
     pool: &mut StakePool,
     current_time: i64,
 ) -> ProgramResult {
-    if pool.total_staked == 0 {
-        pool.last_update_time = current_time;
-        return Ok(());
+    if !advance_pool(pool, current_time)? {
+        return Err(StakingError::EmissionBacklog.into());
     }
+    Ok(())
+}
 
-    let time_elapsed = current_time - pool.last_update_time;
-    if time_elapsed > 0 {
-        let reward = (time_elapsed as u64).checked_mul(pool.reward_rate)
-            .ok_or(ProgramError::Overflow)?;
-        let reward_per_token = (reward as u128)
-            .checked_mul(1_000_000_000_000u128)
-            .ok_or(ProgramError::Overflow)?
-            .checked_div(pool.total_staked as u128)
-            .ok_or(ProgramError::Overflow)?;
-        
-        pool.reward_per_token_stored = pool.reward_per_token_stored
-            .checked_add(reward_per_token)
-            .ok_or(ProgramError::Overflow)?;
-        pool.last_update_time = current_time;
+// Accrues rewards segment by segment across decay and epoch boundaries; returns
+// false if MAX_EMISSION_STEPS was hit before reaching current_time
+fn advance_pool(
+    pool: &mut StakePool,
+    current_time: i64,
+) -> Result<bool, ProgramError> {
+    let end_time = current_time.max(pool.last_update_time);
+    let mut steps = 0;
+
+    while pool.last_update_time < end_time {
+        let mut segment_end = end_time;
+        if pool.emission.is_enabled() && pool.emission.next_decay_time < pool.period_finish {
+            segment_end = segment_end.min(pool.emission.next_decay_time.max(pool.last_update_time));
+        }
+        if pool.epochs.is_enabled() {
+            segment_end = segment_end.min(pool.epochs.end_time.max(pool.last_update_time));
+        }
+
+        // Nothing is emitted past the funded period
+        let emitting_until = segment_end.min(pool.period_finish);
+        let time_elapsed = emitting_until - pool.last_update_time;
+        if time_elapsed > 0 {
+            let reward = (time_elapsed as u64).checked_mul(pool.reward_rate)
+                .ok_or(ProgramError::Overflow)?;
+            if pool.epochs.is_enabled() {
+                pool.epochs.accrued = pool.epochs.accrued
+                    .checked_add(reward)
+                    .ok_or(ProgramError::Overflow)?;
+            } else if pool.total_weighted_stake > 0 {
+                distribute_reward(pool, reward, pool.total_weighted_stake)?;
+            }
+        }
+        pool.last_update_time = segment_end;
+
+        if pool.emission.is_enabled()
+            && pool.emission.next_decay_time < pool.period_finish
+            && segment_end >= pool.emission.next_decay_time
+        {
+            pool.reward_rate = pool.emission.decay(pool.reward_rate as u128) as u64;
+            pool.emission.next_decay_time = pool.emission.next_decay_time
+                .checked_add(pool.emission.decay_interval)
+                .ok_or(ProgramError::Overflow)?;
+            steps += 1;
+        }
+        if pool.epochs.is_enabled() && segment_end >= pool.epochs.end_time {
+            roll_epoch(pool)?;
+            steps += 1;
+        }
+        if steps >= MAX_EMISSION_STEPS {
+            return Ok(pool.last_update_time >= end_time);
+        }
+    }
+
+    Ok(true)
+}
+
+fn distribute_reward(
+    pool: &mut StakePool,
+    reward: u64,
+    eligible_weight: u64,
+) -> ProgramResult {
+    let reward_per_token = (reward as u128)
+        .checked_mul(pool.reward_scale()?)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(eligible_weight as u128)
+        .ok_or(ProgramError::Overflow)?;
+
+    pool.reward_per_token_stored = pool.reward_per_token_stored
+        .checked_add(reward_per_token)
+        .ok_or(ProgramError::Overflow)?;
+    pool.reward_liabilities = pool.reward_liabilities
+        .checked_add(reward)
+        .ok_or(ProgramError::Overflow)?;
+
+    Ok(())
+}
+
+// Splits the epoch's emissions over the weight that was staked for all of it,
+// then makes this epoch's deposits eligible for the next one
+fn roll_epoch(pool: &mut StakePool) -> ProgramResult {
+    let eligible_weight = pool.total_weighted_stake
+        .checked_sub(pool.epochs.total_pending_weight)
+        .ok_or(ProgramError::Overflow)?;
+    if pool.epochs.accrued > 0 && eligible_weight > 0 {
+        distribute_reward(pool, pool.epochs.accrued, eligible_weight)?;
+    }
+
+    pool.epochs.accrued = 0;
+    pool.epochs.total_pending_weight = 0;
+    pool.epochs.current_epoch = pool.epochs.current_epoch
+        .checked_add(1)
+        .ok_or(ProgramError::Overflow)?;
+    pool.epochs.end_time = pool.epochs.end_time
+        .checked_add(pool.epochs.duration)
+        .ok_or(ProgramError::Overflow)?;
+    let slot = (pool.epochs.current_epoch % MAX_EPOCH_HISTORY as u64) as usize;
+    if let Some(start) = pool.epochs.start_rewards.get_mut(slot) {
+        *start = pool.reward_per_token_stored;
     }
 
     Ok(())
 }
 
+// Total emitted between two times at `rate` under the schedule, without touching the pool
+fn scheduled_emissions(
+    rate: u64,
+    emission: &EmissionSchedule,
+    from: i64,
+    to: i64,
+) -> Result<u128, ProgramError> {
+    let mut rate = rate as u128;
+    let mut cursor = from;
+    let mut next_decay_time = emission.next_decay_time;
+    let mut total: u128 = 0;
+
+    while cursor < to {
+        let segment_end = if emission.is_enabled() {
+            to.min(next_decay_time.max(cursor))
+        } else {
+            to
+        };
+        total = total
+            .checked_add(((segment_end - cursor) as u128).checked_mul(rate).ok_or(ProgramError::Overflow)?)
+            .ok_or(ProgramError::Overflow)?;
+        cursor = segment_end;
+
+        if emission.is_enabled() && cursor >= next_decay_time {
+            rate = emission.decay(rate);
+            next_decay_time = next_decay_time
+                .checked_add(emission.decay_interval)
+                .ok_or(ProgramError::Overflow)?;
+        }
+    }
+
+    Ok(total)
+}
+
 fn update_rewards(
     pool: &StakePool,
     user: &mut UserStakeInfo,
 ) -> ProgramResult {
     let reward_per_token = pool.reward_per_token_stored;
-    let rewards = (user.stake_amount as u128)
+    // Rewards accrue on the tier-weighted stake, not the raw amount
+    let eligible_weight = user.weighted_stake
+        .checked_sub(user.pending_weight)
+        .ok_or(ProgramError::Overflow)?;
+    let mut scaled_rewards = (eligible_weight as u128)
         .checked_mul(reward_per_token.checked_sub(user.reward_per_token_paid)
             .ok_or(ProgramError::Overflow)?)
-        .ok_or(ProgramError::Overflow)?
-        .checked_div(1_000_000_000_000u128)
+        .ok_or(ProgramError::Overflow)?;
+
+    // Epoch mode: pending weight has earned since the epoch after it was added
+    if user.pending_weight > 0 && user.pending_epoch < pool.epochs.current_epoch {
+        let eligible_from = pool.epochs.start_reward(user.pending_epoch + 1)
+            .unwrap_or(reward_per_token)
+            .max(user.reward_per_token_paid);
+        scaled_rewards = scaled_rewards
+            .checked_add((user.pending_weight as u128)
+                .checked_mul(reward_per_token.saturating_sub(eligible_from))
+                .ok_or(ProgramError::Overflow)?)
+            .ok_or(ProgramError::Overflow)?;
+        user.pending_weight = 0;
+    }
+    let rewards = scaled_rewards
+        .checked_div(pool.reward_scale()?)
         .ok_or(ProgramError::Overflow)?;
 
     user.rewards_earned = user.rewards_earned
@@ -381,3 +1167,1115 @@ fn update_rewards(
 
     Ok(())
 }
+
+fn reweight_stake(
+    pool: &mut StakePool,
+    user: &mut UserStakeInfo,
+) -> ProgramResult {
+    let multiplier_bps = pool.lock_tiers.get(user.lock_tier as usize)
+        .map(|tier| tier.multiplier_bps)
+        .unwrap_or(BASE_MULTIPLIER_BPS);
+    let boost_bps = if user.launchpad_boosted && pool.boost_launchpad != Pubkey::default() {
+        pool.boost_multiplier_bps
+    } else {
+        BASE_MULTIPLIER_BPS
+    };
+    let weighted_stake = (user.stake_amount as u128)
+        .checked_mul(multiplier_bps as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_mul(boost_bps as u128)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(BASE_MULTIPLIER_BPS as u128 * BASE_MULTIPLIER_BPS as u128)
+        .ok_or(ProgramError::Overflow)?;
+    let weighted_stake = u64::try_from(weighted_stake).map_err(|_| ProgramError::Overflow)?;
+
+    // Epoch mode: increases wait for the next epoch, decreases come out of pending weight first
+    if pool.epochs.is_enabled() {
+        if user.pending_epoch != pool.epochs.current_epoch {
+            user.pending_weight = 0;
+            user.pending_epoch = pool.epochs.current_epoch;
+        }
+        if weighted_stake > user.weighted_stake {
+            let added = weighted_stake - user.weighted_stake;
+            user.pending_weight = user.pending_weight.checked_add(added)
+                .ok_or(ProgramError::Overflow)?;
+            pool.epochs.total_pending_weight = pool.epochs.total_pending_weight
+                .checked_add(added)
+                .ok_or(ProgramError::Overflow)?;
+        } else {
+            let removed = (user.weighted_stake - weighted_stake).min(user.pending_weight);
+            user.pending_weight -= removed;
+            pool.epochs.total_pending_weight = pool.epochs.total_pending_weight
+                .checked_sub(removed)
+                .ok_or(ProgramError::Overflow)?;
+        }
+    }
+
+    pool.total_weighted_stake = pool.total_weighted_stake
+        .checked_sub(user.weighted_stake)
+        .ok_or(ProgramError::Overflow)?
+        .checked_add(weighted_stake)
+        .ok_or(ProgramError::Overflow)?;
+    user.weighted_stake = weighted_stake;
+
+    Ok(())
+}
+
+fn process_fund_rewards(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let funder_token_account = next_account_info(account_info_iter)?;
+    let pool_reward_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let amount = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        u64::from_le_bytes(data)
+    };
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+    if *pool_reward_account.key != pool.reward_token_account {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
+
+    // Settle at the old rate before changing it
+    update_pool(&mut pool, clock.unix_timestamp)?;
+
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            funder_token_account.key,
+            pool_reward_account.key,
+            authority.key,
+            &[],
+            amount,
+        )?,
+        &[
+            funder_token_account.clone(),
+            pool_reward_account.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    // Unemitted rewards from the current period roll into the new one
+    let leftover = scheduled_emissions(
+        pool.reward_rate,
+        &pool.emission,
+        clock.unix_timestamp,
+        pool.period_finish,
+    )?;
+
+    // The schedule restarts with each funding; the starting rate is chosen so the
+    // decayed emissions over the period add up to the funded total
+    let period_finish = clock.unix_timestamp
+        .checked_add(pool.rewards_duration)
+        .ok_or(ProgramError::Overflow)?;
+    pool.emission.next_decay_time = clock.unix_timestamp
+        .checked_add(pool.emission.decay_interval)
+        .ok_or(ProgramError::Overflow)?;
+    let unit_emissions = scheduled_emissions(10_000, &pool.emission, clock.unix_timestamp, period_finish)?;
+    let reward_rate = (amount as u128)
+        .checked_add(leftover)
+        .ok_or(ProgramError::Overflow)?
+        .checked_mul(10_000)
+        .ok_or(ProgramError::Overflow)?
+        .checked_div(unit_emissions)
+        .ok_or(ProgramError::Overflow)?;
+    pool.reward_rate = u64::try_from(reward_rate).map_err(|_| ProgramError::Overflow)?;
+
+    // The vault must cover everything the new period promises on top of what is already owed
+    let vault_balance = TokenAccount::unpack(&pool_reward_account.data.borrow())?.amount;
+    let promised = scheduled_emissions(pool.reward_rate, &pool.emission, clock.unix_timestamp, period_finish)?;
+    if promised > pool.unreserved_rewards(vault_balance) {
+        return Err(StakingError::InsufficientRewardBalance.into());
+    }
+
+    pool.last_update_time = clock.unix_timestamp;
+    pool.period_finish = period_finish;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_emission_schedule(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let args = SetEmissionScheduleArgs::try_from_slice(instruction_data)?;
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    // Bound the decay steps per funded period so the schedule math stays in budget
+    if args.decay_interval < 0
+        || args.decay_bps >= 10_000
+        || (args.decay_interval > 0
+            && pool.rewards_duration / args.decay_interval > MAX_EMISSION_STEPS as i64)
+    {
+        return Err(StakingError::InvalidEmissionSchedule.into());
+    }
+
+    // Settle under the old schedule; decay only lowers emissions, so funding still covers them
+    update_pool(&mut pool, clock.unix_timestamp)?;
+
+    pool.emission = EmissionSchedule {
+        decay_interval: args.decay_interval,
+        decay_bps: args.decay_bps,
+        next_decay_time: clock.unix_timestamp
+            .checked_add(args.decay_interval)
+            .ok_or(ProgramError::Overflow)?,
+    };
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_reward_rate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let pool_reward_account = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let reward_rate = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        u64::from_le_bytes(data)
+    };
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+    if *pool_reward_account.key != pool.reward_token_account {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
+
+    // Everything up to now accrues at the old rate before it changes
+    update_pool(&mut pool, clock.unix_timestamp)?;
+
+    // The rest of the funded period must still be covered at the new rate
+    let promised = scheduled_emissions(reward_rate, &pool.emission, clock.unix_timestamp, pool.period_finish)?;
+    let vault_balance = TokenAccount::unpack(&pool_reward_account.data.borrow())?.amount;
+    if promised > pool.unreserved_rewards(vault_balance) {
+        return Err(StakingError::InsufficientRewardBalance.into());
+    }
+
+    pool.reward_rate = reward_rate;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_max_total_staked(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+
+    let max_total_staked = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        u64::from_le_bytes(data)
+    };
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    // Lowering below the current total only blocks new stakes; nobody is forced out
+    pool.max_total_staked = max_total_staked;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_paused(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let signer = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+
+    let is_paused = *instruction_data.first().ok_or(StakingError::InvalidInstruction)? != 0;
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !signer.is_signer || (*signer.key != pool.guardian && *signer.key != pool.pool_authority) {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    pool.is_paused = is_paused;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    msg!("Staking paused: {}", is_paused);
+    Ok(())
+}
+
+fn process_set_guardian(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let new_guardian = next_account_info(account_info_iter)?;
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    pool.guardian = *new_guardian.key;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn vest_reward<'a>(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo<'a>,
+    beneficiary: &AccountInfo<'a>,
+    vesting_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    amount: u64,
+    current_time: i64,
+    duration: i64,
+) -> ProgramResult {
+    let (expected, bump) = Pubkey::find_program_address(
+        &[REWARD_VESTING_SEED, pool_account.key.as_ref(), beneficiary.key.as_ref()],
+        program_id,
+    );
+    if *vesting_account.key != expected {
+        return Err(StakingError::InvalidVestingAccount.into());
+    }
+
+    let mut vesting = if vesting_account.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                beneficiary.key,
+                vesting_account.key,
+                Rent::get()?.minimum_balance(RewardVesting::LEN),
+                RewardVesting::LEN as u64,
+                program_id,
+            ),
+            &[beneficiary.clone(), vesting_account.clone(), system_program.clone()],
+            &[&[REWARD_VESTING_SEED, pool_account.key.as_ref(), beneficiary.key.as_ref(), &[bump]]],
+        )?;
+        RewardVesting {
+            pool: *pool_account.key,
+            beneficiary: *beneficiary.key,
+            locked_total: 0,
+            start_time: current_time,
+            duration,
+            released: 0,
+            carried: 0,
+        }
+    } else {
+        RewardVesting::try_from_slice(&vesting_account.data.borrow())?
+    };
+
+    vesting.add(amount, current_time, duration)?;
+    vesting.serialize(&mut *vesting_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_reward_vesting(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+
+    let duration = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        i64::from_le_bytes(data)
+    };
+    if duration < 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    // Only affects future claims; existing vesting schedules keep their duration
+    pool.reward_vesting_duration = duration;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_claim_vested(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let beneficiary = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let vesting_account = next_account_info(account_info_iter)?;
+    let user_reward_account = next_account_info(account_info_iter)?;
+    let pool_reward_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    if vesting_account.owner != program_id {
+        return Err(StakingError::InvalidVestingAccount.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    let mut vesting = RewardVesting::try_from_slice(&vesting_account.data.borrow())?;
+    if vesting.pool != *pool_account.key {
+        return Err(StakingError::InvalidVestingAccount.into());
+    }
+    if !beneficiary.is_signer || vesting.beneficiary != *beneficiary.key {
+        return Err(StakingError::Unauthorized.into());
+    }
+    if *pool_reward_account.key != pool.reward_token_account {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
+    if TokenAccount::unpack(&user_reward_account.data.borrow())?.owner != vesting.beneficiary {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
+
+    let amount = vesting.claimable(clock.unix_timestamp);
+    if amount == 0 {
+        return Ok(());
+    }
+
+    transfer_from_vault(
+        program_id,
+        pool_account,
+        pool_reward_account,
+        user_reward_account,
+        vault_authority,
+        token_program,
+        amount,
+    )?;
+
+    vesting.released = vesting.vested(clock.unix_timestamp);
+    vesting.carried = 0;
+    vesting.serialize(&mut *vesting_account.data.borrow_mut())?;
+    pool.reward_liabilities = pool.reward_liabilities.saturating_sub(amount);
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Permissionless pool creation at [STAKE_POOL_SEED, mint, index]; the creator becomes pool authority
+fn process_create_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let creator = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let token_mint = next_account_info(account_info_iter)?;
+    let stake_token_account = next_account_info(account_info_iter)?;
+    let reward_token_account = next_account_info(account_info_iter)?;
+    let reward_mint = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !creator.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let index = instruction_data.get(..8)
+        .ok_or(StakingError::InvalidInstruction)?;
+    let (expected, bump) = Pubkey::find_program_address(
+        &[STAKE_POOL_SEED, token_mint.key.as_ref(), index],
+        program_id,
+    );
+    if *pool_account.key != expected {
+        return Err(StakingError::InvalidPoolAddress.into());
+    }
+    if !pool_account.data_is_empty() {
+        return Err(StakingError::AlreadyInUse.into());
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            creator.key,
+            pool_account.key,
+            Rent::get()?.minimum_balance(StakePool::LEN),
+            StakePool::LEN as u64,
+            program_id,
+        ),
+        &[creator.clone(), pool_account.clone(), system_program.clone()],
+        &[&[STAKE_POOL_SEED, token_mint.key.as_ref(), index, &[bump]]],
+    )?;
+
+    // Remaining data is the InitializePoolArgs payload accepted by Initialize
+    process_initialize(
+        program_id,
+        &[
+            pool_account.clone(),
+            token_mint.clone(),
+            creator.clone(),
+            stake_token_account.clone(),
+            reward_token_account.clone(),
+            reward_mint.clone(),
+        ],
+        &instruction_data[8..],
+    )
+}
+
+fn process_close_stake_account(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    let mut user_info = load_user_stake(program_id, pool_account, user_stake_info)?;
+    if !owner.is_signer || user_info.owner != *owner.key {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    // Settle first so rewards accrued since the last claim are not forfeited
+    update_pool(&mut pool, clock.unix_timestamp)?;
+    update_rewards(&pool, &mut user_info)?;
+    if user_info.stake_amount > 0 || user_info.rewards_earned > 0 || user_info.cooling_amount > 0 {
+        return Err(StakingError::StakeAccountNotEmpty.into());
+    }
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    let dest_starting_lamports = owner.lamports();
+    **owner.lamports.borrow_mut() = dest_starting_lamports
+        .checked_add(user_stake_info.lamports())
+        .ok_or(ProgramError::Overflow)?;
+    **user_stake_info.lamports.borrow_mut() = 0;
+    user_stake_info.data.borrow_mut().fill(0);
+
+    Ok(())
+}
+
+fn process_extend_lock(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let tier_index = *instruction_data.first().ok_or(StakingError::InvalidInstruction)?;
+
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    let mut user_info = load_user_stake(program_id, pool_account, user_stake_info)?;
+    if !owner.is_signer || user_info.owner != *owner.key {
+        return Err(StakingError::Unauthorized.into());
+    }
+    if user_info.stake_amount == 0 {
+        return Err(StakingError::InsufficientStakeBalance.into());
+    }
+
+    let current_multiplier = pool.lock_tiers.get(user_info.lock_tier as usize)
+        .map(|tier| tier.multiplier_bps)
+        .unwrap_or(BASE_MULTIPLIER_BPS);
+    let tier = pool.lock_tiers.get(tier_index as usize).cloned()
+        .ok_or(StakingError::InvalidLockTier)?;
+
+    // The new lock must end no earlier than the current one and never lower the multiplier
+    let current_unlock = user_info.start_time + user_info.lock_period;
+    let new_unlock = clock.unix_timestamp
+        .checked_add(tier.duration)
+        .ok_or(ProgramError::Overflow)?;
+    if tier.multiplier_bps < current_multiplier || new_unlock < current_unlock {
+        return Err(StakingError::InvalidLockTier.into());
+    }
+
+    // Settle at the old weight so the boost only applies from now on
+    update_pool(&mut pool, clock.unix_timestamp)?;
+    update_rewards(&pool, &mut user_info)?;
+
+    user_info.lock_tier = tier_index;
+    user_info.lock_period = tier.duration;
+    user_info.start_time = clock.unix_timestamp;
+    reweight_stake(&mut pool, &mut user_info)?;
+
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    user_info.serialize(&mut *user_stake_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Permissionless so a governance keeper can checkpoint every staker for a proposal
+fn process_snapshot_voting_power(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let checkpoint_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let snapshot_time = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        i64::from_le_bytes(data)
+    };
+    // Positions can only be observed as they are now, never backdated or pre-recorded;
+    // the tolerance only covers the keeper's transaction landing a little late
+    if snapshot_time > clock.unix_timestamp
+        || clock.unix_timestamp - snapshot_time > SNAPSHOT_TOLERANCE
+    {
+        return Err(StakingError::InvalidSnapshot.into());
+    }
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let user_info = load_user_stake(program_id, pool_account, user_stake_info)?;
+
+    let snapshot_bytes = snapshot_time.to_le_bytes();
+    let (expected, bump) = Pubkey::find_program_address(
+        &[VOTING_POWER_SEED, pool_account.key.as_ref(), user_info.owner.as_ref(), &snapshot_bytes],
+        program_id,
+    );
+    if *checkpoint_account.key != expected {
+        return Err(StakingError::InvalidSnapshot.into());
+    }
+    if !checkpoint_account.data_is_empty() {
+        return Err(StakingError::AlreadyInUse.into());
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            checkpoint_account.key,
+            Rent::get()?.minimum_balance(VotingPowerCheckpoint::LEN),
+            VotingPowerCheckpoint::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), checkpoint_account.clone(), system_program.clone()],
+        &[&[
+            VOTING_POWER_SEED,
+            pool_account.key.as_ref(),
+            user_info.owner.as_ref(),
+            &snapshot_bytes,
+            &[bump],
+        ]],
+    )?;
+
+    let unlock_time = user_info.start_time.saturating_add(user_info.lock_period);
+    let checkpoint = VotingPowerCheckpoint {
+        pool: *pool_account.key,
+        wallet: user_info.owner,
+        snapshot_time,
+        recorded_at: clock.unix_timestamp,
+        stake_amount: user_info.stake_amount,
+        lock_remaining: unlock_time.saturating_sub(clock.unix_timestamp).max(0),
+    };
+    checkpoint.serialize(&mut *checkpoint_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_epoch_accounting(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let duration = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        i64::from_le_bytes(data)
+    };
+    if duration < 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    // Switching modes with open positions would strand pending weight and accrued rewards
+    if pool.total_weighted_stake > 0 {
+        return Err(StakingError::PoolNotEmpty.into());
+    }
+    update_pool(&mut pool, clock.unix_timestamp)?;
+
+    pool.epochs = EpochAccounting {
+        duration,
+        current_epoch: pool.epochs.current_epoch,
+        end_time: clock.unix_timestamp
+            .checked_add(duration)
+            .ok_or(ProgramError::Overflow)?,
+        accrued: 0,
+        total_pending_weight: 0,
+        start_rewards: vec![pool.reward_per_token_stored; MAX_EPOCH_HISTORY],
+    };
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_launchpad_boost(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+
+    let args = SetLaunchpadBoostArgs::try_from_slice(instruction_data)?;
+    if args.multiplier_bps < BASE_MULTIPLIER_BPS {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    // Boosted positions pick up a changed multiplier the next time they are reweighted
+    pool.boost_launchpad = args.launchpad;
+    pool.boost_multiplier_bps = args.multiplier_bps;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_apply_launchpad_boost(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let participant_account = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    let mut user_info = load_user_stake(program_id, pool_account, user_stake_info)?;
+    if !owner.is_signer || user_info.owner != *owner.key {
+        return Err(StakingError::Unauthorized.into());
+    }
+    if pool.boost_launchpad == Pubkey::default() {
+        return Err(StakingError::InvalidParticipant.into());
+    }
+
+    // The record must be the launchpad program's participant PDA for this wallet
+    if *participant_account.owner != LAUNCHPAD_PROGRAM_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let (expected, _) = Pubkey::find_program_address(
+        &[PARTICIPANT_SEED, pool.boost_launchpad.as_ref(), owner.key.as_ref()],
+        participant_account.owner,
+    );
+    if *participant_account.key != expected {
+        return Err(StakingError::InvalidParticipant.into());
+    }
+    let participant = LaunchpadParticipant::deserialize(&mut &participant_account.data.borrow()[..])?;
+    if participant.wallet != *owner.key || participant.amount_contributed == 0 {
+        return Err(StakingError::InvalidParticipant.into());
+    }
+
+    // Settle at the unboosted weight so the boost is not applied retroactively
+    update_pool(&mut pool, clock.unix_timestamp)?;
+    update_rewards(&pool, &mut user_info)?;
+
+    user_info.launchpad_boosted = true;
+    reweight_stake(&mut pool, &mut user_info)?;
+
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+    user_info.serialize(&mut *user_stake_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Loads a position and checks it is this pool's PDA for its owner
+fn load_user_stake(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo,
+    user_stake_info: &AccountInfo,
+) -> Result<UserStakeInfo, ProgramError> {
+    if user_stake_info.owner != program_id {
+        return Err(StakingError::InvalidStakeAccount.into());
+    }
+    let user_info = UserStakeInfo::try_from_slice(&user_stake_info.data.borrow())?;
+    let (expected, _) = Pubkey::find_program_address(
+        &[USER_STAKE_SEED, pool_account.key.as_ref(), user_info.owner.as_ref()],
+        program_id,
+    );
+    if *user_stake_info.key != expected {
+        return Err(StakingError::InvalidStakeAccount.into());
+    }
+
+    Ok(user_info)
+}
+
+fn transfer_from_vault<'a>(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo<'a>,
+    vault: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    vault_authority: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let (expected, bump) = Pubkey::find_program_address(
+        &[VAULT_AUTHORITY_SEED, pool_account.key.as_ref()],
+        program_id,
+    );
+    if *vault_authority.key != expected {
+        return Err(StakingError::InvalidVaultAuthority.into());
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if amount == 0 {
+        return Ok(());
+    }
+
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            vault.key,
+            destination.key,
+            vault_authority.key,
+            &[],
+            amount,
+        )?,
+        &[
+            vault.clone(),
+            destination.clone(),
+            vault_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&[VAULT_AUTHORITY_SEED, pool_account.key.as_ref(), &[bump]]],
+    )
+}
+
+fn process_set_unstake_cooldown(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+
+    let cooldown = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        i64::from_le_bytes(data)
+    };
+    if cooldown < 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    // Amounts already cooling keep the end time they were given
+    pool.unstake_cooldown = cooldown;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_withdraw_unstaked(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let owner = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let user_token_account = next_account_info(account_info_iter)?;
+    let pool_token_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let vault_authority = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let pool = StakePool::unpack(&pool_account.data.borrow())?;
+    let mut user_info = load_user_stake(program_id, pool_account, user_stake_info)?;
+    if !owner.is_signer || user_info.owner != *owner.key {
+        return Err(StakingError::Unauthorized.into());
+    }
+    if *pool_token_account.key != pool.stake_token_account {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
+    if user_info.cooling_amount == 0 {
+        return Err(StakingError::InsufficientStakeBalance.into());
+    }
+    if clock.unix_timestamp < user_info.cooldown_end {
+        return Err(StakingError::CooldownActive.into());
+    }
+
+    transfer_from_vault(
+        program_id,
+        pool_account,
+        pool_token_account,
+        user_token_account,
+        vault_authority,
+        token_program,
+        user_info.cooling_amount,
+    )?;
+
+    user_info.cooling_amount = 0;
+    user_info.serialize(&mut *user_stake_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn register_referral<'a>(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    referral_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    referrer: &Pubkey,
+) -> ProgramResult {
+    let (expected, bump) = Pubkey::find_program_address(
+        &[STAKING_REFERRAL_SEED, pool_account.key.as_ref(), referrer.as_ref()],
+        program_id,
+    );
+    if *referral_account.key != expected {
+        return Err(StakingError::InvalidReferrer.into());
+    }
+
+    let mut record = if referral_account.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                referral_account.key,
+                Rent::get()?.minimum_balance(StakingReferral::LEN),
+                StakingReferral::LEN as u64,
+                program_id,
+            ),
+            &[payer.clone(), referral_account.clone(), system_program.clone()],
+            &[&[STAKING_REFERRAL_SEED, pool_account.key.as_ref(), referrer.as_ref(), &[bump]]],
+        )?;
+        StakingReferral {
+            pool: *pool_account.key,
+            referrer: *referrer,
+            referee_count: 0,
+            total_paid: 0,
+        }
+    } else {
+        StakingReferral::try_from_slice(&referral_account.data.borrow())?
+    };
+
+    record.referee_count = record.referee_count.checked_add(1)
+        .ok_or(ProgramError::Overflow)?;
+    record.serialize(&mut *referral_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pay_referral<'a>(
+    program_id: &Pubkey,
+    pool_account: &AccountInfo<'a>,
+    referrer: &Pubkey,
+    referral_account: &AccountInfo<'a>,
+    referrer_reward_account: &AccountInfo<'a>,
+    pool_reward_account: &AccountInfo<'a>,
+    vault_authority: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let (expected, _) = Pubkey::find_program_address(
+        &[STAKING_REFERRAL_SEED, pool_account.key.as_ref(), referrer.as_ref()],
+        program_id,
+    );
+    if *referral_account.key != expected || referral_account.owner != program_id {
+        return Err(StakingError::InvalidReferrer.into());
+    }
+    if TokenAccount::unpack(&referrer_reward_account.data.borrow())?.owner != *referrer {
+        return Err(StakingError::InvalidTokenAccount.into());
+    }
+
+    transfer_from_vault(
+        program_id,
+        pool_account,
+        pool_reward_account,
+        referrer_reward_account,
+        vault_authority,
+        token_program,
+        amount,
+    )?;
+
+    let mut record = StakingReferral::try_from_slice(&referral_account.data.borrow())?;
+    record.total_paid = record.total_paid.checked_add(amount)
+        .ok_or(ProgramError::Overflow)?;
+    record.serialize(&mut *referral_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_referral_bps(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+
+    let referral_bps = {
+        let mut data = [0u8; 2];
+        data.copy_from_slice(&instruction_data[..2]);
+        u16::from_le_bytes(data)
+    };
+    if referral_bps as u64 + SERVICE_FEE_BPS > 10000 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    pool.referral_bps = referral_bps;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_max_stake_per_wallet(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let pool_account = next_account_info(account_info_iter)?;
+
+    let max_stake_per_wallet = {
+        let mut data = [0u8; 8];
+        data.copy_from_slice(&instruction_data[..8]);
+        u64::from_le_bytes(data)
+    };
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    if !authority.is_signer || *authority.key != pool.pool_authority {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    // Existing positions above a lowered cap are left alone; they just cannot grow
+    pool.max_stake_per_wallet = max_stake_per_wallet;
+    pool.serialize(&mut *pool_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+// Read-only: runs the accrual math on copies and returns the position's pending
+// reward (before service fee and referral share) as a little-endian u64
+fn process_get_pending_rewards(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pool_account = next_account_info(account_info_iter)?;
+    let user_stake_info = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    if pool_account.owner != program_id {
+        return Err(StakingError::InvalidInstruction.into());
+    }
+    let mut pool = StakePool::unpack(&pool_account.data.borrow())?;
+    let mut user_info = load_user_stake(program_id, pool_account, user_stake_info)?;
+
+    // A backlog only means the figure trails slightly until someone runs UpdatePool
+    advance_pool(&mut pool, clock.unix_timestamp)?;
+    update_rewards(&pool, &mut user_info)?;
+
+    set_return_data(&user_info.rewards_earned.try_to_vec()?);
+
+    Ok(())
+}
@@ -13,7 +13,7 @@ use solana_program::{
 use thiserror::Error;
 
 // Program ID and Fee Wallet
-solana_program::declare_id!("TokenVerify111111111111111111111111111111");
+solana_program::declare_id!("TokenVerify11111111111111111111111111111111");
 pub const FEE_WALLET: &str = "6zkf4DviZZkpWVEh53MrcQV6vGXGpESnNXgAvU6KpBUH";
 
 // Verification fees in lamports